@@ -0,0 +1,20 @@
+use hermez_api::HermezApi;
+
+#[async_std::main]
+async fn main() {
+    let api = HermezApi::builder(HermezApi::TESTNET_URL)
+        .concurrency(4)
+        .build()
+        .unwrap();
+
+    let accounts = api
+        .get_accounts_many(&["hez:ETH:256", "hez:HEZ:257", "hez:ETH:258"])
+        .await;
+    println!("{:#?}", accounts);
+
+    let batches = api.get_batches_range(10..20).await;
+    println!("{:#?}", batches);
+
+    let tokens = api.get_tokens_many(&[0, 1, 2, 1]).await;
+    println!("{:#?}", tokens);
+}
@@ -0,0 +1,22 @@
+use hermez_api::accounts::AccountsQuery;
+use hermez_api::HermezApi;
+
+#[async_std::main]
+async fn main() {
+    let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+
+    let query = AccountsQuery {
+        token_ids: Some(vec![0, 1]),
+        limit: Some(5),
+        ..Default::default()
+    };
+
+    // Queries can be stored and rebuilt later.
+    let json = serde_json::to_string(&query).unwrap();
+    println!("{}", json);
+    let query: AccountsQuery = serde_json::from_str(&json).unwrap();
+
+    let page = api.run(&query).await.unwrap();
+
+    println!("{:#?}, {}", page.accounts, page.pending_items);
+}
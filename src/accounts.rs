@@ -1,41 +1,88 @@
-use super::http::Http;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
+use super::Endpoint;
 use super::ErrorKind;
+use super::HermezApi;
 use super::PaginationOrder;
+use super::Query;
 
 use super::tokens::Token;
 
-pub struct AccountsGetOptions<'a> {
-    http: &'a Http,
-    url: &'a Url,
-    hez_ethereum_address: Option<&'a str>,
-    bjj: Option<&'a str>,
-    token_ids: Option<&'a [u32]>,
+/// Owned description of a request to the accounts endpoint.
+///
+/// Can be cloned, stored and serialized, and then run with `HermezApi::run()`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct AccountsQuery {
+    pub hez_ethereum_address: Option<String>,
+    pub bjj: Option<String>,
+    pub token_ids: Option<Vec<u32>>,
+
+    pub from_item: Option<u64>,
+    pub order: Option<PaginationOrder>,
+    pub limit: Option<u64>,
+}
+
+impl Query for AccountsQuery {
+    type Output = Accounts;
+    const ENDPOINT: Endpoint = Endpoint::Accounts;
+
+    fn url(&self, base: &Url) -> Result<Url, ErrorKind> {
+        if is_more_than_one_defined!(self.hez_ethereum_address, self.bjj) {
+            return Err(ErrorKind::Api(String::from(
+                "Get Accounts: Cannot specify both an Ethereum address and a BJJ key",
+            )));
+        }
+
+        let mut url = base.join("accounts").unwrap();
+        {
+            let mut query_pairs = url.query_pairs_mut();
+
+            if let Some(token_ids) = &self.token_ids {
+                query_pairs.append_pair("tokenIds", &itertools::join(token_ids, ","));
+            }
 
-    from_item: Option<u64>,
-    order: Option<PaginationOrder>,
-    limit: Option<u64>,
+            fetch_stmt!(
+                self,
+                query_pairs,
+                hez_ethereum_address,
+                "hezEthereumAddress"
+            );
+            fetch_stmt!(self, query_pairs, bjj, "BJJ");
+            /*
+                        if let Some(hez_ethereum_address) = &self.hez_ethereum_address {
+                            query_pairs.append_pair("hezEthereumAddress", hez_ethereum_address);
+                        }
+
+                        if let Some(bjj) = &self.bjj {
+                            query_pairs.append_pair("BJJ", bjj);
+                        }
+            */
+            pagination_fetch_stmts!(self, query_pairs);
+        }
+        Ok(url)
+    }
 }
 
-impl<'a> AccountsGetOptions<'a> {
-    pub fn new(http: &'a Http, url: &'a Url) -> Self {
+paged_query!(AccountsQuery, Account, accounts);
+
+pub struct AccountsGetOptions {
+    api: HermezApi,
+    query: AccountsQuery,
+}
+
+impl AccountsGetOptions {
+    pub fn new(api: &HermezApi) -> Self {
         Self {
-            http,
-            url,
-            hez_ethereum_address: None,
-            bjj: None,
-            token_ids: None,
-            from_item: None,
-            order: None,
-            limit: None,
+            api: api.clone(),
+            query: Default::default(),
         }
     }
 
-    setter!(hez_ethereum_address, &'a str);
-    setter!(bjj, &'a str);
-    setter!(token_ids, &'a [u32]);
+    setter!(hez_ethereum_address, &str);
+    setter!(bjj, &str);
+    setter!(token_ids, &[u32]);
 
     pagination_setters!();
     /*
@@ -73,52 +120,25 @@ impl<'a> AccountsGetOptions<'a> {
         }
     */
 
-    pub async fn fetch(&self) -> Result<(Vec<Account>, u64), ErrorKind> {
-        if is_more_than_one_defined!(self.hez_ethereum_address, self.bjj) {
-            return Err(ErrorKind::Api(String::from(
-                "Get Accounts: Cannot specify both an Ethereum address and a BJJ key",
-            )));
-        }
-
-        let mut url = self.url.join("accounts").unwrap();
-        {
-            let mut query_pairs = url.query_pairs_mut();
-
-            if let Some(token_ids) = &self.token_ids {
-                query_pairs.append_pair("tokenIds", &itertools::join(*token_ids, ","));
-            }
-
-            fetch_stmt!(
-                self,
-                query_pairs,
-                hez_ethereum_address,
-                "hezEthereumAddress"
-            );
-            fetch_stmt!(self, query_pairs, bjj, "BJJ");
-            /*
-                        if let Some(hez_ethereum_address) = &self.hez_ethereum_address {
-                            query_pairs.append_pair("hezEthereumAddress", hez_ethereum_address);
-                        }
+    /// The query built so far.
+    pub fn query(&self) -> &AccountsQuery {
+        &self.query
+    }
 
-                        if let Some(bjj) = &self.bjj {
-                            query_pairs.append_pair("BJJ", bjj);
-                        }
-            */
-            pagination_fetch_stmts!(self, query_pairs);
-        }
-        let accounts: Accounts = self.http.get(&url).await?;
+    pub async fn fetch(&self) -> Result<(Vec<Account>, u64), ErrorKind> {
+        let accounts = self.api.run(&self.query).await?;
         Ok((accounts.accounts, accounts.pending_items))
     }
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct Accounts {
+pub struct Accounts {
     pub pending_items: u64,
     pub accounts: Vec<Account>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Account {
     pub item_id: u64,
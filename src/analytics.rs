@@ -0,0 +1,495 @@
+//! Reports on the slot auction and on coordinators.
+
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+use super::batches::{Batch, BatchesQuery};
+use super::bids::{Bid, BidsQuery};
+use super::coordinators::CoordinatorsQuery;
+use super::slots::SlotsQuery;
+use super::{parse_amount, ErrorKind, HermezApi};
+
+/// Bidding activity of one bidder over a range of slots.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct BidderStats {
+    pub bidder_addr: String,
+    /// Forger address and URL registered for the bidder, if any.
+    pub forger_addr: Option<String>,
+    pub url: Option<String>,
+    /// Number of bids placed.
+    pub bids: u64,
+    /// Number of slots bid on.
+    pub slots_bid: u64,
+    pub slots_won: u64,
+    /// Sum and average of the bids placed.
+    pub total_bid: u128,
+    pub average_bid: u128,
+    /// Sum and average of the winning bids.
+    pub total_won: u128,
+    pub average_won: u128,
+    /// Share of the slots bid on that were won.
+    pub win_rate: f64,
+}
+
+/// Who won the slots in a range, and for how much.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct BidReport {
+    /// The slots covered, which are those of the range asked for whose
+    /// auctions are closed.
+    pub min_slot_num: u32,
+    pub max_slot_num: u32,
+    /// Bidders, most slots won first.
+    pub bidders: Vec<BidderStats>,
+}
+
+/// What happened in a slot, compared with who won it.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SlotPerformance {
+    pub slot_num: u32,
+    /// Forger address of the winning bid, `None` if nobody bid, in which
+    /// case the boot coordinator forges.
+    pub winner: Option<String>,
+    /// Addresses that forged batches in the slot.
+    pub forgers: Vec<String>,
+    pub batches: u64,
+    pub transactions: u64,
+    /// Fees collected, by token id.
+    pub collected_fees: BTreeMap<String, u128>,
+    pub collected_fees_usd: f64,
+    /// The slot was won, but the winner forged no batch in it.
+    pub missed: bool,
+    /// The slot was won by another coordinator, but the boot coordinator
+    /// forged in it.
+    pub boot_coordinator_forged: bool,
+}
+
+/// How a coordinator used the slots it won.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CoordinatorPerformance {
+    pub forger_addr: String,
+    pub slots_won: u64,
+    /// Slots won in which the coordinator forged no batch.
+    pub slots_missed: u64,
+    /// Batches, transactions and fees forged by the coordinator in the slots it won.
+    pub batches: u64,
+    pub transactions: u64,
+    pub collected_fees: BTreeMap<String, u128>,
+    pub collected_fees_usd: f64,
+}
+
+/// Forging activity over a range of slots.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PerformanceReport {
+    pub boot_coordinator: String,
+    pub slots: Vec<SlotPerformance>,
+    /// Coordinators that won slots, most slots won first.
+    pub coordinators: Vec<CoordinatorPerformance>,
+}
+
+impl HermezApi {
+    /// Gathers the bids on a range of slots, and reports on each bidder.
+    ///
+    /// Only slots whose auction is closed are counted.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use hermez_api::HermezApi;
+    ///
+    /// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+    /// let report = api.get_bid_report(12000..12300).await.unwrap();
+    ///
+    /// for bidder in report.bidders {
+    ///     println!("{} won {} slots", bidder.bidder_addr, bidder.slots_won);
+    /// }
+    /// ```
+    pub async fn get_bid_report(&self, slot_nums: Range<u32>) -> Result<BidReport, ErrorKind> {
+        let state = self.get_state().await?;
+        let last_closed = state.network.current_slot as u64 + state.auction.closed_auction_slots;
+        let slot_nums = slot_nums.start..slot_nums.end.min(last_closed as u32 + 1);
+
+        let mut report = BidReport {
+            min_slot_num: slot_nums.start,
+            max_slot_num: slot_nums.end.saturating_sub(1),
+            bidders: Vec::new(),
+        };
+        if slot_nums.is_empty() {
+            return Ok(report);
+        }
+
+        let pages: Vec<Result<Vec<Bid>, ErrorKind>> = stream::iter(slot_nums.clone())
+            .map(|slot_num| {
+                let api = self.clone();
+                async move {
+                    let query = BidsQuery {
+                        slot_num: Some(slot_num),
+                        ..Default::default()
+                    };
+                    api.run_all(&query).await
+                }
+            })
+            .buffered(self.inner.concurrency)
+            .collect()
+            .await;
+        let mut bids = Vec::new();
+        for page in pages {
+            bids.extend(page?);
+        }
+
+        let bidders: BTreeSet<String> = bids.iter().map(|bid| bid.bidder_addr.clone()).collect();
+        let won: Vec<Result<(String, Vec<u128>), ErrorKind>> = stream::iter(bidders)
+            .map(|bidder_addr| {
+                let api = self.clone();
+                let slot_nums = slot_nums.clone();
+                async move {
+                    let query = SlotsQuery {
+                        min_slot_num: Some(slot_nums.start),
+                        max_slot_num: Some(slot_nums.end - 1),
+                        won_by_ethereum_address: Some(bidder_addr.clone()),
+                        finished_auction: Some(true),
+                        ..Default::default()
+                    };
+                    let slots = api.run_all(&query).await?;
+                    let amounts = slots
+                        .iter()
+                        .filter_map(|slot| slot.best_bid.as_ref())
+                        .map(|bid| parse_amount(&bid.bid_value))
+                        .collect::<Result<_, _>>()?;
+                    Ok((bidder_addr, amounts))
+                }
+            })
+            .buffered(self.inner.concurrency)
+            .collect()
+            .await;
+        let won = won.into_iter().collect::<Result<BTreeMap<_, _>, _>>()?;
+
+        report.bidders = aggregate_bids(&bids, &won)?;
+
+        let coordinators = self.run_all(&CoordinatorsQuery::default()).await?;
+        for bidder in &mut report.bidders {
+            // The latest registration of the bidder wins.
+            if let Some(coordinator) = coordinators
+                .iter()
+                .filter(|coordinator| coordinator.bidder_addr == bidder.bidder_addr)
+                .max_by_key(|coordinator| coordinator.item_id)
+            {
+                bidder.forger_addr = Some(coordinator.forger_addr.clone());
+                bidder.url = Some(coordinator.url.clone());
+            }
+        }
+
+        Ok(report)
+    }
+
+    /// Compares who won each slot in a range with who forged batches in it.
+    ///
+    /// Slots after the current one are left out, and the current slot may
+    /// not be over yet.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use hermez_api::HermezApi;
+    ///
+    /// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+    /// let report = api.get_performance_report(12000..12300).await.unwrap();
+    ///
+    /// for coordinator in report.coordinators {
+    ///     println!(
+    ///         "{} missed {} of {} slots",
+    ///         coordinator.forger_addr, coordinator.slots_missed, coordinator.slots_won
+    ///     );
+    /// }
+    /// ```
+    pub async fn get_performance_report(
+        &self,
+        slot_nums: Range<u32>,
+    ) -> Result<PerformanceReport, ErrorKind> {
+        let state = self.get_state().await?;
+        let boot_coordinator = state.auction.boot_coordinator;
+        let slot_nums = slot_nums.start..slot_nums.end.min(state.network.current_slot + 1);
+
+        let mut report = PerformanceReport {
+            boot_coordinator,
+            ..Default::default()
+        };
+        if slot_nums.is_empty() {
+            return Ok(report);
+        }
+
+        let query = SlotsQuery {
+            min_slot_num: Some(slot_nums.start),
+            max_slot_num: Some(slot_nums.end - 1),
+            ..Default::default()
+        };
+        let winners: BTreeMap<u32, String> = self
+            .run_all(&query)
+            .await?
+            .into_iter()
+            .filter_map(|slot| Some((slot.slot_num, slot.best_bid?.forger_addr)))
+            .collect();
+
+        let batches: Vec<Result<Vec<Batch>, ErrorKind>> = stream::iter(slot_nums.clone())
+            .map(|slot_num| {
+                let api = self.clone();
+                async move {
+                    let query = BatchesQuery {
+                        slot_num: Some(slot_num),
+                        ..Default::default()
+                    };
+                    api.run_all(&query).await
+                }
+            })
+            .buffered(self.inner.concurrency)
+            .collect()
+            .await;
+
+        let mut coordinators = BTreeMap::new();
+        for (slot_num, batches) in slot_nums.zip(batches) {
+            let batches = batches?;
+            let winner = winners.get(&slot_num).map(String::as_str);
+            let slot = slot_performance(slot_num, winner, &batches, &report.boot_coordinator)?;
+            record_winner(&mut coordinators, &slot, &batches)?;
+            report.slots.push(slot);
+        }
+        report.coordinators = coordinators.into_values().collect();
+        report
+            .coordinators
+            .sort_by_key(|coordinator| Reverse(coordinator.slots_won));
+
+        Ok(report)
+    }
+}
+
+fn same_address(a: &str, b: &str) -> bool {
+    a.eq_ignore_ascii_case(b)
+}
+
+fn slot_performance(
+    slot_num: u32,
+    winner: Option<&str>,
+    batches: &[Batch],
+    boot_coordinator: &str,
+) -> Result<SlotPerformance, ErrorKind> {
+    let mut slot = SlotPerformance {
+        slot_num,
+        winner: winner.map(str::to_owned),
+        ..Default::default()
+    };
+
+    for batch in batches {
+        if !slot
+            .forgers
+            .iter()
+            .any(|forger| same_address(forger, &batch.forger_addr))
+        {
+            slot.forgers.push(batch.forger_addr.clone());
+        }
+        slot.batches += 1;
+        slot.transactions += batch.forged_transactions;
+        for (token_id, amount) in &batch.collected_fees {
+            *slot.collected_fees.entry(token_id.clone()).or_default() += parse_amount(amount)?;
+        }
+        slot.collected_fees_usd += batch.historic_total_collected_fees_usd;
+    }
+
+    if let Some(winner) = winner {
+        slot.missed = !slot
+            .forgers
+            .iter()
+            .any(|forger| same_address(forger, winner));
+        slot.boot_coordinator_forged = !same_address(winner, boot_coordinator)
+            && slot
+                .forgers
+                .iter()
+                .any(|forger| same_address(forger, boot_coordinator));
+    }
+    Ok(slot)
+}
+
+// Adds a slot to the totals of its winner, counting only what the winner forged.
+fn record_winner(
+    coordinators: &mut BTreeMap<String, CoordinatorPerformance>,
+    slot: &SlotPerformance,
+    batches: &[Batch],
+) -> Result<(), ErrorKind> {
+    let winner = match &slot.winner {
+        Some(winner) => winner,
+        None => return Ok(()),
+    };
+    let coordinator = coordinators
+        .entry(winner.to_ascii_lowercase())
+        .or_insert_with(|| CoordinatorPerformance {
+            forger_addr: winner.clone(),
+            ..Default::default()
+        });
+
+    coordinator.slots_won += 1;
+    if slot.missed {
+        coordinator.slots_missed += 1;
+    }
+    for batch in batches
+        .iter()
+        .filter(|batch| same_address(&batch.forger_addr, winner))
+    {
+        coordinator.batches += 1;
+        coordinator.transactions += batch.forged_transactions;
+        for (token_id, amount) in &batch.collected_fees {
+            *coordinator
+                .collected_fees
+                .entry(token_id.clone())
+                .or_default() += parse_amount(amount)?;
+        }
+        coordinator.collected_fees_usd += batch.historic_total_collected_fees_usd;
+    }
+    Ok(())
+}
+
+// Combines the bids placed with the winning bids of each bidder.
+fn aggregate_bids(
+    bids: &[Bid],
+    won: &BTreeMap<String, Vec<u128>>,
+) -> Result<Vec<BidderStats>, ErrorKind> {
+    let mut stats: BTreeMap<&str, (BidderStats, BTreeSet<u32>)> = BTreeMap::new();
+    for bid in bids {
+        let (bidder, slots) = stats.entry(&bid.bidder_addr).or_insert_with(|| {
+            let bidder = BidderStats {
+                bidder_addr: bid.bidder_addr.clone(),
+                ..Default::default()
+            };
+            (bidder, BTreeSet::new())
+        });
+        bidder.bids += 1;
+        bidder.total_bid += parse_amount(&bid.bid_value)?;
+        slots.insert(bid.slot_num);
+    }
+
+    let mut bidders: Vec<BidderStats> = stats
+        .into_values()
+        .map(|(mut bidder, slots)| {
+            let amounts = won.get(&bidder.bidder_addr).map_or(&[][..], |v| &v[..]);
+            bidder.slots_bid = slots.len() as u64;
+            bidder.slots_won = amounts.len() as u64;
+            bidder.total_won = amounts.iter().sum();
+            bidder.average_bid = bidder.total_bid / bidder.bids as u128;
+            if bidder.slots_won > 0 {
+                bidder.average_won = bidder.total_won / bidder.slots_won as u128;
+            }
+            bidder.win_rate = bidder.slots_won as f64 / bidder.slots_bid as f64;
+            bidder
+        })
+        .collect();
+
+    bidders.sort_by_key(|bidder| Reverse(bidder.slots_won));
+    Ok(bidders)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{aggregate_bids, record_winner, slot_performance};
+    use crate::batches::Batch;
+    use crate::bids::Bid;
+    use std::collections::{BTreeMap, HashMap};
+
+    fn bid(bidder_addr: &str, slot_num: u32, bid_value: u128) -> Bid {
+        Bid {
+            item_id: 0,
+            bidder_addr: bidder_addr.to_owned(),
+            forger_addr: String::new(),
+            slot_num,
+            url: String::new(),
+            bid_value: bid_value.to_string(),
+            ethereum_block_num: 0,
+            timestamp: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_aggregate_bids() {
+        let bids = [
+            bid("0xa", 10, 100),
+            bid("0xb", 10, 110),
+            bid("0xa", 10, 121),
+            bid("0xa", 11, 100),
+            bid("0xb", 12, 100),
+        ];
+        let mut won = BTreeMap::new();
+        won.insert("0xa".to_owned(), vec![121, 100]);
+        won.insert("0xb".to_owned(), vec![100]);
+
+        let bidders = aggregate_bids(&bids, &won).unwrap();
+
+        assert_eq!("0xa", bidders[0].bidder_addr);
+        assert_eq!(3, bidders[0].bids);
+        assert_eq!(2, bidders[0].slots_bid);
+        assert_eq!(2, bidders[0].slots_won);
+        assert_eq!(321, bidders[0].total_bid);
+        assert_eq!(107, bidders[0].average_bid);
+        assert_eq!(110, bidders[0].average_won);
+        assert_eq!(1.0, bidders[0].win_rate);
+
+        assert_eq!("0xb", bidders[1].bidder_addr);
+        assert_eq!(0.5, bidders[1].win_rate);
+    }
+
+    fn batch(forger_addr: &str, fee: &str) -> Batch {
+        let mut collected_fees = HashMap::new();
+        collected_fees.insert("0".to_owned(), fee.to_owned());
+        Batch {
+            item_id: 0,
+            batch_num: 0,
+            ethereum_block_num: 0,
+            ethereum_block_hash: String::new(),
+            ethereum_tx_hash: String::new(),
+            timestamp: String::new(),
+            forger_addr: forger_addr.to_owned(),
+            collected_fees,
+            historic_total_collected_fees_usd: 0.5,
+            state_root: String::new(),
+            num_accounts: 0,
+            exit_root: String::new(),
+            forge_l1_transactions_num: None,
+            slot_num: 0,
+            forged_transactions: 3,
+        }
+    }
+
+    #[test]
+    fn test_performance() {
+        let boot = "0xB007";
+        let mut coordinators = BTreeMap::new();
+
+        let batches = [batch("0xaa", "10"), batch("0xAA", "5"), batch(boot, "1")];
+        let slot = slot_performance(1, Some("0xaa"), &batches, boot).unwrap();
+        assert_eq!(vec!["0xaa".to_owned(), boot.to_owned()], slot.forgers);
+        assert_eq!(3, slot.batches);
+        assert_eq!(9, slot.transactions);
+        assert_eq!(Some(&16), slot.collected_fees.get("0"));
+        assert!(!slot.missed);
+        assert!(slot.boot_coordinator_forged);
+        record_winner(&mut coordinators, &slot, &batches).unwrap();
+
+        let batches = [batch("0xb007", "1")];
+        let slot = slot_performance(2, Some("0xaa"), &batches, boot).unwrap();
+        assert!(slot.missed);
+        assert!(slot.boot_coordinator_forged);
+        record_winner(&mut coordinators, &slot, &batches).unwrap();
+
+        let slot = slot_performance(3, None, &batches, boot).unwrap();
+        assert!(!slot.missed);
+        assert!(!slot.boot_coordinator_forged);
+        record_winner(&mut coordinators, &slot, &batches).unwrap();
+
+        assert_eq!(1, coordinators.len());
+        let coordinator = &coordinators["0xaa"];
+        assert_eq!(2, coordinator.slots_won);
+        assert_eq!(1, coordinator.slots_missed);
+        assert_eq!(2, coordinator.batches);
+        assert_eq!(6, coordinator.transactions);
+        assert_eq!(Some(&15), coordinator.collected_fees.get("0"));
+    }
+}
@@ -0,0 +1,193 @@
+//! Slot auction rules: minimum bids, auction status and how bids are shared out.
+//!
+//! These follow the rules of the auction contract, using the parameters
+//! reported in `StateAuction`. Amounts are in the smallest unit of HEZ.
+
+use serde::{Deserialize, Serialize};
+
+use super::slots::Slot;
+use super::state::State;
+use super::{parse_amount, ErrorKind, HermezApi};
+
+/// Where a slot's auction stands.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AuctionStatus {
+    /// Bidding is over, the slot is current, past or about to be forged.
+    Closed,
+    Open,
+    /// The slot is too far ahead to be bid on yet.
+    NotYetOpen,
+}
+
+/// The shares of a winning bid.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BidSplit {
+    /// Sent to the donation address.
+    pub donation: u128,
+    pub burn: u128,
+    /// Kept as a reward for forgers.
+    pub forger: u128,
+}
+
+/// Auction parameters, taken from the state of the network.
+///
+/// # Example
+///
+/// ```no_run
+/// use hermez_api::HermezApi;
+///
+/// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+/// let auction = api.get_auction().await.unwrap();
+/// let slot = api.get_slot(12400).await.unwrap();
+///
+/// println!("{:?}", auction.status(slot.slot_num));
+/// println!("Minimum bid: {}", auction.min_bid(&slot).unwrap());
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Auction {
+    pub current_slot: u32,
+    pub closed_auction_slots: u64,
+    pub open_auction_slots: u64,
+    /// Minimum bid for each slot set, slot `n` being in set `n % len`.
+    pub default_slot_set_bid: Vec<u128>,
+    /// Slot from which `default_slot_set_bid` applies.
+    pub default_slot_set_bid_slot_num: u64,
+    /// Minimum amount by which a bid must beat the previous one, in
+    /// hundredths of a percent.
+    pub outbidding: u64,
+    /// Shares of a bid given to donation, burn and forgers, in hundredths of a percent.
+    pub allocation_ratio: [u64; 3],
+}
+
+impl Auction {
+    pub fn new(state: &State) -> Result<Self, ErrorKind> {
+        let auction = &state.auction;
+        let allocation_ratio = match auction.allocation_ratio.as_slice() {
+            [donation, burn, forger] => [*donation, *burn, *forger],
+            _ => {
+                return Err(ErrorKind::Api(format!(
+                    "Auction: expected 3 allocation ratios, got {}",
+                    auction.allocation_ratio.len()
+                )))
+            }
+        };
+
+        Ok(Self {
+            current_slot: state.network.current_slot,
+            closed_auction_slots: auction.closed_auction_slots,
+            open_auction_slots: auction.open_auction_slots,
+            default_slot_set_bid: auction
+                .default_slot_set_bid
+                .iter()
+                .map(|bid| parse_amount(bid))
+                .collect::<Result<_, _>>()?,
+            default_slot_set_bid_slot_num: auction.default_slot_set_bid_slot_num,
+            outbidding: auction.outbidding as u64,
+            allocation_ratio,
+        })
+    }
+
+    pub fn status(&self, slot_num: u32) -> AuctionStatus {
+        let first_open = self.current_slot as u64 + self.closed_auction_slots + 1;
+        let slot_num = slot_num as u64;
+        if slot_num < first_open {
+            AuctionStatus::Closed
+        } else if slot_num < first_open + self.open_auction_slots {
+            AuctionStatus::Open
+        } else {
+            AuctionStatus::NotYetOpen
+        }
+    }
+
+    /// The minimum bid when nobody has bid on the slot yet.
+    ///
+    /// Only the current slot set bids are known, which apply from slot
+    /// `default_slot_set_bid_slot_num` on. That covers every open slot, but
+    /// the result is not reliable for slots before it.
+    pub fn default_bid(&self, slot_num: u32) -> u128 {
+        if self.default_slot_set_bid.is_empty() {
+            return 0;
+        }
+        self.default_slot_set_bid[slot_num as usize % self.default_slot_set_bid.len()]
+    }
+
+    /// The minimum valid bid for a slot, given its current best bid.
+    ///
+    /// A bid must beat the best bid, or the default bid of the slot's set
+    /// when there is none, by the outbidding percentage. As with
+    /// `default_bid()`, slots before `default_slot_set_bid_slot_num` may have
+    /// had other default bids.
+    pub fn min_bid_over(&self, slot_num: u32, best_bid: Option<u128>) -> u128 {
+        let previous = match best_bid {
+            Some(bid) if bid > 0 => bid,
+            _ => self.default_bid(slot_num),
+        };
+        previous + previous * self.outbidding as u128 / 10000
+    }
+
+    /// The minimum valid bid for a slot.
+    pub fn min_bid(&self, slot: &Slot) -> Result<u128, ErrorKind> {
+        let best_bid = match &slot.best_bid {
+            Some(bid) => Some(parse_amount(&bid.bid_value)?),
+            None => None,
+        };
+        Ok(self.min_bid_over(slot.slot_num, best_bid))
+    }
+
+    /// How a winning bid is shared out.
+    pub fn split(&self, bid: u128) -> BidSplit {
+        let donation = bid * self.allocation_ratio[0] as u128 / 10000;
+        let burn = bid * self.allocation_ratio[1] as u128 / 10000;
+        BidSplit {
+            donation,
+            burn,
+            forger: bid - donation - burn,
+        }
+    }
+}
+
+impl HermezApi {
+    /// Get the auction parameters from the state of the network.
+    pub async fn get_auction(&self) -> Result<Auction, ErrorKind> {
+        Auction::new(&self.get_state().await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Auction, AuctionStatus, BidSplit};
+
+    const HEZ: u128 = 1_000_000_000_000_000_000;
+
+    #[test]
+    fn test_auction() {
+        let auction = Auction {
+            current_slot: 12329,
+            closed_auction_slots: 2,
+            open_auction_slots: 4320,
+            default_slot_set_bid: vec![10 * HEZ, 10 * HEZ, 10 * HEZ, 10 * HEZ, 10 * HEZ, 0],
+            default_slot_set_bid_slot_num: 0,
+            outbidding: 1000,
+            allocation_ratio: [4000, 4000, 2000],
+        };
+
+        assert_eq!(AuctionStatus::Closed, auction.status(12300));
+        assert_eq!(AuctionStatus::Closed, auction.status(12331));
+        assert_eq!(AuctionStatus::Open, auction.status(12332));
+        assert_eq!(AuctionStatus::Open, auction.status(16651));
+        assert_eq!(AuctionStatus::NotYetOpen, auction.status(16652));
+
+        assert_eq!(11 * HEZ, auction.min_bid_over(12332, None));
+        assert_eq!(0, auction.min_bid_over(12335, None));
+        assert_eq!(22 * HEZ, auction.min_bid_over(12332, Some(20 * HEZ)));
+
+        assert_eq!(
+            BidSplit {
+                donation: 4 * HEZ,
+                burn: 4 * HEZ,
+                forger: 2 * HEZ,
+            },
+            auction.split(10 * HEZ)
+        );
+    }
+}
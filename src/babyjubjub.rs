@@ -0,0 +1,327 @@
+//! BabyJubJub keys, and the EdDSA signatures with Poseidon that authorize
+//! L2 transactions.
+//!
+//! Keys and signatures are derived as circomlib and the Hermez wallets do, so
+//! a private key gives the same public key, and the same signatures, here as
+//! in any other Hermez client.
+
+use num_bigint::BigUint;
+
+use std::fmt;
+use std::sync::OnceLock;
+
+use super::blake512::blake512;
+use super::poseidon::{modulus, poseidon};
+use super::ErrorKind;
+
+const A: u32 = 168700;
+const D: u32 = 168696;
+const BASE8_X: &str =
+    "5299619240641551281634865583518297030282874472190772894086521144482721001553";
+const BASE8_Y: &str =
+    "16950150798460657717958625567821834550301663161624707787222815936182638968203";
+const SUBORDER: &str =
+    "2736030358979909402780800718157159386076813972158567259200215660948447373041";
+
+/// A BabyJubJub private key.
+#[derive(Clone, PartialEq)]
+pub struct PrivateKey([u8; 32]);
+
+impl PrivateKey {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    /// Reads a key given as 64 hexadecimal digits, with or without "0x".
+    pub fn from_hex(hex: &str) -> Result<Self, ErrorKind> {
+        let hex = hex.trim().trim_start_matches("0x");
+        let invalid = || ErrorKind::Config(String::from("Invalid BJJ private key"));
+        if hex.len() != 64 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let mut bytes = [0; 32];
+        for (byte, digits) in bytes.iter_mut().zip(hex.as_bytes().chunks(2)) {
+            let digits = std::str::from_utf8(digits).map_err(|_| invalid())?;
+            *byte = u8::from_str_radix(digits, 16).map_err(|_| invalid())?;
+        }
+        Ok(Self(bytes))
+    }
+
+    pub fn to_hex(&self) -> String {
+        to_hex(&self.0)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn public_key(&self) -> PublicKey {
+        let (x, y) = mul(base8(), &(self.scalar() >> 3u32));
+        PublicKey { x, y }
+    }
+
+    /// Signs a field element, like the hash of a transaction.
+    pub fn sign(&self, message: &BigUint) -> Signature {
+        let hash = blake512(&self.0);
+        let s = self.scalar();
+        let public_key = self.public_key();
+
+        let mut nonce_input = hash[32..].to_vec();
+        nonce_input.extend_from_slice(&to_le_bytes(message));
+        let r = BigUint::from_bytes_le(&blake512(&nonce_input)) % suborder();
+        let r8 = mul(base8(), &r);
+
+        let hm = poseidon(&[
+            r8.0.clone(),
+            r8.1.clone(),
+            public_key.x,
+            public_key.y,
+            message.clone(),
+        ]);
+        Signature {
+            r8_x: r8.0,
+            r8_y: r8.1,
+            s: (r + hm * s) % suborder(),
+        }
+    }
+
+    // The secret scalar, from the first half of the hash of the key.
+    fn scalar(&self) -> BigUint {
+        let mut bytes = blake512(&self.0)[..32].to_vec();
+        bytes[0] &= 0xf8;
+        bytes[31] &= 0x7f;
+        bytes[31] |= 0x40;
+        BigUint::from_bytes_le(&bytes)
+    }
+}
+
+impl fmt::Debug for PrivateKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PrivateKey(..)")
+    }
+}
+
+/// A point of the curve, as a public key.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PublicKey {
+    pub x: BigUint,
+    pub y: BigUint,
+}
+
+impl PublicKey {
+    /// The y coordinate in little endian, with the sign of x in the top bit.
+    pub fn compress(&self) -> [u8; 32] {
+        let mut bytes = to_le_bytes(&self.y);
+        if self.x > modulus() >> 1u32 {
+            bytes[31] |= 0x80;
+        }
+        bytes
+    }
+
+    /// The key as given to the API, like "hez:rR7LXKal-av7I56Y0dEBCVmwc9zp...".
+    pub fn to_hez(&self) -> String {
+        let mut bytes = self.compress().to_vec();
+        let checksum = bytes.iter().fold(0u8, |sum, byte| sum.wrapping_add(*byte));
+        bytes.push(checksum);
+        format!("hez:{}", base64::encode_config(&bytes, base64::URL_SAFE))
+    }
+
+    /// Checks a signature of a field element.
+    pub fn verify(&self, message: &BigUint, signature: &Signature) -> bool {
+        if &signature.s >= suborder() {
+            return false;
+        }
+        let hm = poseidon(&[
+            signature.r8_x.clone(),
+            signature.r8_y.clone(),
+            self.x.clone(),
+            self.y.clone(),
+            message.clone(),
+        ]);
+        let left = mul(base8(), &signature.s);
+        let right = add(
+            &(signature.r8_x.clone(), signature.r8_y.clone()),
+            &mul(&(self.x.clone(), self.y.clone()), &(hm * 8u32)),
+        );
+        left == right
+    }
+}
+
+/// An EdDSA signature with Poseidon.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Signature {
+    pub r8_x: BigUint,
+    pub r8_y: BigUint,
+    pub s: BigUint,
+}
+
+impl Signature {
+    /// The compressed point R8 followed by S in little endian.
+    pub fn compress(&self) -> [u8; 64] {
+        let r8 = PublicKey {
+            x: self.r8_x.clone(),
+            y: self.r8_y.clone(),
+        };
+        let mut bytes = [0; 64];
+        bytes[..32].copy_from_slice(&r8.compress());
+        bytes[32..].copy_from_slice(&to_le_bytes(&self.s));
+        bytes
+    }
+
+    /// The compressed signature in hexadecimal, as posted to the pool.
+    pub fn to_hex(&self) -> String {
+        to_hex(&self.compress())
+    }
+}
+
+// The sign and y coordinate of a compressed key, given as "hez:" followed by
+// the key and a checksum byte in URL safe base64. An empty key gives zeros.
+pub(crate) fn parse_bjj(bjj: &str) -> Result<(bool, BigUint), ErrorKind> {
+    let invalid = || ErrorKind::Api(format!("Invalid BJJ key {}", bjj));
+    if bjj.is_empty() {
+        return Ok((false, BigUint::from(0u32)));
+    }
+    let bytes = base64::decode_config(bjj.trim_start_matches("hez:"), base64::URL_SAFE)
+        .map_err(|_| invalid())?;
+    if bytes.len() != 33 {
+        return Err(invalid());
+    }
+    let mut key = bytes[..32].to_vec();
+    let sign = key[31] & 0x80 != 0;
+    key[31] &= 0x7f;
+    let ay = BigUint::from_bytes_le(&key);
+    if &ay >= modulus() {
+        return Err(invalid());
+    }
+    Ok((sign, ay))
+}
+
+type Point = (BigUint, BigUint);
+
+fn base8() -> &'static Point {
+    static BASE8: OnceLock<Point> = OnceLock::new();
+    BASE8.get_or_init(|| (BASE8_X.parse().unwrap(), BASE8_Y.parse().unwrap()))
+}
+
+fn suborder() -> &'static BigUint {
+    static SUBORDER_INT: OnceLock<BigUint> = OnceLock::new();
+    SUBORDER_INT.get_or_init(|| SUBORDER.parse().unwrap())
+}
+
+// Points in projective coordinates (X : Y : Z), for x = X / Z and y = Y / Z,
+// so that only the final conversion needs an inversion.
+type Projective = (BigUint, BigUint, BigUint);
+
+fn add(p1: &Point, p2: &Point) -> Point {
+    let one = BigUint::from(1u32);
+    to_affine(&add_projective(
+        &(p1.0.clone(), p1.1.clone(), one.clone()),
+        &(p2.0.clone(), p2.1.clone(), one),
+    ))
+}
+
+// Addition on the twisted Edwards curve a x^2 + y^2 = 1 + d x^2 y^2.
+fn add_projective(p1: &Projective, p2: &Projective) -> Projective {
+    let p = modulus();
+    let a = &p1.2 * &p2.2 % p;
+    let b = &a * &a % p;
+    let c = &p1.0 * &p2.0 % p;
+    let d = &p1.1 * &p2.1 % p;
+    let e = BigUint::from(D) * &c % p * &d % p;
+    let f = (&b + p - &e) % p;
+    let g = (b + e) % p;
+    let sum = (&p1.0 + &p1.1) * (&p2.0 + &p2.1) % p;
+    let x = &a * &f % p * ((sum + 2u32 * p - &c - &d) % p) % p;
+    let y = a * &g % p * ((d + p - BigUint::from(A) * c % p) % p) % p;
+    (x, y, f * g % p)
+}
+
+fn to_affine(point: &Projective) -> Point {
+    let p = modulus();
+    let z = inverse(&point.2);
+    (&point.0 * &z % p, &point.1 * &z % p)
+}
+
+fn mul(point: &Point, scalar: &BigUint) -> Point {
+    let mut result = (
+        BigUint::from(0u32),
+        BigUint::from(1u32),
+        BigUint::from(1u32),
+    );
+    let mut addend = (point.0.clone(), point.1.clone(), BigUint::from(1u32));
+    for i in 0..scalar.bits() {
+        if scalar.bit(i) {
+            result = add_projective(&result, &addend);
+        }
+        addend = add_projective(&addend, &addend);
+    }
+    to_affine(&result)
+}
+
+fn inverse(value: &BigUint) -> BigUint {
+    let p = modulus();
+    value.modpow(&(p - 2u32), p)
+}
+
+fn to_le_bytes(value: &BigUint) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    let le = value.to_bytes_le();
+    bytes[..le.len()].copy_from_slice(&le);
+    bytes
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_bjj, PrivateKey};
+    use num_bigint::BigUint;
+
+    #[test]
+    fn test_sign() {
+        // Vector of the circomlib tests.
+        let key = PrivateKey::from_hex(
+            "0001020304050607080900010203040506070809000102030405060708090001",
+        )
+        .unwrap();
+        let public_key = key.public_key();
+        assert_eq!(
+            "13277427435165878497778222415993513565335242147425444199013288855685581939618",
+            public_key.x.to_string()
+        );
+        assert_eq!(
+            "13622229784656158136036771217484571176836296686641868549125388198837476602820",
+            public_key.y.to_string()
+        );
+
+        let message = BigUint::from_bytes_le(&[0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+        let signature = key.sign(&message);
+        assert_eq!(
+            "11384336176656855268977457483345535180380036354188103142384839473266348197733",
+            signature.r8_x.to_string()
+        );
+        assert_eq!(
+            "15383486972088797283337779941324724402501462225528836549661220478783371668959",
+            signature.r8_y.to_string()
+        );
+        assert_eq!(
+            "1672775540645840396591609181675628451599263765380031905495115170613215233181",
+            signature.s.to_string()
+        );
+        assert!(public_key.verify(&message, &signature));
+        assert!(!public_key.verify(&(message + 1u32), &signature));
+
+        let (sign, ay) = parse_bjj(&public_key.to_hez()).unwrap();
+        assert_eq!(public_key.y, ay);
+        assert_eq!(public_key.compress()[31] & 0x80 != 0, sign);
+    }
+
+    #[test]
+    fn test_parse_bjj() {
+        assert!(parse_bjj("hez:rR7LXKal-av7I56Y0dEBCVmwc9zpoLY5ERhy5w7G-xwe").is_ok());
+        assert_eq!((false, BigUint::from(0u32)), parse_bjj("").unwrap());
+        assert!(parse_bjj("hez:rR7LXKal").is_err());
+    }
+}
@@ -1,70 +1,86 @@
-use super::http::Http;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
+use super::Endpoint;
 use super::ErrorKind;
+use super::HermezApi;
 use super::PaginationOrder;
+use super::Query;
 
 use super::transactions_history::HistoryTransaction;
 
 use std::collections::HashMap;
 
-pub struct BatchesGetOptions<'a> {
-    http: &'a Http,
-    url: &'a Url,
+/// Owned description of a request to the batches endpoint.
+///
+/// Can be cloned, stored and serialized, and then run with `HermezApi::run()`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BatchesQuery {
+    pub min_batch_num: Option<u32>,
+    pub max_batch_num: Option<u32>,
+    pub slot_num: Option<u32>,
+    pub forger_addr: Option<String>,
+
+    pub from_item: Option<u64>,
+    pub order: Option<PaginationOrder>,
+    pub limit: Option<u64>,
+}
+
+impl Query for BatchesQuery {
+    type Output = Batches;
+    const ENDPOINT: Endpoint = Endpoint::Batches;
 
-    min_batch_num: Option<u32>,
-    max_batch_num: Option<u32>,
-    slot_num: Option<u32>,
-    forger_addr: Option<&'a str>,
+    fn url(&self, base: &Url) -> Result<Url, ErrorKind> {
+        let mut url = base.join("batches").unwrap();
+        {
+            let mut query_pairs = url.query_pairs_mut();
 
-    from_item: Option<u64>,
-    order: Option<PaginationOrder>,
-    limit: Option<u64>,
+            fetch_stmt!(self, query_pairs, min_batch_num, "minBatchNum");
+            fetch_stmt!(self, query_pairs, max_batch_num, "maxBatchNum");
+            fetch_stmt!(self, query_pairs, slot_num, "slotNum");
+            fetch_stmt!(self, query_pairs, forger_addr, "forgerAddr", &str);
+
+            pagination_fetch_stmts!(self, query_pairs);
+        }
+        Ok(url)
+    }
 }
 
-impl<'a> BatchesGetOptions<'a> {
-    pub fn new(http: &'a Http, url: &'a Url) -> Self {
-        Self {
-            http,
-            url,
+paged_query!(BatchesQuery, Batch, batches);
 
-            min_batch_num: None,
-            max_batch_num: None,
-            slot_num: None,
-            forger_addr: None,
+pub struct BatchesGetOptions {
+    api: HermezApi,
+    query: BatchesQuery,
+}
 
-            from_item: None,
-            order: None,
-            limit: None,
+impl BatchesGetOptions {
+    pub fn new(api: &HermezApi) -> Self {
+        Self {
+            api: api.clone(),
+            query: Default::default(),
         }
     }
 
     setter!(min_batch_num, u32);
     setter!(max_batch_num, u32);
     setter!(slot_num, u32);
-    setter!(forger_addr, &'a str);
+    setter!(forger_addr, &str);
 
     pagination_setters!();
 
-    pub async fn fetch(&self) -> Result<(Vec<Batch>, u64), ErrorKind> {
-        let mut url = self.url.join("batches").unwrap();
-        {
-            let mut query_pairs = url.query_pairs_mut();
-
-            fetch_stmt!(self, query_pairs, min_batch_num, "minBatchNum");
-            fetch_stmt!(self, query_pairs, max_batch_num, "maxBatchNum");
-            fetch_stmt!(self, query_pairs, slot_num, "slotNum");
-            fetch_stmt!(self, query_pairs, forger_addr, "forgerAddr", &str);
+    /// The query built so far.
+    pub fn query(&self) -> &BatchesQuery {
+        &self.query
+    }
 
-            pagination_fetch_stmts!(self, query_pairs);
-        }
-        let batches: Batches = self.http.get(&url).await?;
+    pub async fn fetch(&self) -> Result<(Vec<Batch>, u64), ErrorKind> {
+        let batches = self.api.run(&self.query).await?;
         Ok((batches.batches, batches.pending_items))
     }
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct FullBatch {
     pub batch: Batch,
     pub transactions: Vec<HistoryTransaction>,
@@ -72,28 +88,28 @@ pub struct FullBatch {
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct Batches {
+pub struct Batches {
     pub pending_items: u64,
     pub batches: Vec<Batch>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Batch {
-    item_id: u64,
-    batch_num: u32,
-    ethereum_block_num: u128,
-    ethereum_block_hash: String,
-    ethereum_tx_hash: String,
-    timestamp: String,
-    forger_addr: String,
-    collected_fees: HashMap<String, String>,
+    pub item_id: u64,
+    pub batch_num: u32,
+    pub ethereum_block_num: u128,
+    pub ethereum_block_hash: String,
+    pub ethereum_tx_hash: String,
+    pub timestamp: String,
+    pub forger_addr: String,
+    pub collected_fees: HashMap<String, String>,
     #[serde(rename = "historicTotalCollectedFeesUSD")]
-    historic_total_collected_fees_usd: f64,
-    state_root: String,
-    num_accounts: u64,
-    exit_root: String,
-    forge_l1_transactions_num: Option<u64>,
-    slot_num: u32,
-    forged_transactions: u64,
+    pub historic_total_collected_fees_usd: f64,
+    pub state_root: String,
+    pub num_accounts: u64,
+    pub exit_root: String,
+    pub forge_l1_transactions_num: Option<u64>,
+    pub slot_num: u32,
+    pub forged_transactions: u64,
 }
@@ -1,49 +1,37 @@
-use super::http::Http;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
+use super::Endpoint;
 use super::ErrorKind;
+use super::HermezApi;
 use super::PaginationOrder;
+use super::Query;
 
-pub struct BidsGetOptions<'a> {
-    http: &'a Http,
-    url: &'a Url,
+/// Owned description of a request to the bids endpoint.
+///
+/// Can be cloned, stored and serialized, and then run with `HermezApi::run()`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct BidsQuery {
+    pub slot_num: Option<u32>,
+    pub bidder_addr: Option<String>,
 
-    slot_num: Option<u32>,
-    bidder_addr: Option<&'a str>,
-
-    from_item: Option<u64>,
-    order: Option<PaginationOrder>,
-    limit: Option<u64>,
+    pub from_item: Option<u64>,
+    pub order: Option<PaginationOrder>,
+    pub limit: Option<u64>,
 }
 
-impl<'a> BidsGetOptions<'a> {
-    pub fn new(http: &'a Http, url: &'a Url) -> Self {
-        Self {
-            http,
-            url,
-
-            slot_num: None,
-            bidder_addr: None,
-
-            from_item: None,
-            order: None,
-            limit: None,
-        }
-    }
-
-    setter!(slot_num, u32);
-    setter!(bidder_addr, &'a str);
+impl Query for BidsQuery {
+    type Output = Bids;
+    const ENDPOINT: Endpoint = Endpoint::Bids;
 
-    pagination_setters!();
-
-    pub async fn fetch(&self) -> Result<(Vec<Bid>, u64), ErrorKind> {
+    fn url(&self, base: &Url) -> Result<Url, ErrorKind> {
         if self.slot_num.is_none() && self.bidder_addr.is_none() {
             return Err(ErrorKind::Api(String::from(
                 "get_bids: At least one filter must be specified",
             )));
         }
-        let mut url = self.url.join("bids").unwrap();
+        let mut url = base.join("bids").unwrap();
         {
             let mut query_pairs = url.query_pairs_mut();
 
@@ -52,19 +40,49 @@ impl<'a> BidsGetOptions<'a> {
 
             pagination_fetch_stmts!(self, query_pairs);
         }
-        let bids: Bids = self.http.get(&url).await?;
+        Ok(url)
+    }
+}
+
+paged_query!(BidsQuery, Bid, bids);
+
+pub struct BidsGetOptions {
+    api: HermezApi,
+    query: BidsQuery,
+}
+
+impl BidsGetOptions {
+    pub fn new(api: &HermezApi) -> Self {
+        Self {
+            api: api.clone(),
+            query: Default::default(),
+        }
+    }
+
+    setter!(slot_num, u32);
+    setter!(bidder_addr, &str);
+
+    pagination_setters!();
+
+    /// The query built so far.
+    pub fn query(&self) -> &BidsQuery {
+        &self.query
+    }
+
+    pub async fn fetch(&self) -> Result<(Vec<Bid>, u64), ErrorKind> {
+        let bids = self.api.run(&self.query).await?;
         Ok((bids.bids, bids.pending_items))
     }
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct Bids {
+pub struct Bids {
     pub pending_items: u64,
     pub bids: Vec<Bid>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Bid {
     pub item_id: u64,
@@ -0,0 +1,216 @@
+// A BJJ private key stored on disk, encrypted with a password.
+//
+// The key is encrypted with ChaCha20-Poly1305, under a key derived from the
+// password with scrypt. The public parts, the BJJ key and an optional
+// Ethereum address, are kept in clear so that balances can be shown without
+// the password.
+
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use serde::{Deserialize, Serialize};
+use std::convert::TryInto;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use hermez_api::babyjubjub::PrivateKey;
+
+// scrypt parameters, as recommended for interactive logins.
+const LOG_N: u8 = 15;
+const R: u32 = 8;
+const P: u32 = 1;
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Keystore {
+    pub version: u32,
+    pub bjj: String,
+    pub hez_ethereum_address: Option<String>,
+    log_n: u8,
+    r: u32,
+    p: u32,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+impl Keystore {
+    pub fn encrypt(
+        key: &PrivateKey,
+        hez_ethereum_address: Option<String>,
+        password: &str,
+    ) -> Result<Self, String> {
+        Self::encrypt_with(key, hez_ethereum_address, password, LOG_N)
+    }
+
+    fn encrypt_with(
+        key: &PrivateKey,
+        hez_ethereum_address: Option<String>,
+        password: &str,
+        log_n: u8,
+    ) -> Result<Self, String> {
+        let salt = random::<16>()?;
+        let nonce = random::<12>()?;
+        let cipher = cipher(password, &salt, log_n, R, P)?;
+        let ciphertext = cipher
+            .encrypt(&Nonce::from(nonce), key.as_bytes().as_ref())
+            .map_err(|_| String::from("Cannot encrypt the key"))?;
+        Ok(Self {
+            version: 1,
+            bjj: key.public_key().to_hez(),
+            hez_ethereum_address,
+            log_n,
+            r: R,
+            p: P,
+            salt: base64::encode(salt),
+            nonce: base64::encode(nonce),
+            ciphertext: base64::encode(ciphertext),
+        })
+    }
+
+    pub fn decrypt(&self, password: &str) -> Result<PrivateKey, String> {
+        let invalid = || String::from("Invalid keystore");
+        let salt = base64::decode(&self.salt).map_err(|_| invalid())?;
+        let nonce: [u8; 12] = base64::decode(&self.nonce)
+            .map_err(|_| invalid())?
+            .try_into()
+            .map_err(|_| invalid())?;
+        let ciphertext = base64::decode(&self.ciphertext).map_err(|_| invalid())?;
+
+        let cipher = cipher(password, &salt, self.log_n, self.r, self.p)?;
+        let bytes = cipher
+            .decrypt(&Nonce::from(nonce), ciphertext.as_ref())
+            .map_err(|_| String::from("Wrong password"))?;
+        let key = PrivateKey::new(bytes.try_into().map_err(|_| invalid())?);
+        if key.public_key().to_hez() != self.bjj {
+            return Err(invalid());
+        }
+        Ok(key)
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|err| format!("Cannot read keystore {}: {}", path.display(), err))?;
+        serde_json::from_str(&text)
+            .map_err(|err| format!("Invalid keystore {}: {}", path.display(), err))
+    }
+
+    // Writes the keystore, refusing to replace another unless `force`.
+    pub fn save(&self, path: &Path, force: bool) -> Result<(), String> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .map_err(|err| format!("Cannot create {}: {}", dir.display(), err))?;
+        }
+        let text = serde_json::to_string_pretty(self).unwrap() + "\n";
+        let mut options = fs::OpenOptions::new();
+        options.write(true).create_new(!force);
+        if force {
+            options.create(true).truncate(true);
+        }
+        // Only readable by its owner, from its creation.
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let mut file = options.open(path).map_err(|err| {
+            if err.kind() == io::ErrorKind::AlreadyExists {
+                format!(
+                    "Keystore {} already exists, use --force to replace it",
+                    path.display()
+                )
+            } else {
+                format!("Cannot write keystore {}: {}", path.display(), err)
+            }
+        })?;
+        let error = |err: io::Error| format!("Cannot write keystore {}: {}", path.display(), err);
+        // A replaced file keeps its mode otherwise.
+        #[cfg(unix)]
+        if force {
+            use std::os::unix::fs::PermissionsExt;
+            file.set_permissions(fs::Permissions::from_mode(0o600))
+                .map_err(error)?;
+        }
+        file.write_all(text.as_bytes()).map_err(error)
+    }
+}
+
+// `$HOME/.hermez/keystore.json`.
+pub fn default_path() -> PathBuf {
+    let home = std::env::var_os("HOME").unwrap_or_default();
+    PathBuf::from(home).join(".hermez").join("keystore.json")
+}
+
+// The password from HERMEZ_PASSWORD, or asked on the terminal, twice when
+// `confirm` is set.
+pub fn password(confirm: bool) -> Result<String, String> {
+    if let Ok(password) = std::env::var("HERMEZ_PASSWORD") {
+        return Ok(password);
+    }
+    let password = rpassword::prompt_password("Password: ").map_err(|err| err.to_string())?;
+    if confirm {
+        let again =
+            rpassword::prompt_password("Confirm password: ").map_err(|err| err.to_string())?;
+        if again != password {
+            return Err(String::from("Passwords do not match"));
+        }
+    }
+    Ok(password)
+}
+
+pub fn random<const N: usize>() -> Result<[u8; N], String> {
+    let mut bytes = [0; N];
+    getrandom::getrandom(&mut bytes).map_err(|err| err.to_string())?;
+    Ok(bytes)
+}
+
+fn cipher(
+    password: &str,
+    salt: &[u8],
+    log_n: u8,
+    r: u32,
+    p: u32,
+) -> Result<ChaCha20Poly1305, String> {
+    let params =
+        scrypt::Params::new(log_n, r, p, 32).map_err(|_| String::from("Invalid keystore"))?;
+    let mut key = [0; 32];
+    scrypt::scrypt(password.as_bytes(), salt, &params, &mut key)
+        .map_err(|_| String::from("Invalid keystore"))?;
+    Ok(ChaCha20Poly1305::new(&Key::from(key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Keystore;
+    use hermez_api::babyjubjub::PrivateKey;
+
+    #[test]
+    fn test_keystore() {
+        let key = PrivateKey::new([7; 32]);
+        let keystore = Keystore::encrypt_with(&key, None, "secret", 4).unwrap();
+        assert_eq!(key.public_key().to_hez(), keystore.bjj);
+        assert_eq!(key, keystore.decrypt("secret").unwrap());
+        assert!(keystore.decrypt("wrong").is_err());
+    }
+
+    #[test]
+    fn test_save() {
+        let key = PrivateKey::new([7; 32]);
+        let keystore = Keystore::encrypt_with(&key, None, "secret", 4).unwrap();
+        let path = std::env::temp_dir()
+            .join(format!("hermez-keystore-{}", std::process::id()))
+            .join("keystore.json");
+        let _ = std::fs::remove_file(&path);
+        keystore.save(&path, false).unwrap();
+        assert!(keystore.save(&path, false).is_err());
+        keystore.save(&path, true).unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(0o600, mode & 0o777);
+        }
+        assert_eq!(
+            key,
+            Keystore::load(&path).unwrap().decrypt("secret").unwrap()
+        );
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
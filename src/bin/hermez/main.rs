@@ -0,0 +1,677 @@
+//! Command line access to the Hermez API.
+//!
+//! Each subcommand mirrors a method of `HermezApi`. Given an id, list
+//! subcommands get that one item, otherwise they list the items matching the
+//! filters, following every page unless `--page` is given.
+//!
+//! The `wallet` subcommands keep a BJJ key in an encrypted keystore, and
+//! sign and send transfers and exits with it.
+
+use clap::{Args, Parser, Subcommand};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use hermez_api::{ErrorKind, HermezApi, PagedQuery, PaginationOrder};
+
+mod keystore;
+mod output;
+mod wallet;
+
+use output::Format;
+
+#[derive(Parser)]
+#[command(name = "hermez", about = "Query the Hermez rollup API")]
+struct Cli {
+    /// Use the Mainnet node.
+    #[arg(long, global = true, conflicts_with_all = ["testnet", "url"])]
+    mainnet: bool,
+    /// Use the Rinkeby test net node, the default.
+    #[arg(long, global = true, conflicts_with = "url")]
+    testnet: bool,
+    /// Base URL of the node to use.
+    #[arg(long, global = true, env = "HERMEZ_URL")]
+    url: Option<String>,
+    #[arg(short, long, global = true, value_enum, default_value = "json")]
+    output: Format,
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Show the state of the network.
+    State,
+    /// Show the health of the node.
+    Health,
+    /// Show the configuration of the rollup.
+    Config,
+    /// Get a token, or list tokens.
+    Tokens {
+        id: Option<u32>,
+        #[arg(long, value_delimiter = ',')]
+        ids: Option<Vec<u32>>,
+        #[arg(long, value_delimiter = ',')]
+        symbols: Option<Vec<String>>,
+        #[command(flatten)]
+        pagination: Pagination,
+    },
+    /// Get an account, or list accounts.
+    Accounts {
+        account_index: Option<String>,
+        #[arg(long)]
+        hez_ethereum_address: Option<String>,
+        #[arg(long)]
+        bjj: Option<String>,
+        #[arg(long, value_delimiter = ',')]
+        token_ids: Option<Vec<u32>>,
+        #[command(flatten)]
+        pagination: Pagination,
+    },
+    /// Get an exit, given a batch number and an account index, or list exits.
+    Exits {
+        #[arg(requires = "exit_account_index")]
+        exit_batch_num: Option<u32>,
+        exit_account_index: Option<String>,
+        #[arg(long)]
+        token_id: Option<u32>,
+        #[arg(long)]
+        hez_ethereum_address: Option<String>,
+        #[arg(long)]
+        bjj: Option<String>,
+        #[arg(long)]
+        account_index: Option<String>,
+        #[arg(long)]
+        batch_num: Option<u32>,
+        #[arg(long)]
+        only_pending_withdraws: Option<bool>,
+        #[command(flatten)]
+        pagination: Pagination,
+    },
+    /// Get a forged transaction, or list the history of transactions.
+    History {
+        id: Option<String>,
+        #[arg(long)]
+        token_id: Option<u32>,
+        #[arg(long)]
+        hez_ethereum_address: Option<String>,
+        #[arg(long)]
+        from_hez_ethereum_address: Option<String>,
+        #[arg(long)]
+        to_hez_ethereum_address: Option<String>,
+        #[arg(long)]
+        bjj: Option<String>,
+        #[arg(long)]
+        from_bjj: Option<String>,
+        #[arg(long)]
+        to_bjj: Option<String>,
+        #[arg(long)]
+        account_index: Option<String>,
+        #[arg(long)]
+        from_account_index: Option<String>,
+        #[arg(long)]
+        to_account_index: Option<String>,
+        #[arg(long)]
+        batch_num: Option<u32>,
+        /// Transaction type, as named by the API, like Transfer or Deposit.
+        #[arg(long, value_parser = enum_arg::<hermez_api::transactions_history::TransactionType>)]
+        r#type: Option<hermez_api::transactions_history::TransactionType>,
+        #[command(flatten)]
+        pagination: Pagination,
+    },
+    /// Get a batch, or list batches.
+    Batches {
+        batch_num: Option<u32>,
+        /// Get the batch with its transactions.
+        #[arg(long, requires = "batch_num")]
+        full: bool,
+        #[arg(long)]
+        min_batch_num: Option<u32>,
+        #[arg(long)]
+        max_batch_num: Option<u32>,
+        #[arg(long)]
+        slot_num: Option<u32>,
+        #[arg(long)]
+        forger_addr: Option<String>,
+        #[command(flatten)]
+        pagination: Pagination,
+    },
+    /// Get a slot, or list slots.
+    Slots {
+        slot_num: Option<u32>,
+        #[arg(long)]
+        min_slot_num: Option<u32>,
+        #[arg(long)]
+        max_slot_num: Option<u32>,
+        #[arg(long)]
+        won_by_ethereum_address: Option<String>,
+        #[arg(long)]
+        finished_auction: Option<bool>,
+        #[command(flatten)]
+        pagination: Pagination,
+    },
+    /// List bids.
+    Bids {
+        #[arg(long)]
+        slot_num: Option<u32>,
+        #[arg(long)]
+        bidder_addr: Option<String>,
+        #[command(flatten)]
+        pagination: Pagination,
+    },
+    /// List coordinators.
+    Coordinators {
+        #[arg(long)]
+        forger_addr: Option<String>,
+        #[arg(long)]
+        bidder_addr: Option<String>,
+        #[command(flatten)]
+        pagination: Pagination,
+    },
+    /// Get or post transactions of the pool.
+    Pool {
+        #[command(subcommand)]
+        command: PoolCommand,
+    },
+    /// Get or post the authorization to create accounts for an address.
+    AccountCreationAuthorization {
+        #[command(subcommand)]
+        command: AuthorizationCommand,
+    },
+    /// Keep a BJJ key, and send transactions signed with it.
+    Wallet(wallet::Wallet),
+}
+
+#[derive(Subcommand)]
+enum PoolCommand {
+    /// Get a transaction of the pool, or list transactions of the pool.
+    Get {
+        id: Option<String>,
+        /// State of the transactions, pend, fing, fged or invl.
+        #[arg(long, value_parser = enum_arg::<hermez_api::transactions_pool::PoolL2TransactionState>)]
+        state: Option<hermez_api::transactions_pool::PoolL2TransactionState>,
+        #[arg(long)]
+        token_id: Option<u32>,
+        #[arg(long)]
+        account_index: Option<String>,
+        #[arg(long)]
+        from_account_index: Option<String>,
+        #[arg(long)]
+        to_account_index: Option<String>,
+        #[arg(long, value_parser = enum_arg::<hermez_api::transactions_history::TransactionType>)]
+        r#type: Option<hermez_api::transactions_history::TransactionType>,
+        #[arg(long)]
+        hez_ethereum_address: Option<String>,
+        #[arg(long)]
+        from_hez_ethereum_address: Option<String>,
+        #[arg(long)]
+        to_hez_ethereum_address: Option<String>,
+        #[arg(long)]
+        bjj: Option<String>,
+        #[arg(long)]
+        from_bjj: Option<String>,
+        #[arg(long)]
+        to_bjj: Option<String>,
+        #[command(flatten)]
+        pagination: Pagination,
+    },
+    /// Post a signed transaction to the pool, and print its id.
+    Post(PoolPost),
+}
+
+#[derive(Args)]
+struct PoolPost {
+    #[arg(long)]
+    id: String,
+    /// Transaction type, as named by the API, like Transfer or Exit.
+    #[arg(long, value_parser = enum_arg::<hermez_api::transactions_pool::TransactionTypeL2>)]
+    r#type: Option<hermez_api::transactions_pool::TransactionTypeL2>,
+    #[arg(long)]
+    token_id: Option<u32>,
+    #[arg(long)]
+    from_account_index: Option<String>,
+    #[arg(long)]
+    to_account_index: Option<String>,
+    #[arg(long)]
+    to_hez_ethereum_address: Option<String>,
+    #[arg(long)]
+    to_bjj: Option<String>,
+    #[arg(long)]
+    amount: Option<String>,
+    #[arg(long)]
+    fee: Option<u16>,
+    #[arg(long)]
+    nonce: Option<u128>,
+    #[arg(long)]
+    signature: Option<String>,
+    #[arg(long)]
+    request_from_account_index: Option<String>,
+    #[arg(long)]
+    request_to_account_index: Option<String>,
+    #[arg(long)]
+    request_to_hez_ethereum_address: Option<String>,
+    #[arg(long)]
+    request_to_bjj: Option<String>,
+    #[arg(long)]
+    request_token_id: Option<u32>,
+    #[arg(long)]
+    request_amount: Option<String>,
+    #[arg(long)]
+    request_fee: Option<u16>,
+    #[arg(long)]
+    request_nonce: Option<u128>,
+    /// Send to the coordinators forging the next slots instead of the node.
+    #[arg(long)]
+    next_forgers: bool,
+}
+
+#[derive(Subcommand)]
+enum AuthorizationCommand {
+    Get {
+        hez_ethereum_address: String,
+    },
+    Post {
+        #[arg(long)]
+        hez_ethereum_address: String,
+        #[arg(long)]
+        bjj: String,
+        #[arg(long)]
+        signature: String,
+    },
+}
+
+#[derive(Args)]
+struct Pagination {
+    /// Only get one page of results.
+    #[arg(long)]
+    page: bool,
+    #[arg(long)]
+    from_item: Option<u64>,
+    /// asc or desc.
+    #[arg(long, value_parser = enum_arg::<PaginationOrder>)]
+    order: Option<PaginationOrder>,
+    /// Number of items per page.
+    #[arg(long)]
+    limit: Option<u64>,
+}
+
+// Applies pagination arguments to a `*GetOptions`.
+macro_rules! paginate {
+    ($options: ident, $pagination: ident) => {
+        if let Some(from_item) = $pagination.from_item {
+            $options.from_item(from_item);
+        }
+        if let Some(order) = $pagination.order {
+            $options.order(order);
+        }
+        if let Some(limit) = $pagination.limit {
+            $options.limit(limit);
+        }
+    };
+}
+
+// Calls the setters of a `*GetOptions` for the arguments given.
+macro_rules! set {
+    ($options: ident, $($name: ident),*) => {
+        $(
+            if let Some($name) = &$name {
+                $options.$name($name);
+            }
+        )*
+    };
+    ($options: ident, $($name: ident),*; copy) => {
+        $(
+            if let Some($name) = $name {
+                $options.$name($name);
+            }
+        )*
+    };
+}
+
+#[async_std::main]
+async fn main() {
+    let cli = Cli::parse();
+    let url = match (&cli.url, cli.mainnet) {
+        (Some(url), _) => url.as_str(),
+        (None, true) => HermezApi::MAINNET_URL,
+        (None, false) => HermezApi::TESTNET_URL,
+    };
+    let result = match HermezApi::new(url) {
+        Ok(api) => run(&api, cli.command, cli.output).await,
+        Err(err) => Err(format!("Invalid URL {}: {}", url, err)),
+    };
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+async fn run(api: &HermezApi, command: Command, format: Format) -> Result<(), String> {
+    match command {
+        Command::State => show(format, api.get_state().await),
+        Command::Health => show(format, api.get_health().await),
+        Command::Config => show(format, api.get_config().await),
+        Command::Tokens { id: Some(id), .. } => show(format, api.get_token(id).await),
+        Command::Tokens {
+            id: None,
+            ids,
+            symbols,
+            pagination,
+        } => {
+            let mut options = api.tokens_get_options();
+            if let Some(ids) = &ids {
+                options.ids(ids);
+            }
+            if let Some(symbols) = &symbols {
+                let symbols: Vec<&str> = symbols.iter().map(String::as_str).collect();
+                options.symbols(&symbols);
+            }
+            paginate!(options, pagination);
+            show(format, list(api, options.query(), pagination.page).await)
+        }
+        Command::Accounts {
+            account_index: Some(account_index),
+            ..
+        } => show(format, api.get_account(&account_index).await),
+        Command::Accounts {
+            account_index: None,
+            hez_ethereum_address,
+            bjj,
+            token_ids,
+            pagination,
+        } => {
+            let mut options = api.accounts_get_options();
+            set!(options, hez_ethereum_address, bjj);
+            if let Some(token_ids) = &token_ids {
+                options.token_ids(token_ids);
+            }
+            paginate!(options, pagination);
+            show(format, list(api, options.query(), pagination.page).await)
+        }
+        Command::Exits {
+            exit_batch_num: Some(batch_num),
+            exit_account_index: Some(account_index),
+            ..
+        } => show(format, api.get_exit(batch_num, &account_index).await),
+        Command::Exits {
+            token_id,
+            hez_ethereum_address,
+            bjj,
+            account_index,
+            batch_num,
+            only_pending_withdraws,
+            pagination,
+            ..
+        } => {
+            let mut options = api.exits_get_options();
+            set!(options, hez_ethereum_address, bjj, account_index);
+            set!(options, token_id, batch_num, only_pending_withdraws; copy);
+            paginate!(options, pagination);
+            show(format, list(api, options.query(), pagination.page).await)
+        }
+        Command::History { id: Some(id), .. } => {
+            show(format, api.get_transaction_history(&id).await)
+        }
+        Command::History {
+            id: None,
+            token_id,
+            hez_ethereum_address,
+            from_hez_ethereum_address,
+            to_hez_ethereum_address,
+            bjj,
+            from_bjj,
+            to_bjj,
+            account_index,
+            from_account_index,
+            to_account_index,
+            batch_num,
+            r#type,
+            pagination,
+        } => {
+            let mut options = api.transactions_history_get_options();
+            set!(
+                options,
+                hez_ethereum_address,
+                from_hez_ethereum_address,
+                to_hez_ethereum_address,
+                bjj,
+                from_bjj,
+                to_bjj,
+                account_index,
+                from_account_index,
+                to_account_index
+            );
+            set!(options, token_id, batch_num, r#type; copy);
+            paginate!(options, pagination);
+            show(format, list(api, options.query(), pagination.page).await)
+        }
+        Command::Batches {
+            batch_num: Some(batch_num),
+            full: true,
+            ..
+        } => show(format, api.get_full_batch(batch_num).await),
+        Command::Batches {
+            batch_num: Some(batch_num),
+            ..
+        } => show(format, api.get_batch(batch_num).await),
+        Command::Batches {
+            batch_num: None,
+            min_batch_num,
+            max_batch_num,
+            slot_num,
+            forger_addr,
+            pagination,
+            ..
+        } => {
+            let mut options = api.batches_get_options();
+            set!(options, forger_addr);
+            set!(options, min_batch_num, max_batch_num, slot_num; copy);
+            paginate!(options, pagination);
+            show(format, list(api, options.query(), pagination.page).await)
+        }
+        Command::Slots {
+            slot_num: Some(slot_num),
+            ..
+        } => show(format, api.get_slot(slot_num).await),
+        Command::Slots {
+            slot_num: None,
+            min_slot_num,
+            max_slot_num,
+            won_by_ethereum_address,
+            finished_auction,
+            pagination,
+        } => {
+            let mut options = api.slots_get_options();
+            set!(options, won_by_ethereum_address);
+            set!(options, min_slot_num, max_slot_num, finished_auction; copy);
+            paginate!(options, pagination);
+            show(format, list(api, options.query(), pagination.page).await)
+        }
+        Command::Bids {
+            slot_num,
+            bidder_addr,
+            pagination,
+        } => {
+            let mut options = api.bids_get_options();
+            set!(options, bidder_addr);
+            set!(options, slot_num; copy);
+            paginate!(options, pagination);
+            show(format, list(api, options.query(), pagination.page).await)
+        }
+        Command::Coordinators {
+            forger_addr,
+            bidder_addr,
+            pagination,
+        } => {
+            let mut options = api.coordinators_get_options();
+            set!(options, forger_addr, bidder_addr);
+            paginate!(options, pagination);
+            show(format, list(api, options.query(), pagination.page).await)
+        }
+        Command::Pool {
+            command: PoolCommand::Get { id: Some(id), .. },
+        } => show(format, api.get_transactions_pool(&id).await),
+        Command::Pool {
+            command:
+                PoolCommand::Get {
+                    id: None,
+                    state,
+                    token_id,
+                    account_index,
+                    from_account_index,
+                    to_account_index,
+                    r#type,
+                    hez_ethereum_address,
+                    from_hez_ethereum_address,
+                    to_hez_ethereum_address,
+                    bjj,
+                    from_bjj,
+                    to_bjj,
+                    pagination,
+                },
+        } => {
+            let mut options = api.transactions_pool_get_options();
+            set!(
+                options,
+                account_index,
+                from_account_index,
+                to_account_index,
+                hez_ethereum_address,
+                from_hez_ethereum_address,
+                to_hez_ethereum_address,
+                bjj,
+                from_bjj,
+                to_bjj
+            );
+            set!(options, state, token_id, r#type; copy);
+            paginate!(options, pagination);
+            show(format, list(api, options.query(), pagination.page).await)
+        }
+        Command::Pool {
+            command: PoolCommand::Post(post),
+        } => post_transaction(api, post, format).await,
+        Command::AccountCreationAuthorization {
+            command: AuthorizationCommand::Get {
+                hez_ethereum_address,
+            },
+        } => show(
+            format,
+            api.get_account_creation_authorization(&hez_ethereum_address)
+                .await,
+        ),
+        Command::AccountCreationAuthorization {
+            command:
+                AuthorizationCommand::Post {
+                    hez_ethereum_address,
+                    bjj,
+                    signature,
+                },
+        } => show(
+            format,
+            api.post_account_creation_authorization(&hez_ethereum_address, &bjj, &signature)
+                .await
+                .map(|()| "success"),
+        ),
+        Command::Wallet(wallet) => wallet::run(api, wallet, format).await,
+    }
+}
+
+async fn post_transaction(api: &HermezApi, post: PoolPost, format: Format) -> Result<(), String> {
+    let PoolPost {
+        id,
+        r#type,
+        token_id,
+        from_account_index,
+        to_account_index,
+        to_hez_ethereum_address,
+        to_bjj,
+        amount,
+        fee,
+        nonce,
+        signature,
+        request_from_account_index,
+        request_to_account_index,
+        request_to_hez_ethereum_address,
+        request_to_bjj,
+        request_token_id,
+        request_amount,
+        request_fee,
+        request_nonce,
+        next_forgers,
+    } = post;
+
+    let mut options = api.transactions_pool_post_options();
+    options.id(&id);
+    set!(
+        options,
+        from_account_index,
+        to_account_index,
+        to_hez_ethereum_address,
+        to_bjj,
+        amount,
+        signature,
+        request_from_account_index,
+        request_to_account_index,
+        request_to_hez_ethereum_address,
+        request_to_bjj,
+        request_amount
+    );
+    set!(
+        options,
+        r#type,
+        token_id,
+        fee,
+        nonce,
+        request_token_id,
+        request_fee,
+        request_nonce;
+        copy
+    );
+
+    if next_forgers {
+        let submissions = options.send_to_next_forgers().await;
+        let submissions = submissions.map(|submissions| {
+            submissions
+                .into_iter()
+                .map(|submission| {
+                    serde_json::json!({
+                        "forgerAddr": submission.forger_addr,
+                        "url": submission.url,
+                        "slots": submission.slots,
+                        "id": submission.result.as_ref().ok(),
+                        "error": submission.result.err().map(|err| err.to_string()),
+                    })
+                })
+                .collect::<Vec<_>>()
+        });
+        show(format, submissions)
+    } else {
+        show(format, options.fetch().await)
+    }
+}
+
+fn show<T: Serialize>(format: Format, result: Result<T, ErrorKind>) -> Result<(), String> {
+    output::print(format, &result.map_err(|err| err.to_string())?)
+}
+
+// All the items matching a query, or a single page of them.
+async fn list<Q>(api: &HermezApi, query: &Q, page: bool) -> Result<Vec<Q::Item>, ErrorKind>
+where
+    Q: PagedQuery,
+{
+    if page {
+        Ok(Q::into_items(api.run(query).await?).0)
+    } else {
+        api.run_all(query).await
+    }
+}
+
+// Parses an argument as the serialized name of an enum variant, also trying
+// it in upper and lower case.
+fn enum_arg<T: DeserializeOwned>(arg: &str) -> Result<T, String> {
+    for name in [arg.to_owned(), arg.to_uppercase(), arg.to_lowercase()] {
+        if let Ok(value) = serde_json::from_value(serde_json::Value::String(name)) {
+            return Ok(value);
+        }
+    }
+    Err(String::from("not a value the API accepts"))
+}
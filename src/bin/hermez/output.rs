@@ -0,0 +1,119 @@
+// Printing of responses as JSON, YAML or a table.
+
+use clap::ValueEnum;
+use serde::Serialize;
+use serde_json::Value;
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Format {
+    Json,
+    Yaml,
+    Table,
+}
+
+pub fn print<T: Serialize>(format: Format, value: &T) -> Result<(), String> {
+    let value = serde_json::to_value(value).map_err(|err| err.to_string())?;
+    let text = match format {
+        Format::Json => serde_json::to_string_pretty(&value).unwrap() + "\n",
+        Format::Yaml => serde_yaml::to_string(&value).map_err(|err| err.to_string())?,
+        Format::Table => table(&value),
+    };
+    print!("{}", text);
+    Ok(())
+}
+
+// A list of objects has a column per field and a row per object. A single
+// object has a row per field. Nested values are shown as JSON.
+fn table(value: &Value) -> String {
+    let (header, rows): (Vec<String>, Vec<Vec<String>>) = match value {
+        Value::Array(items) => {
+            let mut header: Vec<String> = Vec::new();
+            for item in items {
+                if let Value::Object(fields) = item {
+                    for key in fields.keys() {
+                        if !header.contains(key) {
+                            header.push(key.clone());
+                        }
+                    }
+                }
+            }
+            if header.is_empty() {
+                let rows = items.iter().map(|item| vec![cell(item)]).collect();
+                (vec![String::from("value")], rows)
+            } else {
+                let rows = items
+                    .iter()
+                    .map(|item| {
+                        header
+                            .iter()
+                            .map(|key| item.get(key).map(cell).unwrap_or_default())
+                            .collect()
+                    })
+                    .collect();
+                (header, rows)
+            }
+        }
+        Value::Object(fields) => {
+            let rows = fields
+                .iter()
+                .map(|(key, value)| vec![key.clone(), cell(value)])
+                .collect();
+            (vec![String::from("field"), String::from("value")], rows)
+        }
+        value => (vec![String::from("value")], vec![vec![cell(value)]]),
+    };
+
+    let mut widths: Vec<usize> = header.iter().map(|name| name.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |cells: &[String]| {
+        let cells: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, &width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        cells.join("  ").trim_end().to_owned() + "\n"
+    };
+
+    let mut text = line(&header);
+    let rule: Vec<String> = widths.iter().map(|&width| "-".repeat(width)).collect();
+    text += &line(&rule);
+    for row in &rows {
+        text += &line(row);
+    }
+    text
+}
+
+fn cell(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(string) => string.clone(),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::table;
+
+    #[test]
+    fn test_table() {
+        let value = serde_json::json!([
+            { "id": 0, "symbol": "ETH", "USD": 2000.5 },
+            { "id": 1, "symbol": "HEZ", "USD": null },
+        ]);
+        assert_eq!(
+            "USD     id  symbol\n------  --  ------\n2000.5  0   ETH\n        1   HEZ\n",
+            table(&value)
+        );
+
+        let value = serde_json::json!({ "batchNum": 5, "token": { "id": 0 } });
+        assert_eq!(
+            "field     value\n--------  --------\nbatchNum  5\ntoken     {\"id\":0}\n",
+            table(&value)
+        );
+    }
+}
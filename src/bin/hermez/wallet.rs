@@ -0,0 +1,370 @@
+// The `wallet` subcommands, which keep a BJJ key in a keystore and sign L2
+// transactions with it.
+
+use clap::{Args, Subcommand};
+use std::convert::TryFrom;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use hermez_api::babyjubjub::PrivateKey;
+use hermez_api::transactions_pool::{
+    fee_amount, PoolL2TransactionState, PoolQuery, TransactionTypeL2,
+};
+use hermez_api::{format_amount, parse_amount, parse_formatted_amount, ErrorKind, HermezApi};
+
+use super::keystore::{self, Keystore};
+use super::output::{self, Format};
+
+// Recipient address of transfers to a BJJ key.
+const FF_ADDRESS: &str = "hez:0xffffffffffffffffffffffffffffffffffffffff";
+
+// Time between checks of a transaction being forged.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Args)]
+pub struct Wallet {
+    /// Path of the keystore, by default ~/.hermez/keystore.json.
+    #[arg(long, global = true, env = "HERMEZ_KEYSTORE")]
+    keystore: Option<PathBuf>,
+    #[command(subcommand)]
+    command: WalletCommand,
+}
+
+#[derive(Subcommand)]
+enum WalletCommand {
+    /// Create a new key, encrypted with a password, and print its BJJ key.
+    Create {
+        /// Ethereum address of the wallet, used for withdrawals.
+        #[arg(long)]
+        hez_ethereum_address: Option<String>,
+        /// Replace an existing keystore.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Import a key, read from HERMEZ_PRIVATE_KEY or asked for, in hexadecimal.
+    Import {
+        /// Ethereum address of the wallet, used for withdrawals.
+        #[arg(long)]
+        hez_ethereum_address: Option<String>,
+        /// Replace an existing keystore.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Show the balances of the accounts of the wallet.
+    Balances,
+    /// Send tokens to an account index, a hez:0x address or a BJJ key.
+    Transfer {
+        #[arg(long)]
+        to: String,
+        #[command(flatten)]
+        send: Send,
+    },
+    /// Send tokens out of the rollup, to be withdrawn on Ethereum.
+    Exit {
+        #[command(flatten)]
+        send: Send,
+    },
+    /// Show the exits of the wallet address not fully withdrawn.
+    Withdrawals {
+        /// By default the address of the keystore.
+        #[arg(long)]
+        hez_ethereum_address: Option<String>,
+    },
+}
+
+#[derive(Args)]
+struct Send {
+    /// Account index to send from, like hez:ETH:256.
+    #[arg(long)]
+    from: String,
+    /// Amount in units of the token, like 1.5.
+    #[arg(long)]
+    amount: String,
+    /// Fee selector, by default the lowest covering the recommended fee.
+    #[arg(long)]
+    fee: Option<u16>,
+    /// By default the next nonce after the account and its pending
+    /// transactions.
+    #[arg(long)]
+    nonce: Option<u128>,
+    /// Wait for the transaction to be forged, and print it.
+    #[arg(long)]
+    wait: bool,
+    /// Seconds to wait for the transaction to be forged.
+    #[arg(long, default_value_t = 600, requires = "wait")]
+    wait_timeout: u64,
+    /// Check and sign the transaction, and print it without posting it.
+    #[arg(long, conflicts_with = "wait")]
+    dry_run: bool,
+}
+
+pub async fn run(api: &HermezApi, wallet: Wallet, format: Format) -> Result<(), String> {
+    let path = wallet.keystore.unwrap_or_else(keystore::default_path);
+    match wallet.command {
+        WalletCommand::Create {
+            hez_ethereum_address,
+            force,
+        } => {
+            let key = PrivateKey::new(keystore::random()?);
+            save(&key, hez_ethereum_address, &path, force, format)
+        }
+        WalletCommand::Import {
+            hez_ethereum_address,
+            force,
+        } => {
+            let hex = match std::env::var("HERMEZ_PRIVATE_KEY") {
+                Ok(hex) => hex,
+                Err(_) => rpassword::prompt_password("BJJ private key: ")
+                    .map_err(|err| err.to_string())?,
+            };
+            let key = PrivateKey::from_hex(&hex).map_err(|err| err.to_string())?;
+            save(&key, hez_ethereum_address, &path, force, format)
+        }
+        WalletCommand::Balances => {
+            let keystore = Keystore::load(&path)?;
+            super::show(format, api.portfolio(&keystore.bjj).await)
+        }
+        WalletCommand::Transfer { to, send } => {
+            transaction(api, &path, Some(to), send, format).await
+        }
+        WalletCommand::Exit { send } => transaction(api, &path, None, send, format).await,
+        WalletCommand::Withdrawals {
+            hez_ethereum_address,
+        } => {
+            let address = match hez_ethereum_address {
+                Some(address) => address,
+                None => Keystore::load(&path)?.hez_ethereum_address.ok_or_else(|| {
+                    String::from(
+                        "The keystore has no Ethereum address, give --hez-ethereum-address",
+                    )
+                })?,
+            };
+            super::show(format, api.pending_withdrawals(&address).await)
+        }
+    }
+}
+
+fn save(
+    key: &PrivateKey,
+    hez_ethereum_address: Option<String>,
+    path: &Path,
+    force: bool,
+    format: Format,
+) -> Result<(), String> {
+    let keystore = Keystore::encrypt(key, hez_ethereum_address, &keystore::password(true)?)?;
+    keystore.save(path, force)?;
+    output::print(
+        format,
+        &serde_json::json!({
+            "bjj": keystore.bjj,
+            "hezEthereumAddress": keystore.hez_ethereum_address,
+            "keystore": path,
+        }),
+    )
+}
+
+// Signs a transfer to `to`, or an exit, from an account of the wallet, and
+// posts it unless it is a dry run.
+async fn transaction(
+    api: &HermezApi,
+    path: &Path,
+    to: Option<String>,
+    send: Send,
+    format: Format,
+) -> Result<(), String> {
+    let keystore = Keystore::load(path)?;
+    let account = api
+        .get_account(&send.from)
+        .await
+        .map_err(|err| err.to_string())?;
+    if account.bjj != keystore.bjj {
+        return Err(format!("{} is not an account of the wallet", send.from));
+    }
+    let token = &account.token;
+    let amount =
+        parse_formatted_amount(&send.amount, token.decimals).map_err(|err| err.to_string())?;
+
+    let mut options = api.transactions_pool_post_options();
+    options
+        .token_id(token.id)
+        .from_account_index(&send.from)
+        .amount(&amount.to_string());
+    let creates_account = match &to {
+        None => {
+            let exit_index = format!("hez:{}:1", token.symbol);
+            options
+                .r#type(TransactionTypeL2::Exit)
+                .to_account_index(&exit_index);
+            false
+        }
+        Some(to) if to.starts_with("hez:0x") => {
+            options
+                .r#type(TransactionTypeL2::TransferToEthAddr)
+                .to_hez_ethereum_address(to);
+            true
+        }
+        Some(to) if to.split(':').count() == 3 => {
+            options
+                .r#type(TransactionTypeL2::Transfer)
+                .to_account_index(to);
+            false
+        }
+        Some(to) => {
+            options
+                .r#type(TransactionTypeL2::TransferToBJJ)
+                .to_bjj(to)
+                .to_hez_ethereum_address(FF_ADDRESS);
+            true
+        }
+    };
+
+    let fee = match send.fee {
+        Some(fee) => fee,
+        None => {
+            let state = api.get_state().await.map_err(|err| err.to_string())?;
+            let recommended = if creates_account {
+                state.recommended_fee.create_account
+            } else {
+                state.recommended_fee.existing_account
+            };
+            let price = token
+                .usd
+                .ok_or_else(|| format!("No USD price for {}, give --fee", token.symbol))?;
+            fee_selector(amount, token.decimals, price, recommended)
+                .ok_or_else(|| format!("No fee covers the recommended {} USD", recommended))?
+        }
+    };
+    let fee_paid = fee_amount(amount, fee as u32).ok_or_else(|| {
+        format!(
+            "The fee of selector {} on {} {} overflows",
+            fee,
+            format_amount(amount, token.decimals),
+            token.symbol
+        )
+    })?;
+    let balance = parse_amount(&account.balance).map_err(|err| err.to_string())?;
+    if amount.saturating_add(fee_paid) > balance {
+        return Err(format!(
+            "Insufficient balance, {} {} for {} {} and a fee of {}",
+            format_amount(balance, token.decimals),
+            token.symbol,
+            format_amount(amount, token.decimals),
+            token.symbol,
+            format_amount(fee_paid, token.decimals)
+        ));
+    }
+
+    let nonce = match send.nonce {
+        Some(nonce) => nonce,
+        None => next_nonce(api, &send.from, account.nonce).await?,
+    };
+    if nonce < account.nonce {
+        return Err(format!(
+            "Nonce {} is already used, the account is at {}",
+            nonce, account.nonce
+        ));
+    }
+
+    let chain_id = api
+        .get_config()
+        .await
+        .map_err(|err| err.to_string())?
+        .chain_id;
+    let chain_id = u16::try_from(chain_id).map_err(|_| format!("Invalid chain id {}", chain_id))?;
+    let key = keystore.decrypt(&keystore::password(false)?)?;
+    options
+        .fee(fee)
+        .nonce(nonce)
+        .sign(&key, chain_id)
+        .map_err(|err| err.to_string())?;
+
+    if send.dry_run {
+        return output::print(format, options.body());
+    }
+    let id = options.fetch().await.map_err(|err| err.to_string())?;
+    if !send.wait {
+        return output::print(format, &id);
+    }
+    eprintln!("Sent {}, waiting for it to be forged", id);
+    wait(api, &id, Duration::from_secs(send.wait_timeout), format).await
+}
+
+// The lowest fee selector paying at least `recommended` USD.
+fn fee_selector(amount: u128, decimals: u32, price: f64, recommended: f64) -> Option<u16> {
+    (0..=255u16).find(|&selector| match fee_amount(amount, selector as u32) {
+        Some(fee) => fee as f64 / 10f64.powi(decimals as i32) * price >= recommended,
+        None => false,
+    })
+}
+
+// The nonce following the account and its transactions still in the pool.
+async fn next_nonce(api: &HermezApi, account_index: &str, nonce: u128) -> Result<u128, String> {
+    let pending_query = PoolQuery {
+        state: Some(PoolL2TransactionState::Pend),
+        from_account_index: Some(account_index.to_owned()),
+        ..PoolQuery::default()
+    };
+    let forging_query = PoolQuery {
+        state: Some(PoolL2TransactionState::Fing),
+        ..pending_query.clone()
+    };
+    let (pending, forging) =
+        futures::join!(api.run_all(&pending_query), api.run_all(&forging_query));
+    let pending = pending.map_err(|err| err.to_string())?;
+    let forging = forging.map_err(|err| err.to_string())?;
+    Ok(pending
+        .iter()
+        .chain(forging.iter())
+        .map(|transaction| transaction.nonce + 1)
+        .fold(nonce, u128::max))
+}
+
+// Polls the pool until the transaction is forged, then prints it from the
+// history. Gives up after `timeout`, with the last error seen if any.
+async fn wait(api: &HermezApi, id: &str, timeout: Duration, format: Format) -> Result<(), String> {
+    let deadline = Instant::now() + timeout;
+    let mut last_error = None;
+    while Instant::now() < deadline {
+        async_std::task::sleep(POLL_INTERVAL).await;
+        match api.get_transactions_pool(id).await {
+            Ok(transaction) if transaction.state == PoolL2TransactionState::Invl => {
+                return Err(format!(
+                    "Transaction {} is invalid: {}",
+                    id, transaction.info
+                ));
+            }
+            Ok(transaction) if transaction.state != PoolL2TransactionState::Fged => {
+                last_error = None;
+            }
+            // Forged, or already gone from the pool.
+            Ok(_) | Err(ErrorKind::Http(404, _)) => match api.get_transaction_history(id).await {
+                Ok(transaction) => return output::print(format, &transaction),
+                Err(err) => last_error = Some(err.to_string()),
+            },
+            Err(err) => last_error = Some(err.to_string()),
+        }
+    }
+    let mut message = format!(
+        "Transaction {} not forged after {} seconds",
+        id,
+        timeout.as_secs()
+    );
+    if let Some(err) = last_error {
+        message += &format!(", last error: {}", err);
+    }
+    Err(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::fee_selector;
+
+    #[test]
+    fn test_fee_selector() {
+        // 1 token of 18 decimals at 10 USD, for a 0.01 USD fee.
+        let selector = fee_selector(10u128.pow(18), 18, 10.0, 0.01).unwrap();
+        assert!(selector > 0);
+        assert!(fee_selector(10u128.pow(18), 18, 10.0, 1e30).is_none());
+        assert_eq!(Some(0), fee_selector(10u128.pow(18), 18, 10.0, 0.0));
+    }
+}
@@ -0,0 +1,195 @@
+use url::Url;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use super::cache::Cache;
+use super::http::{Http, HttpConfig};
+use super::rate_limit::RateLimit;
+use super::Endpoint;
+use super::ErrorKind;
+use super::HermezApi;
+
+/// Builds a `HermezApi` with custom client settings.
+///
+/// # Example
+///
+/// ```no_run
+/// use hermez_api::rate_limit::RateLimit;
+/// use hermez_api::{Endpoint, HermezApi};
+/// use std::time::Duration;
+///
+/// let api = HermezApi::builder("https://some.other.domain/hermez")
+///     .timeout(Duration::from_secs(10))
+///     .connect_timeout(Duration::from_secs(3))
+///     .user_agent("my-indexer/1.0")
+///     .header("Authorization", "Bearer xyz")
+///     .rate_limit(RateLimit::per_second(20))
+///     .endpoint_rate_limit(Endpoint::History, RateLimit::new(2.0, 10))
+///     .build()
+///     .unwrap();
+/// ```
+pub struct HermezApiBuilder {
+    base_api_url: String,
+    version: String,
+    proxy: Option<String>,
+    concurrency: usize,
+    config: HttpConfig,
+}
+
+impl HermezApiBuilder {
+    pub fn new(base_api_url: &str) -> Self {
+        Self {
+            base_api_url: base_api_url.to_owned(),
+            version: HermezApi::VERSION.to_owned(),
+            proxy: None,
+            concurrency: HermezApi::DEFAULT_CONCURRENCY,
+            config: Default::default(),
+        }
+    }
+
+    /// API version to use. Defaults to `HermezApi::VERSION`.
+    pub fn version(&mut self, version: &str) -> &mut Self {
+        self.version = version.to_owned();
+        self
+    }
+
+    /// Maximum time allowed for a whole request.
+    pub fn timeout(&mut self, timeout: Duration) -> &mut Self {
+        self.config.timeout = Some(timeout);
+        self
+    }
+
+    /// Maximum time allowed to establish a connection. Requires the curl-client transport.
+    pub fn connect_timeout(&mut self, connect_timeout: Duration) -> &mut Self {
+        self.config.connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// Value of the User-Agent header sent with every request.
+    pub fn user_agent(&mut self, user_agent: &str) -> &mut Self {
+        self.config.user_agent = Some(user_agent.to_owned());
+        self
+    }
+
+    /// Adds a header sent with every request.
+    pub fn header(&mut self, name: &str, value: &str) -> &mut Self {
+        self.config
+            .headers
+            .push((name.to_owned(), value.to_owned()));
+        self
+    }
+
+    /// HTTP proxy to send requests through. Requires the curl-client transport.
+    pub fn proxy(&mut self, proxy: &str) -> &mut Self {
+        self.proxy = Some(proxy.to_owned());
+        self
+    }
+
+    /// Limits the rate of all requests. Requests over the limit are queued.
+    /// `build()` fails unless the rate is a finite number above 0.
+    pub fn rate_limit(&mut self, rate_limit: RateLimit) -> &mut Self {
+        self.config.rate_limit = Some(rate_limit);
+        self
+    }
+
+    /// Limits the rate of requests to one endpoint. This applies on top of
+    /// the global limit set with `rate_limit()`. `build()` fails unless the
+    /// rate is a finite number above 0.
+    pub fn endpoint_rate_limit(&mut self, endpoint: Endpoint, rate_limit: RateLimit) -> &mut Self {
+        self.config
+            .endpoint_rate_limits
+            .insert(endpoint, rate_limit);
+        self
+    }
+
+    /// Caches responses to single object requests, see `Cache`.
+    pub fn cache(&mut self, cache: Cache) -> &mut Self {
+        self.config.cache = Some(cache);
+        self
+    }
+
+    /// Maximum number of requests in flight during bulk lookups such as
+    /// `HermezApi::get_accounts_many()`.
+    pub fn concurrency(&mut self, concurrency: usize) -> &mut Self {
+        self.concurrency = concurrency.max(1);
+        self
+    }
+
+    pub fn build(&self) -> Result<HermezApi, ErrorKind> {
+        let mut config = self.config.clone();
+        let rate_limits = self.config.rate_limit.iter();
+        for rate_limit in rate_limits.chain(self.config.endpoint_rate_limits.values()) {
+            if !(rate_limit.rate.is_finite() && rate_limit.rate > 0.0) {
+                return Err(ErrorKind::Config(format!(
+                    "Invalid rate limit of {} requests per second",
+                    rate_limit.rate
+                )));
+            }
+        }
+        if let Some(proxy) = &self.proxy {
+            config.proxy = Some(
+                Url::parse(proxy)
+                    .map_err(|err| ErrorKind::Config(format!("Invalid proxy URL: {}", err)))?,
+            );
+        }
+
+        HermezApi::from_parts(
+            &self.base_api_url,
+            &self.version,
+            Arc::new(Http::with_config(&config)?),
+            self.concurrency,
+        )
+        .map_err(|err| ErrorKind::Config(format!("Invalid base URL: {}", err)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HermezApiBuilder;
+    use crate::rate_limit::RateLimit;
+    use crate::Endpoint;
+    use std::time::Duration;
+
+    #[test]
+    fn test_builder() {
+        let api = HermezApiBuilder::new("https://api.testnet.hermez.io")
+            .version("v2")
+            .timeout(Duration::from_secs(10))
+            .user_agent("hermez-api-test")
+            .header("X-Api-Key", "abc")
+            .build()
+            .unwrap();
+
+        assert_eq!("https://api.testnet.hermez.io/v2/", api.inner.url.as_str());
+
+        assert!(HermezApiBuilder::new("xyz").build().is_err());
+        assert!(HermezApiBuilder::new("https://api.testnet.hermez.io")
+            .header("Bad Header", "abc")
+            .build()
+            .is_err());
+        assert!(HermezApiBuilder::new("https://api.testnet.hermez.io")
+            .proxy("not a url")
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn test_invalid_rate_limit() {
+        let url = "https://api.testnet.hermez.io";
+        assert!(HermezApiBuilder::new(url)
+            .rate_limit(RateLimit::per_second(5))
+            .build()
+            .is_ok());
+        assert!(HermezApiBuilder::new(url)
+            .rate_limit(RateLimit::per_second(0))
+            .build()
+            .is_err());
+        for rate in [-1.0, f64::NAN, f64::INFINITY] {
+            assert!(HermezApiBuilder::new(url)
+                .endpoint_rate_limit(Endpoint::Batches, RateLimit::new(rate, 1))
+                .build()
+                .is_err());
+        }
+    }
+}
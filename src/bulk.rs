@@ -0,0 +1,250 @@
+// Bulk lookups, run with bounded concurrency.
+
+use futures::stream::{self, StreamExt};
+
+use std::collections::HashMap;
+use std::ops::Range;
+
+use super::accounts::Account;
+use super::batches::Batch;
+use super::tokens::{Token, TokensQuery};
+use super::ErrorKind;
+use super::HermezApi;
+
+// Number of token ids requested at once with the ids filter.
+const TOKEN_IDS_PER_REQUEST: usize = 50;
+
+// The distinct ids of `token_ids`, sorted and split into requests.
+fn groups_of(token_ids: &[u32]) -> Vec<Vec<u32>> {
+    let mut unique_ids = token_ids.to_vec();
+    unique_ids.sort_unstable();
+    unique_ids.dedup();
+    unique_ids
+        .chunks(TOKEN_IDS_PER_REQUEST)
+        .map(|ids| ids.to_vec())
+        .collect()
+}
+
+// The token of each id of `token_ids`, in order, from the results of the
+// requests for `groups`. An id missing from its group's result is a 404.
+fn match_tokens(
+    token_ids: &[u32],
+    groups: &[Vec<u32>],
+    results: Vec<Result<Vec<Token>, ErrorKind>>,
+) -> Vec<Result<Token, ErrorKind>> {
+    let mut found = HashMap::new();
+    for (ids, result) in groups.iter().zip(results) {
+        match result {
+            Ok(tokens) => {
+                for token in tokens {
+                    found.insert(token.id, Ok(token));
+                }
+            }
+            Err(err) => {
+                for id in ids {
+                    found.insert(*id, Err(err.clone()));
+                }
+            }
+        }
+    }
+
+    token_ids
+        .iter()
+        .map(|id| {
+            found
+                .get(id)
+                .cloned()
+                .unwrap_or_else(|| Err(ErrorKind::Http(404, format!("Token {} not found", id))))
+        })
+        .collect()
+}
+
+impl HermezApi {
+    /// Get several accounts by their indices.
+    ///
+    /// Results are returned in the same order as `account_indices`.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use hermez_api::HermezApi;
+    ///
+    /// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+    /// let accounts = api.get_accounts_many(&["hez:ETH:256", "hez:HEZ:257"]).await;
+    ///
+    /// for account in accounts {
+    ///     println!("{:?}", account);
+    /// }
+    /// ```
+    pub async fn get_accounts_many(
+        &self,
+        account_indices: &[&str],
+    ) -> Vec<Result<Account, ErrorKind>> {
+        // Items are owned so the resulting future stays Send.
+        let account_indices: Vec<String> = account_indices.iter().map(|s| s.to_string()).collect();
+        stream::iter(account_indices)
+            .map(|account_index| {
+                let api = self.clone();
+                async move { api.get_account(&account_index).await }
+            })
+            .buffered(self.inner.concurrency)
+            .collect()
+            .await
+    }
+
+    /// Get every batch in a range of batch numbers.
+    ///
+    /// Results are returned in batch number order.
+    pub async fn get_batches_range(&self, batch_nums: Range<u32>) -> Vec<Result<Batch, ErrorKind>> {
+        stream::iter(batch_nums)
+            .map(|batch_num| {
+                let api = self.clone();
+                async move { api.get_batch(batch_num).await }
+            })
+            .buffered(self.inner.concurrency)
+            .collect()
+            .await
+    }
+
+    /// Get several tokens by their ids.
+    ///
+    /// The ids are requested in groups using the ids filter of the tokens
+    /// endpoint. Results are returned in the same order as `token_ids`.
+    pub async fn get_tokens_many(&self, token_ids: &[u32]) -> Vec<Result<Token, ErrorKind>> {
+        let groups = groups_of(token_ids);
+        let results = stream::iter(groups.clone())
+            .map(|ids| {
+                let api = self.clone();
+                async move { api.fetch_all_tokens(&ids).await }
+            })
+            .buffered(self.inner.concurrency)
+            .collect()
+            .await;
+        match_tokens(token_ids, &groups, results)
+    }
+
+    async fn fetch_all_tokens(&self, ids: &[u32]) -> Result<Vec<Token>, ErrorKind> {
+        let query = TokensQuery {
+            ids: Some(ids.to_vec()),
+            limit: Some(ids.len() as u64),
+            ..Default::default()
+        };
+        self.run_all(&query).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{groups_of, match_tokens};
+    use crate::test_support;
+    use crate::tokens::Token;
+    use crate::{ErrorKind, HermezApi};
+
+    fn token(id: u32) -> serde_json::Value {
+        let mut token = test_support::token();
+        token["id"] = serde_json::json!(id);
+        token
+    }
+
+    #[test]
+    fn test_groups_of() {
+        let mut ids: Vec<u32> = (0..120).rev().collect();
+        ids.extend(&[5, 7, 5]);
+        let groups = groups_of(&ids);
+        assert_eq!(
+            vec![50, 50, 20],
+            groups.iter().map(Vec::len).collect::<Vec<_>>()
+        );
+        assert_eq!((0..50).collect::<Vec<u32>>(), groups[0]);
+        assert_eq!(119, groups[2][19]);
+    }
+
+    #[test]
+    fn test_match_tokens() {
+        let tokens: Vec<Token> = [1, 3]
+            .iter()
+            .map(|&id| serde_json::from_value(token(id)).unwrap())
+            .collect();
+        let groups = vec![vec![1, 2, 3], vec![4]];
+        let results = vec![Ok(tokens), Err(ErrorKind::Http(500, String::new()))];
+
+        let matched = match_tokens(&[3, 1, 2, 4, 3], &groups, results);
+        let ids: Vec<Result<u32, String>> = matched
+            .into_iter()
+            .map(|token| {
+                token
+                    .map(|token| token.id)
+                    .map_err(|err| format!("{:?}", err))
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                Ok(3),
+                Ok(1),
+                Err(String::from("Http(404, \"Token 2 not found\")")),
+                Err(String::from("Http(500, \"\")")),
+                Ok(3),
+            ],
+            ids
+        );
+    }
+
+    #[test]
+    fn test_bulk() {
+        let api = HermezApi::new(&test_support::serve(|request| {
+            let path = request.path();
+            if let Some(account_index) = path.strip_prefix("accounts/") {
+                (200, test_support::account(account_index, "1", 0))
+            } else if let Some(batch_num) = path.strip_prefix("batches/") {
+                (200, test_support::batch(batch_num.parse().unwrap(), 0))
+            } else {
+                // Token 0 does not exist.
+                let tokens: Vec<serde_json::Value> = request
+                    .param("ids")
+                    .unwrap()
+                    .split(',')
+                    .map(|id| id.parse().unwrap())
+                    .filter(|&id| id != 0)
+                    .map(token)
+                    .collect();
+                (
+                    200,
+                    serde_json::json!({ "tokens": tokens, "pendingItems": 0 }),
+                )
+            }
+        }))
+        .unwrap();
+
+        async_std::task::block_on(async {
+            let indices = ["hez:ETH:258", "hez:ETH:256", "hez:ETH:258", "hez:ETH:257"];
+            let accounts: Vec<String> = api
+                .get_accounts_many(&indices)
+                .await
+                .into_iter()
+                .map(|account| account.unwrap().account_index)
+                .collect();
+            assert_eq!(indices.to_vec(), accounts);
+
+            let batch_nums: Vec<u32> = api
+                .get_batches_range(3..9)
+                .await
+                .into_iter()
+                .map(|batch| batch.unwrap().batch_num)
+                .collect();
+            assert_eq!((3..9).collect::<Vec<u32>>(), batch_nums);
+
+            let ids: Vec<u32> = (0..=60).rev().chain(vec![7, 7]).collect();
+            let tokens = api.get_tokens_many(&ids).await;
+            assert_eq!(ids.len(), tokens.len());
+            for (id, token) in ids.iter().zip(tokens) {
+                match token {
+                    Ok(token) => assert_eq!(*id, token.id),
+                    Err(err) => {
+                        assert_eq!(0, *id);
+                        assert!(matches!(err, ErrorKind::Http(404, _)));
+                    }
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,310 @@
+//! Response caching.
+//!
+//! When a `Cache` is given to `HermezApiBuilder::cache()`, responses to the
+//! single object requests `get_config()`, `get_token()`, `get_state()`,
+//! `get_batch()` and `get_full_batch()` are kept for the time to live set for
+//! their endpoint. List requests are never cached.
+
+use serde::{Deserialize, Serialize};
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use super::Endpoint;
+
+/// How long a response is kept.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Ttl {
+    Forever,
+    For(Duration),
+}
+
+/// A cached response body.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub body: String,
+    /// When the entry expires, never if `None`.
+    pub expires: Option<SystemTime>,
+}
+
+/// Storage for cached responses, keyed by request URL.
+pub trait CacheBackend: Send + Sync {
+    fn get(&self, key: &str) -> Option<CacheEntry>;
+
+    fn put(&self, key: &str, entry: CacheEntry);
+
+    fn remove(&self, key: &str);
+
+    fn keys(&self) -> Vec<String>;
+
+    fn clear(&self) {
+        for key in self.keys() {
+            self.remove(&key);
+        }
+    }
+}
+
+/// Keeps cached responses in memory.
+#[derive(Default)]
+pub struct MemoryBackend {
+    entries: Mutex<HashMap<String, CacheEntry>>,
+}
+
+impl CacheBackend for MemoryBackend {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        self.entries.lock().unwrap().insert(key.to_owned(), entry);
+    }
+
+    fn remove(&self, key: &str) {
+        self.entries.lock().unwrap().remove(key);
+    }
+
+    fn keys(&self) -> Vec<String> {
+        self.entries.lock().unwrap().keys().cloned().collect()
+    }
+
+    fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
+
+/// Keeps cached responses as files in a directory, so they survive restarts.
+///
+/// Failing to read or write a file is treated as a cache miss.
+pub struct DiskBackend {
+    dir: PathBuf,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DiskEntry {
+    key: String,
+    #[serde(flatten)]
+    entry: CacheEntry,
+}
+
+impl DiskBackend {
+    pub fn new<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir: dir.as_ref().to_owned(),
+        })
+    }
+
+    fn path(&self, key: &str) -> PathBuf {
+        let name: String = key
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || c == '.' || c == '-' {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        self.dir.join(name + ".json")
+    }
+
+    fn read(path: &Path) -> Option<DiskEntry> {
+        serde_json::from_slice(&fs::read(path).ok()?).ok()
+    }
+}
+
+impl CacheBackend for DiskBackend {
+    fn get(&self, key: &str) -> Option<CacheEntry> {
+        // Different keys can map to the same file name, so check the key.
+        Self::read(&self.path(key))
+            .filter(|stored| stored.key == key)
+            .map(|stored| stored.entry)
+    }
+
+    fn put(&self, key: &str, entry: CacheEntry) {
+        let stored = DiskEntry {
+            key: key.to_owned(),
+            entry,
+        };
+        if let Ok(data) = serde_json::to_vec(&stored) {
+            let _ = fs::write(self.path(key), data);
+        }
+    }
+
+    fn remove(&self, key: &str) {
+        let _ = fs::remove_file(self.path(key));
+    }
+
+    fn keys(&self) -> Vec<String> {
+        match fs::read_dir(&self.dir) {
+            Ok(dir) => dir
+                .filter_map(|entry| Self::read(&entry.ok()?.path()))
+                .map(|stored| stored.key)
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+}
+
+/// Cache settings: where responses are stored, and for how long for each endpoint.
+///
+/// By default configuration is kept forever, tokens for five minutes, state
+/// for five seconds and batches forever, since forged batches don't change.
+///
+/// # Example
+///
+/// ```no_run
+/// use hermez_api::cache::{Cache, Ttl};
+/// use hermez_api::{Endpoint, HermezApi};
+/// use std::time::Duration;
+///
+/// let mut cache = Cache::disk("/var/cache/hermez").unwrap();
+/// cache.ttl(Endpoint::State, Ttl::For(Duration::from_secs(2)));
+///
+/// let api = HermezApi::builder(HermezApi::TESTNET_URL)
+///     .cache(cache)
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct Cache {
+    backend: Arc<dyn CacheBackend>,
+    ttls: HashMap<Endpoint, Ttl>,
+}
+
+impl fmt::Debug for Cache {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Cache").field("ttls", &self.ttls).finish()
+    }
+}
+
+impl Cache {
+    pub fn new(backend: Arc<dyn CacheBackend>) -> Self {
+        let mut ttls = HashMap::new();
+        ttls.insert(Endpoint::Config, Ttl::Forever);
+        ttls.insert(Endpoint::Tokens, Ttl::For(Duration::from_secs(5 * 60)));
+        ttls.insert(Endpoint::State, Ttl::For(Duration::from_secs(5)));
+        ttls.insert(Endpoint::Batches, Ttl::Forever);
+        Self { backend, ttls }
+    }
+
+    /// A cache held in memory.
+    pub fn memory() -> Self {
+        Self::new(Arc::new(MemoryBackend::default()))
+    }
+
+    /// A cache held in files in `dir`.
+    pub fn disk<P: AsRef<Path>>(dir: P) -> io::Result<Self> {
+        Ok(Self::new(Arc::new(DiskBackend::new(dir)?)))
+    }
+
+    /// Sets how long responses from `endpoint` are kept.
+    pub fn ttl(&mut self, endpoint: Endpoint, ttl: Ttl) -> &mut Self {
+        self.ttls.insert(endpoint, ttl);
+        self
+    }
+
+    /// Stops responses from `endpoint` being cached.
+    pub fn disable(&mut self, endpoint: Endpoint) -> &mut Self {
+        self.ttls.remove(&endpoint);
+        self
+    }
+
+    pub(crate) fn is_cached(&self, endpoint: Endpoint) -> bool {
+        self.ttls.contains_key(&endpoint)
+    }
+
+    pub(crate) fn lookup(&self, key: &str) -> Option<String> {
+        let entry = self.backend.get(key)?;
+        match entry.expires {
+            Some(expires) if expires <= SystemTime::now() => {
+                self.backend.remove(key);
+                None
+            }
+            _ => Some(entry.body),
+        }
+    }
+
+    pub(crate) fn store(&self, endpoint: Endpoint, key: &str, body: &str) {
+        let expires = match self.ttls.get(&endpoint) {
+            Some(Ttl::Forever) => None,
+            Some(Ttl::For(ttl)) => Some(SystemTime::now() + *ttl),
+            None => return,
+        };
+        self.backend.put(
+            key,
+            CacheEntry {
+                body: body.to_owned(),
+                expires,
+            },
+        );
+    }
+
+    /// Removes the entries whose key matches `predicate`.
+    pub(crate) fn remove_if<F: Fn(&str) -> bool>(&self, predicate: F) {
+        for key in self.backend.keys() {
+            if predicate(&key) {
+                self.backend.remove(&key);
+            }
+        }
+    }
+
+    pub(crate) fn remove(&self, key: &str) {
+        self.backend.remove(key);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.backend.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Cache, Ttl};
+    use crate::Endpoint;
+    use std::time::Duration;
+
+    fn check(cache: &mut Cache) {
+        cache.ttl(Endpoint::State, Ttl::For(Duration::from_secs(0)));
+
+        cache.store(Endpoint::Config, "https://node/v1/config", "{}");
+        cache.store(Endpoint::Batches, "https://node/v1/batches/1", "{\"a\":1}");
+        cache.store(Endpoint::Batches, "https://node/v1/batches/2", "{\"a\":2}");
+        cache.store(Endpoint::State, "https://node/v1/state", "{}");
+        cache.store(Endpoint::Health, "https://node/v1/health", "{}");
+
+        assert_eq!(
+            Some("{}".to_owned()),
+            cache.lookup("https://node/v1/config")
+        );
+        assert_eq!(None, cache.lookup("https://node/v1/state"));
+        assert_eq!(None, cache.lookup("https://node/v1/health"));
+
+        cache.remove_if(|key| key.ends_with("/2"));
+        assert_eq!(
+            Some("{\"a\":1}".to_owned()),
+            cache.lookup("https://node/v1/batches/1")
+        );
+        assert_eq!(None, cache.lookup("https://node/v1/batches/2"));
+
+        cache.clear();
+        assert_eq!(None, cache.lookup("https://node/v1/config"));
+    }
+
+    #[test]
+    fn test_memory_cache() {
+        check(&mut Cache::memory());
+    }
+
+    #[test]
+    fn test_disk_cache() {
+        let dir = std::env::temp_dir().join(format!("hermez-api-cache-{}", std::process::id()));
+        check(&mut Cache::disk(&dir).unwrap());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
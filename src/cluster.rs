@@ -0,0 +1,370 @@
+//! Access to several nodes, routing requests to the healthiest one.
+
+use futures::future::join_all;
+use url::ParseError;
+
+use std::future::Future;
+use std::sync::RwLock;
+
+use super::account_creation_authorization::AccountCreationAuthorization;
+use super::accounts::{Account, AccountsGetOptions};
+use super::batches::{Batch, BatchesGetOptions, FullBatch};
+use super::bids::BidsGetOptions;
+use super::config::Config;
+use super::coordinators::CoordinatorsGetOptions;
+use super::exits::{Exit, ExitsGetOptions};
+use super::health::Health;
+use super::slots::{Slot, SlotsGetOptions};
+use super::state::State;
+use super::tokens::{Token, TokensGetOptions};
+use super::transactions_history::{HistoryTransaction, TransactionsHistoryGetOptions};
+use super::transactions_pool::{
+    PoolL2Transaction, TransactionsPoolGetOptions, TransactionsPoolPostOptions,
+};
+use super::{ErrorKind, HermezApi, Query};
+
+/// Health of a node, as seen by `HermezCluster::refresh()`.
+#[derive(Clone, Debug)]
+pub struct NodeStatus {
+    /// URL of the node's API.
+    pub url: String,
+    /// The node and both of its databases report being up.
+    pub healthy: bool,
+    /// Number of Ethereum blocks the node is behind.
+    pub sync_lag: Option<u128>,
+    /// Last batch known to the node.
+    pub last_batch: Option<u32>,
+    /// The error met while checking the node, if any.
+    pub error: Option<ErrorKind>,
+}
+
+/// Spreads requests over several nodes.
+///
+/// Requests go to the best ranked node. Healthy nodes rank first, ordered by
+/// how far they are behind Ethereum, then the others in the order given.
+/// When a node fails to answer, it is moved to the back and the request is
+/// retried on the next one. Call `refresh()` to rank the nodes again.
+///
+/// The `*_get_options()` builders are bound to the best ranked node at the
+/// time they are created. Use `run()` for queries with failover.
+///
+/// # Example
+///
+/// ```no_run
+/// use hermez_api::cluster::HermezCluster;
+/// use hermez_api::HermezApi;
+///
+/// let cluster = HermezCluster::new(&[
+///     "https://my.own.node",
+///     HermezApi::MAINNET_URL,
+/// ])
+/// .unwrap();
+///
+/// for status in cluster.refresh().await {
+///     println!("{} healthy: {}, lag: {:?}", status.url, status.healthy, status.sync_lag);
+/// }
+///
+/// let state = cluster.get_state().await.unwrap();
+/// ```
+pub struct HermezCluster {
+    nodes: Vec<HermezApi>,
+    ranking: RwLock<Vec<usize>>,
+}
+
+impl HermezCluster {
+    /// Creates a cluster from base URLs, such as `HermezApi::MAINNET_URL`.
+    pub fn new(base_api_urls: &[&str]) -> Result<Self, ParseError> {
+        let nodes = base_api_urls
+            .iter()
+            .map(|url| HermezApi::new(url))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self::from_nodes(nodes))
+    }
+
+    /// Creates a cluster from existing clients, for instance ones made with
+    /// `HermezApi::builder()`.
+    pub fn from_nodes(nodes: Vec<HermezApi>) -> Self {
+        let ranking = (0..nodes.len()).collect();
+        Self {
+            nodes,
+            ranking: RwLock::new(ranking),
+        }
+    }
+
+    /// All nodes, in the order given.
+    pub fn nodes(&self) -> &[HermezApi] {
+        &self.nodes
+    }
+
+    /// The best ranked node.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the cluster has no nodes.
+    pub fn best(&self) -> &HermezApi {
+        &self.nodes[self.ranking.read().unwrap()[0]]
+    }
+
+    /// Checks the health and sync state of every node, and ranks them again.
+    pub async fn refresh(&self) -> Vec<NodeStatus> {
+        let statuses = join_all(self.nodes.iter().map(|node| check(node.clone()))).await;
+        *self.ranking.write().unwrap() = rank(&statuses);
+        statuses
+    }
+
+    fn demote(&self, node: usize) {
+        let mut ranking = self.ranking.write().unwrap();
+        ranking.retain(|n| *n != node);
+        ranking.push(node);
+    }
+
+    /// Runs `request` on the best ranked node, moving on to the next one
+    /// each time a node fails.
+    pub async fn with_failover<T, F, Fut>(&self, request: F) -> Result<T, ErrorKind>
+    where
+        F: Fn(HermezApi) -> Fut,
+        Fut: Future<Output = Result<T, ErrorKind>>,
+    {
+        let ranking = self.ranking.read().unwrap().clone();
+        let mut last_error = ErrorKind::Api(String::from("Cluster: no nodes"));
+        for node in ranking {
+            match request(self.nodes[node].clone()).await {
+                Err(err) if is_node_failure(&err) => {
+                    self.demote(node);
+                    last_error = err;
+                }
+                result => return result,
+            }
+        }
+        Err(last_error)
+    }
+
+    //-----------------------------------------------------
+
+    pub async fn run<Q: Query>(&self, query: &Q) -> Result<Q::Output, ErrorKind> {
+        self.with_failover(|api| async move { api.run(query).await })
+            .await
+    }
+
+    pub async fn get_health(&self) -> Result<Health, ErrorKind> {
+        self.with_failover(|api| async move { api.get_health().await })
+            .await
+    }
+
+    pub fn bids_get_options(&self) -> BidsGetOptions {
+        self.best().bids_get_options()
+    }
+
+    pub async fn get_config(&self) -> Result<Config, ErrorKind> {
+        self.with_failover(|api| async move { api.get_config().await })
+            .await
+    }
+
+    pub async fn get_token(&self, token_id: u32) -> Result<Token, ErrorKind> {
+        self.with_failover(|api| async move { api.get_token(token_id).await })
+            .await
+    }
+
+    pub fn tokens_get_options(&self) -> TokensGetOptions {
+        self.best().tokens_get_options()
+    }
+
+    pub async fn get_account(&self, account_index: &str) -> Result<Account, ErrorKind> {
+        self.with_failover(|api| async move { api.get_account(account_index).await })
+            .await
+    }
+
+    pub fn accounts_get_options(&self) -> AccountsGetOptions {
+        self.best().accounts_get_options()
+    }
+
+    pub async fn get_exit(&self, batch_num: u32, account_index: &str) -> Result<Exit, ErrorKind> {
+        self.with_failover(|api| async move { api.get_exit(batch_num, account_index).await })
+            .await
+    }
+
+    pub fn exits_get_options(&self) -> ExitsGetOptions {
+        self.best().exits_get_options()
+    }
+
+    pub async fn get_transaction_history(&self, id: &str) -> Result<HistoryTransaction, ErrorKind> {
+        self.with_failover(|api| async move { api.get_transaction_history(id).await })
+            .await
+    }
+
+    pub fn transactions_history_get_options(&self) -> TransactionsHistoryGetOptions {
+        self.best().transactions_history_get_options()
+    }
+
+    pub async fn get_batch(&self, batch_num: u32) -> Result<Batch, ErrorKind> {
+        self.with_failover(|api| async move { api.get_batch(batch_num).await })
+            .await
+    }
+
+    pub async fn get_full_batch(&self, batch_num: u32) -> Result<FullBatch, ErrorKind> {
+        self.with_failover(|api| async move { api.get_full_batch(batch_num).await })
+            .await
+    }
+
+    pub fn batches_get_options(&self) -> BatchesGetOptions {
+        self.best().batches_get_options()
+    }
+
+    pub async fn get_slot(&self, slot_num: u32) -> Result<Slot, ErrorKind> {
+        self.with_failover(|api| async move { api.get_slot(slot_num).await })
+            .await
+    }
+
+    pub fn slots_get_options(&self) -> SlotsGetOptions {
+        self.best().slots_get_options()
+    }
+
+    pub async fn get_state(&self) -> Result<State, ErrorKind> {
+        self.with_failover(|api| async move { api.get_state().await })
+            .await
+    }
+
+    pub fn coordinators_get_options(&self) -> CoordinatorsGetOptions {
+        self.best().coordinators_get_options()
+    }
+
+    pub async fn get_account_creation_authorization(
+        &self,
+        hez_ethereum_address: &str,
+    ) -> Result<AccountCreationAuthorization, ErrorKind> {
+        self.with_failover(|api| async move {
+            api.get_account_creation_authorization(hez_ethereum_address)
+                .await
+        })
+        .await
+    }
+
+    pub async fn post_account_creation_authorization(
+        &self,
+        hez_ethereum_address: &str,
+        bjj: &str,
+        signature: &str,
+    ) -> Result<(), ErrorKind> {
+        // Not retried on another node, which would refuse an authorization
+        // the first one stored before failing.
+        self.best()
+            .post_account_creation_authorization(hez_ethereum_address, bjj, signature)
+            .await
+    }
+
+    pub async fn get_transactions_pool(&self, id: &str) -> Result<PoolL2Transaction, ErrorKind> {
+        self.with_failover(|api| async move { api.get_transactions_pool(id).await })
+            .await
+    }
+
+    pub fn transactions_pool_get_options(&self) -> TransactionsPoolGetOptions {
+        self.best().transactions_pool_get_options()
+    }
+
+    pub fn transactions_pool_post_options(&self) -> TransactionsPoolPostOptions {
+        self.best().transactions_pool_post_options()
+    }
+}
+
+// Errors that say something about the node rather than the request.
+fn is_node_failure(err: &ErrorKind) -> bool {
+    match err {
+        ErrorKind::Transport => true,
+        ErrorKind::Http(code, _) => *code >= 500 || *code == 429,
+        _ => false,
+    }
+}
+
+async fn check(node: HermezApi) -> NodeStatus {
+    let mut status = NodeStatus {
+        url: node.url().to_string(),
+        healthy: false,
+        sync_lag: None,
+        last_batch: None,
+        error: None,
+    };
+
+    match node.get_health().await {
+        Ok(health) => {
+            status.healthy = health.status == "UP"
+                && health.history_db.status == "UP"
+                && health.l2_db.status == "UP";
+        }
+        Err(err) => {
+            status.error = Some(err);
+            return status;
+        }
+    }
+
+    match node.get_state().await {
+        Ok(state) => {
+            let network = state.network;
+            status.sync_lag = Some(
+                network
+                    .last_ethereum_block
+                    .saturating_sub(network.last_synched_block),
+            );
+            status.last_batch = Some(network.last_batch.batch_num);
+        }
+        Err(err) => {
+            status.healthy = false;
+            status.error = Some(err);
+        }
+    }
+
+    status
+}
+
+// Healthy nodes first, least behind first, keeping the given order otherwise.
+fn rank(statuses: &[NodeStatus]) -> Vec<usize> {
+    let mut ranking: Vec<usize> = (0..statuses.len()).collect();
+    ranking.sort_by_key(|n| {
+        let status = &statuses[*n];
+        (!status.healthy, status.sync_lag.unwrap_or(u128::MAX))
+    });
+    ranking
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_node_failure, rank, NodeStatus};
+    use crate::ErrorKind;
+
+    fn status(healthy: bool, sync_lag: Option<u128>) -> NodeStatus {
+        NodeStatus {
+            url: String::new(),
+            healthy,
+            sync_lag,
+            last_batch: None,
+            error: if sync_lag.is_none() {
+                Some(ErrorKind::Transport)
+            } else {
+                None
+            },
+        }
+    }
+
+    #[test]
+    fn test_rank() {
+        let statuses = [
+            status(false, None),
+            status(true, Some(12)),
+            status(false, Some(0)),
+            status(true, Some(0)),
+            status(true, Some(12)),
+        ];
+        assert_eq!(vec![3, 1, 4, 2, 0], rank(&statuses));
+    }
+
+    #[test]
+    fn test_is_node_failure() {
+        assert!(is_node_failure(&ErrorKind::Transport));
+        assert!(is_node_failure(&ErrorKind::Http(503, String::new())));
+        assert!(is_node_failure(&ErrorKind::Http(429, String::new())));
+        assert!(!is_node_failure(&ErrorKind::Http(404, String::new())));
+        assert!(!is_node_failure(&ErrorKind::Api(String::from(
+            "Invalid filters"
+        ))));
+        assert!(!is_node_failure(&ErrorKind::Json(String::new())));
+    }
+}
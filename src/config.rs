@@ -1,6 +1,6 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
     pub hermez: Hermez,
@@ -9,7 +9,7 @@ pub struct Config {
     pub chain_id: u64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ConfigAuction {
     pub blocks_per_slot: u64,
@@ -21,7 +21,7 @@ pub struct ConfigAuction {
     pub governance_address: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Hermez {
     pub public_constants: Constants,
@@ -42,7 +42,7 @@ pub struct Hermez {
     pub exchange_multiplier: u64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Constants {
     #[serde(rename = "tokenHEZ")]
@@ -54,14 +54,14 @@ pub struct Constants {
     pub withdraw_delayer_contract: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Verifier {
     pub max_tx: u64,
     pub nlevels: u64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct ConfigWithdrawalDelayer {
     pub max_withdrawal_delay: u64,
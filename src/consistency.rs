@@ -0,0 +1,473 @@
+//! Checks whether several nodes agree with one another.
+//!
+//! The same objects are fetched from every node and compared field by field:
+//! the state and exit roots of batches, the balance and nonce of accounts,
+//! and the list of registered tokens. The outcome is a `ConsistencyReport`,
+//! which can be serialized, and which points out nodes that are stale or on
+//! a fork.
+
+use futures::future::join_all;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::ops::Range;
+
+use super::batches::Batch;
+use super::tokens::{Token, TokensQuery};
+use super::{Endpoint, ErrorKind, HermezApi};
+
+/// An object compared across nodes.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind")]
+pub enum ObjectId {
+    Batch {
+        batch_num: u32,
+    },
+    Account {
+        account_index: String,
+    },
+    Token {
+        token_id: u32,
+    },
+    /// The list of all tokens.
+    Tokens,
+}
+
+/// What a node returned for a field.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "status", content = "value")]
+pub enum NodeValue {
+    Value(String),
+    /// The node does not have the object.
+    Missing,
+    /// The request to the node failed, see `ConsistencyReport::errors`.
+    Failed,
+}
+
+/// A field on which the nodes disagree.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Difference {
+    pub object: ObjectId,
+    pub field: String,
+    /// The value returned by each node, in the order of `ConsistencyReport::nodes`.
+    pub values: Vec<NodeValue>,
+}
+
+/// A request that failed, so the node could not be compared.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct NodeError {
+    /// Index of the node in `ConsistencyReport::nodes`.
+    pub node: usize,
+    pub object: ObjectId,
+    pub error: String,
+}
+
+/// Outcome of a `ConsistencyCheck`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ConsistencyReport {
+    /// Base URLs of the nodes compared.
+    pub nodes: Vec<String>,
+    /// Number of objects compared.
+    pub objects: usize,
+    pub differences: Vec<Difference>,
+    pub errors: Vec<NodeError>,
+}
+
+impl ConsistencyReport {
+    /// All nodes answered, and agree on every object.
+    pub fn is_consistent(&self) -> bool {
+        self.differences.is_empty() && self.errors.is_empty()
+    }
+
+    /// Indices of the nodes that disagree with the majority on at least one
+    /// field. Nodes whose requests failed are not counted either way.
+    pub fn suspect_nodes(&self) -> Vec<usize> {
+        let mut suspects = BTreeSet::new();
+        for difference in &self.differences {
+            let answered = || {
+                difference
+                    .values
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, value)| **value != NodeValue::Failed)
+            };
+            // The most common value, the first seen winning ties.
+            let majority = answered().map(|(_, value)| value).max_by_key(|value| {
+                let count = answered().filter(|(_, v)| v == value).count();
+                let first = answered().position(|(_, v)| v == *value).unwrap();
+                (count, std::cmp::Reverse(first))
+            });
+            if let Some(majority) = majority {
+                suspects.extend(
+                    answered()
+                        .filter(|(_, value)| *value != majority)
+                        .map(|(node, _)| node),
+                );
+            }
+        }
+        suspects.into_iter().collect()
+    }
+}
+
+// Fields compared, by name, for an object fetched from one node.
+type Fields = Vec<(&'static str, String)>;
+
+enum Fetched {
+    Found(Fields),
+    Missing,
+    Failed,
+}
+
+/// Describes which objects to compare, and on which nodes.
+///
+/// # Example
+///
+/// ```no_run
+/// use hermez_api::consistency::ConsistencyCheck;
+/// use hermez_api::HermezApi;
+///
+/// let nodes = [
+///     HermezApi::new("https://my.own.node").unwrap(),
+///     HermezApi::new(HermezApi::MAINNET_URL).unwrap(),
+/// ];
+///
+/// let report = ConsistencyCheck::new(&nodes)
+///     .batches(1000..1100)
+///     .accounts(&["hez:ETH:256"])
+///     .tokens()
+///     .run()
+///     .await;
+///
+/// for node in report.suspect_nodes() {
+///     println!("{} disagrees with the others", report.nodes[node]);
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ConsistencyCheck {
+    nodes: Vec<HermezApi>,
+    batch_nums: Vec<u32>,
+    account_indices: Vec<String>,
+    tokens: bool,
+}
+
+impl ConsistencyCheck {
+    pub fn new(nodes: &[HermezApi]) -> Self {
+        Self {
+            nodes: nodes.to_vec(),
+            batch_nums: Vec::new(),
+            account_indices: Vec::new(),
+            tokens: false,
+        }
+    }
+
+    /// Compares the state and exit roots of the batches in a range.
+    pub fn batches(&mut self, batch_nums: Range<u32>) -> &mut Self {
+        self.batch_nums.extend(batch_nums);
+        self
+    }
+
+    /// Compares the balance and nonce of accounts.
+    pub fn accounts(&mut self, account_indices: &[&str]) -> &mut Self {
+        self.account_indices
+            .extend(account_indices.iter().map(|s| s.to_string()));
+        self
+    }
+
+    /// Compares the lists of tokens.
+    pub fn tokens(&mut self) -> &mut Self {
+        self.tokens = true;
+        self
+    }
+
+    pub async fn run(&self) -> ConsistencyReport {
+        let mut report = ConsistencyReport {
+            nodes: self
+                .nodes
+                .iter()
+                .map(|node| node.url().to_string())
+                .collect(),
+            ..Default::default()
+        };
+        let concurrency = self.nodes.first().map_or(1, |node| node.inner.concurrency);
+
+        let batches: Vec<_> = stream::iter(self.batch_nums.clone())
+            .map(|batch_num| {
+                let nodes = self.nodes.clone();
+                async move {
+                    let results = fetch(&nodes, |node| async move {
+                        // Not `get_batch()`: a cached batch would hide a node
+                        // that has since rolled back or forked.
+                        let url = node
+                            .inner
+                            .url
+                            .join(&format!("batches/{}", batch_num))
+                            .unwrap();
+                        let batch: Batch = node.inner.http.get(Endpoint::Batches, &url).await?;
+                        Ok(vec![
+                            ("state_root", batch.state_root),
+                            ("exit_root", batch.exit_root),
+                        ])
+                    })
+                    .await;
+                    (ObjectId::Batch { batch_num }, results)
+                }
+            })
+            .buffered(concurrency)
+            .collect()
+            .await;
+
+        let accounts: Vec<_> = stream::iter(self.account_indices.clone())
+            .map(|account_index| {
+                let nodes = self.nodes.clone();
+                async move {
+                    let results = fetch(&nodes, |node| {
+                        let account_index = account_index.clone();
+                        async move {
+                            let account = node.get_account(&account_index).await?;
+                            Ok(vec![
+                                ("balance", account.balance),
+                                ("nonce", account.nonce.to_string()),
+                            ])
+                        }
+                    })
+                    .await;
+                    (ObjectId::Account { account_index }, results)
+                }
+            })
+            .buffered(concurrency)
+            .collect()
+            .await;
+
+        for (object, results) in batches.into_iter().chain(accounts) {
+            report.compare(object, results);
+        }
+
+        if self.tokens {
+            self.compare_tokens(&mut report).await;
+        }
+
+        report
+    }
+
+    async fn compare_tokens(&self, report: &mut ConsistencyReport) {
+        let query = TokensQuery::default();
+        let lists = join_all(self.nodes.iter().map(|node| node.run_all(&query))).await;
+
+        let mut token_ids = BTreeSet::new();
+        let mut by_node = Vec::new();
+        for (node, list) in lists.into_iter().enumerate() {
+            match list {
+                Ok(tokens) => {
+                    let tokens: BTreeMap<u32, Token> =
+                        tokens.into_iter().map(|token| (token.id, token)).collect();
+                    token_ids.extend(tokens.keys().copied());
+                    by_node.push(Some(tokens));
+                }
+                Err(err) => {
+                    report.errors.push(NodeError {
+                        node,
+                        object: ObjectId::Tokens,
+                        error: err.to_string(),
+                    });
+                    by_node.push(None);
+                }
+            }
+        }
+
+        for token_id in token_ids {
+            let fetched = by_node
+                .iter()
+                .map(|tokens| match tokens {
+                    Some(tokens) => match tokens.get(&token_id) {
+                        Some(token) => Fetched::Found(vec![
+                            ("ethereum_address", token.ethereum_address.clone()),
+                            ("symbol", token.symbol.clone()),
+                            ("decimals", token.decimals.to_string()),
+                        ]),
+                        None => Fetched::Missing,
+                    },
+                    None => Fetched::Failed,
+                })
+                .collect();
+            report.diff(ObjectId::Token { token_id }, fetched);
+        }
+    }
+}
+
+impl ConsistencyReport {
+    fn compare(&mut self, object: ObjectId, results: Vec<Result<Option<Fields>, ErrorKind>>) {
+        let mut fetched = Vec::new();
+        for (node, result) in results.into_iter().enumerate() {
+            fetched.push(match result {
+                Ok(Some(fields)) => Fetched::Found(fields),
+                Ok(None) => Fetched::Missing,
+                Err(err) => {
+                    self.errors.push(NodeError {
+                        node,
+                        object: object.clone(),
+                        error: err.to_string(),
+                    });
+                    Fetched::Failed
+                }
+            });
+        }
+        self.diff(object, fetched);
+    }
+
+    fn diff(&mut self, object: ObjectId, fetched: Vec<Fetched>) {
+        self.objects += 1;
+
+        let mut names: Vec<&'static str> = Vec::new();
+        for fields in &fetched {
+            if let Fetched::Found(fields) = fields {
+                for (name, _) in fields {
+                    if !names.contains(name) {
+                        names.push(name);
+                    }
+                }
+            }
+        }
+
+        for name in names {
+            let values: Vec<NodeValue> = fetched
+                .iter()
+                .map(|fields| match fields {
+                    Fetched::Found(fields) => fields
+                        .iter()
+                        .find(|(n, _)| *n == name)
+                        .map_or(NodeValue::Missing, |(_, value)| {
+                            NodeValue::Value(value.clone())
+                        }),
+                    Fetched::Missing => NodeValue::Missing,
+                    Fetched::Failed => NodeValue::Failed,
+                })
+                .collect();
+
+            let mut answered = values.iter().filter(|value| **value != NodeValue::Failed);
+            let first = answered.next();
+            if answered.any(|value| Some(value) != first) {
+                self.differences.push(Difference {
+                    object: object.clone(),
+                    field: name.to_owned(),
+                    values,
+                });
+            }
+        }
+    }
+}
+
+// Runs `request` on every node. Objects the node doesn't have come back as `None`.
+async fn fetch<T, F, Fut>(nodes: &[HermezApi], request: F) -> Vec<Result<Option<T>, ErrorKind>>
+where
+    F: Fn(HermezApi) -> Fut,
+    Fut: Future<Output = Result<T, ErrorKind>>,
+{
+    join_all(nodes.iter().map(|node| {
+        let response = request(node.clone());
+        async move {
+            match response.await {
+                Ok(object) => Ok(Some(object)),
+                Err(ErrorKind::Http(404, _)) => Ok(None),
+                Err(err) => Err(err),
+            }
+        }
+    }))
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ConsistencyCheck, ConsistencyReport, Fetched, NodeValue, ObjectId};
+    use crate::cache::Cache;
+    use crate::test_support;
+    use crate::{ErrorKind, HermezApi, HermezApiBuilder};
+    use std::sync::{Arc, Mutex};
+
+    fn batch(state_root: &str) -> Fetched {
+        Fetched::Found(vec![
+            ("state_root", state_root.to_owned()),
+            ("exit_root", String::from("0")),
+        ])
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut report = ConsistencyReport::default();
+        report.diff(
+            ObjectId::Batch { batch_num: 1 },
+            vec![batch("12"), batch("12"), batch("12")],
+        );
+        assert!(report.is_consistent());
+
+        report.diff(
+            ObjectId::Batch { batch_num: 2 },
+            vec![batch("13"), batch("14"), Fetched::Missing, batch("13")],
+        );
+        report.compare(
+            ObjectId::Batch { batch_num: 3 },
+            vec![
+                Ok(Some(vec![("state_root", String::from("15"))])),
+                Err(ErrorKind::Transport),
+                Ok(None),
+                Ok(Some(vec![("state_root", String::from("15"))])),
+            ],
+        );
+
+        assert_eq!(3, report.objects);
+        assert_eq!(3, report.differences.len());
+        assert_eq!("state_root", report.differences[0].field);
+        assert_eq!(
+            vec![
+                NodeValue::Value(String::from("13")),
+                NodeValue::Value(String::from("14")),
+                NodeValue::Missing,
+                NodeValue::Value(String::from("13")),
+            ],
+            report.differences[0].values
+        );
+        assert_eq!(NodeValue::Failed, report.differences[2].values[1]);
+        assert_eq!(1, report.errors.len());
+        assert_eq!(vec![1, 2], report.suspect_nodes());
+
+        let json = serde_json::to_string(&report.differences[1]).unwrap();
+        assert_eq!(
+            r#"{"object":{"kind":"Batch","batch_num":2},"field":"exit_root","values":[{"status":"Value","value":"0"},{"status":"Value","value":"0"},{"status":"Missing"},{"status":"Value","value":"0"}]}"#,
+            json
+        );
+    }
+
+    #[test]
+    fn test_bypasses_cache() {
+        let state_root = Arc::new(Mutex::new(String::from("1")));
+        let url = test_support::serve({
+            let state_root = state_root.clone();
+            move |request| {
+                assert_eq!("batches/1", request.path());
+                let mut batch = test_support::batch(1, 0);
+                batch["stateRoot"] = serde_json::json!(*state_root.lock().unwrap());
+                (200, batch)
+            }
+        });
+        let cached = HermezApiBuilder::new(&url)
+            .cache(Cache::memory())
+            .build()
+            .unwrap();
+        let uncached = HermezApi::new(&url).unwrap();
+
+        async_std::task::block_on(async {
+            assert_eq!("1", cached.get_batch(1).await.unwrap().state_root);
+            // The node rolled back and forged batch 1 again.
+            *state_root.lock().unwrap() = String::from("2");
+            assert_eq!("1", cached.get_batch(1).await.unwrap().state_root);
+
+            let report = ConsistencyCheck::new(&[cached, uncached])
+                .batches(1..2)
+                .run()
+                .await;
+            assert_eq!(1, report.objects);
+            assert!(report.is_consistent());
+        });
+    }
+}
@@ -1,65 +1,83 @@
-use super::http::Http;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
+use super::Endpoint;
 use super::ErrorKind;
+use super::HermezApi;
 use super::PaginationOrder;
+use super::Query;
 
-pub struct CoordinatorsGetOptions<'a> {
-    http: &'a Http,
-    url: &'a Url,
+/// Owned description of a request to the coordinators endpoint.
+///
+/// Can be cloned, stored and serialized, and then run with `HermezApi::run()`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CoordinatorsQuery {
+    pub forger_addr: Option<String>,
+    pub bidder_addr: Option<String>,
 
-    forger_addr: Option<&'a str>,
-    bidder_addr: Option<&'a str>,
+    pub from_item: Option<u64>,
+    pub order: Option<PaginationOrder>,
+    pub limit: Option<u64>,
+}
+
+impl Query for CoordinatorsQuery {
+    type Output = Coordinators;
+    const ENDPOINT: Endpoint = Endpoint::Coordinators;
 
-    from_item: Option<u64>,
-    order: Option<PaginationOrder>,
-    limit: Option<u64>,
+    fn url(&self, base: &Url) -> Result<Url, ErrorKind> {
+        let mut url = base.join("coordinators").unwrap();
+        {
+            let mut query_pairs = url.query_pairs_mut();
+
+            fetch_stmt!(self, query_pairs, forger_addr, "forgerAddr", &str);
+            fetch_stmt!(self, query_pairs, bidder_addr, "bidderAddr", &str);
+
+            pagination_fetch_stmts!(self, query_pairs);
+        }
+        Ok(url)
+    }
 }
 
-impl<'a> CoordinatorsGetOptions<'a> {
-    pub fn new(http: &'a Http, url: &'a Url) -> Self {
-        Self {
-            http,
-            url,
+paged_query!(CoordinatorsQuery, Coordinator, coordinators);
 
-            forger_addr: None,
-            bidder_addr: None,
+pub struct CoordinatorsGetOptions {
+    api: HermezApi,
+    query: CoordinatorsQuery,
+}
 
-            from_item: None,
-            order: None,
-            limit: None,
+impl CoordinatorsGetOptions {
+    pub fn new(api: &HermezApi) -> Self {
+        Self {
+            api: api.clone(),
+            query: Default::default(),
         }
     }
 
-    setter!(forger_addr, &'a str);
-    setter!(bidder_addr, &'a str);
+    setter!(forger_addr, &str);
+    setter!(bidder_addr, &str);
 
     pagination_setters!();
 
-    pub async fn fetch(&self) -> Result<(Vec<Coordinator>, u64), ErrorKind> {
-        let mut url = self.url.join("coordinators").unwrap();
-        {
-            let mut query_pairs = url.query_pairs_mut();
-
-            fetch_stmt!(self, query_pairs, forger_addr, "forgerAddr", &str);
-            fetch_stmt!(self, query_pairs, bidder_addr, "bidderAddr", &str);
+    /// The query built so far.
+    pub fn query(&self) -> &CoordinatorsQuery {
+        &self.query
+    }
 
-            pagination_fetch_stmts!(self, query_pairs);
-        }
-        let coordinators: Coordinators = self.http.get(&url).await?;
+    pub async fn fetch(&self) -> Result<(Vec<Coordinator>, u64), ErrorKind> {
+        let coordinators = self.api.run(&self.query).await?;
         Ok((coordinators.coordinators, coordinators.pending_items))
     }
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct Coordinators {
+pub struct Coordinators {
     pub pending_items: u64,
     pub coordinators: Vec<Coordinator>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Coordinator {
     pub item_id: u64,
@@ -1,54 +1,37 @@
-use super::http::Http;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
+use super::Endpoint;
 use super::ErrorKind;
+use super::HermezApi;
 use super::PaginationOrder;
+use super::Query;
 
 use super::tokens::Token;
 
-pub struct ExitsGetOptions<'a> {
-    http: &'a Http,
-    url: &'a Url,
-    token_id: Option<u32>,
-    hez_ethereum_address: Option<&'a str>,
-    bjj: Option<&'a str>,
-    account_index: Option<&'a str>,
-    batch_num: Option<u32>,
-    only_pending_withdraws: Option<bool>,
-
-    from_item: Option<u64>,
-    order: Option<PaginationOrder>,
-    limit: Option<u64>,
-}
-
-impl<'a> ExitsGetOptions<'a> {
-    pub fn new(http: &'a Http, url: &'a Url) -> Self {
-        Self {
-            http,
-            url,
-            token_id: None,
-            hez_ethereum_address: None,
-            bjj: None,
-            account_index: None,
-            batch_num: None,
-            only_pending_withdraws: None,
-            from_item: None,
-            order: None,
-            limit: None,
-        }
-    }
+/// Owned description of a request to the exits endpoint.
+///
+/// Can be cloned, stored and serialized, and then run with `HermezApi::run()`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExitsQuery {
+    pub token_id: Option<u32>,
+    pub hez_ethereum_address: Option<String>,
+    pub bjj: Option<String>,
+    pub account_index: Option<String>,
+    pub batch_num: Option<u32>,
+    pub only_pending_withdraws: Option<bool>,
 
-    setter!(token_id, u32);
-    setter!(hez_ethereum_address, &'a str);
-    setter!(bjj, &'a str);
-    setter!(account_index, &'a str);
-    setter!(batch_num, u32);
-    setter!(only_pending_withdraws, bool);
+    pub from_item: Option<u64>,
+    pub order: Option<PaginationOrder>,
+    pub limit: Option<u64>,
+}
 
-    pagination_setters!();
+impl Query for ExitsQuery {
+    type Output = Exits;
+    const ENDPOINT: Endpoint = Endpoint::Exits;
 
-    pub async fn fetch(&self) -> Result<(Vec<Exit>, u64), super::ErrorKind> {
+    fn url(&self, base: &Url) -> Result<Url, ErrorKind> {
         if is_more_than_one_defined!(self.hez_ethereum_address, self.bjj, self.account_index) {
             return Err(ErrorKind::Api(String::from(
                 "Get Exits: bjj, hez_ethereum address and account_index are incompatible with each other",
@@ -61,7 +44,7 @@ impl<'a> ExitsGetOptions<'a> {
             )));
         }
 
-        let mut url = self.url.join("exits").unwrap();
+        let mut url = base.join("exits").unwrap();
         {
             let mut query_pairs = url.query_pairs_mut();
 
@@ -84,19 +67,53 @@ impl<'a> ExitsGetOptions<'a> {
 
             pagination_fetch_stmts!(self, query_pairs);
         }
-        let exits: Exits = self.http.get(&url).await?;
+        Ok(url)
+    }
+}
+
+paged_query!(ExitsQuery, Exit, exits);
+
+pub struct ExitsGetOptions {
+    api: HermezApi,
+    query: ExitsQuery,
+}
+
+impl ExitsGetOptions {
+    pub fn new(api: &HermezApi) -> Self {
+        Self {
+            api: api.clone(),
+            query: Default::default(),
+        }
+    }
+
+    setter!(token_id, u32);
+    setter!(hez_ethereum_address, &str);
+    setter!(bjj, &str);
+    setter!(account_index, &str);
+    setter!(batch_num, u32);
+    setter!(only_pending_withdraws, bool);
+
+    pagination_setters!();
+
+    /// The query built so far.
+    pub fn query(&self) -> &ExitsQuery {
+        &self.query
+    }
+
+    pub async fn fetch(&self) -> Result<(Vec<Exit>, u64), super::ErrorKind> {
+        let exits = self.api.run(&self.query).await?;
         Ok((exits.exits, exits.pending_items))
     }
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct Exits {
+pub struct Exits {
     pub pending_items: u64,
     pub exits: Vec<Exit>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Exit {
     pub batch_num: u32,
@@ -112,7 +129,7 @@ pub struct Exit {
     pub token: Token,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct MerkleProof {
     pub root: String,          // BigInt
@@ -124,3 +141,41 @@ pub struct MerkleProof {
     pub value: String, // BigInt
     pub fnc: u8,
 }
+
+/// Where an exit stands in the withdrawal process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExitStatus {
+    /// Not withdrawn from the rollup yet.
+    Pending,
+    InstantlyWithdrawn,
+    /// Sent to the withdrawal delayer, whose delay is not over yet.
+    DelayedRequested,
+    /// Sent to the withdrawal delayer, and can be taken out of it from `at_block`.
+    DelayedWithdrawable {
+        at_block: u128,
+    },
+    /// Taken out of the withdrawal delayer.
+    DelayedWithdrawn,
+}
+
+impl Exit {
+    /// The status of the exit at `block`, given the delay of the withdrawal
+    /// delayer in blocks.
+    pub fn status(&self, delay_blocks: u128, block: u128) -> ExitStatus {
+        if self.delayed_withdraw.is_some() {
+            return ExitStatus::DelayedWithdrawn;
+        }
+        if let Some(request_block) = self.delayed_withdraw_request {
+            let at_block = request_block + delay_blocks;
+            return if block >= at_block {
+                ExitStatus::DelayedWithdrawable { at_block }
+            } else {
+                ExitStatus::DelayedRequested
+            };
+        }
+        if self.instant_withdraw.is_some() {
+            return ExitStatus::InstantlyWithdrawn;
+        }
+        ExitStatus::Pending
+    }
+}
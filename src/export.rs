@@ -0,0 +1,461 @@
+//! Statements of the transactions of an address, for accounting.
+//!
+//! A statement has one row per forged transaction involving the owner, with
+//! amounts in units of the token. It can be written as CSV, as JSON Lines, or
+//! as a double-entry journal in the plain text format of ledger and hledger.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use std::fmt::Write;
+use std::ops::Range;
+
+use super::transactions_history::{HistoryQuery, HistoryTransaction, TransactionType};
+use super::transactions_pool::fee_amount;
+use super::{
+    format_amount, is_owner, parse_amount, parse_timestamp, ErrorKind, HermezApi, PagedQuery,
+    PaginationOrder,
+};
+
+/// Direction of a transaction, relative to the owner of the statement.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Direction {
+    /// Funds received, or deposited from Ethereum.
+    In,
+    /// Funds sent, or exited to Ethereum.
+    Out,
+    /// Funds moved between accounts of the owner.
+    Internal,
+}
+
+/// One transaction of a statement. Amounts are in units of the token, like
+/// "1.5".
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct StatementRow {
+    pub timestamp: DateTime<Utc>,
+    pub r#type: TransactionType,
+    pub direction: Direction,
+    /// Symbol of the token.
+    pub token: String,
+    pub amount: String,
+    /// Amount deposited from Ethereum by an L1 transaction, if it succeeded.
+    pub deposit_amount: Option<String>,
+    /// Fee paid by the sender of an L2 transaction.
+    pub fee: Option<String>,
+    pub historic_usd: Option<f64>,
+    pub historic_fee_usd: Option<f64>,
+    pub batch_num: Option<u32>,
+    pub tx_id: String,
+}
+
+// The names of the fields of `StatementRow`, as serialized.
+const CSV_HEADER: [&str; 11] = [
+    "timestamp",
+    "type",
+    "direction",
+    "token",
+    "amount",
+    "deposit_amount",
+    "fee",
+    "historic_usd",
+    "historic_fee_usd",
+    "batch_num",
+    "tx_id",
+];
+
+/// The transactions of an address over a period of time, oldest first.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Statement {
+    /// Hermez Ethereum address or BJJ key the statement is for.
+    pub owner: String,
+    pub rows: Vec<StatementRow>,
+}
+
+impl Statement {
+    /// Builds the statement of `owner` from its transactions. Transactions
+    /// not forged yet are left out.
+    pub fn new(owner: &str, transactions: &[HistoryTransaction]) -> Result<Self, ErrorKind> {
+        let mut rows = Vec::new();
+        for tx in transactions.iter().filter(|tx| tx.batch_num.is_some()) {
+            rows.push(row(owner, tx)?);
+        }
+        rows.sort_by_key(|row| row.timestamp);
+        Ok(Self {
+            owner: owner.to_owned(),
+            rows,
+        })
+    }
+
+    /// The rows as CSV, with a header line.
+    pub fn to_csv(&self) -> String {
+        let mut writer = csv::WriterBuilder::new()
+            .has_headers(false)
+            .from_writer(Vec::new());
+        // Written here rather than from the first row, so that an empty
+        // statement still has its header.
+        writer.write_record(CSV_HEADER).unwrap();
+        for row in &self.rows {
+            writer.serialize(row).unwrap();
+        }
+        String::from_utf8(writer.into_inner().unwrap()).unwrap()
+    }
+
+    /// The rows as JSON objects, one per line.
+    pub fn to_json_lines(&self) -> String {
+        self.rows
+            .iter()
+            .map(|row| serde_json::to_string(row).unwrap() + "\n")
+            .collect()
+    }
+
+    /// The rows as a double-entry journal, one entry per transaction.
+    ///
+    /// The funds of the owner on the rollup are kept in `Assets:Hermez`, and
+    /// on Ethereum in `Assets:Ethereum`. Transfers with others are balanced
+    /// by `Income:Hermez:Transfers` and `Expenses:Hermez:Transfers`, and the
+    /// fees paid by `Expenses:Hermez:Fees`. Transfers between accounts of the
+    /// owner only show their fee.
+    pub fn to_ledger(&self) -> String {
+        let mut journal = String::new();
+        for row in &self.rows {
+            let postings = postings(row);
+            if postings.is_empty() {
+                continue;
+            }
+            let commodity = commodity(&row.token);
+
+            writeln!(
+                journal,
+                "{} * {:?} {}",
+                row.timestamp.format("%Y/%m/%d"),
+                row.r#type,
+                row.tx_id
+            )
+            .unwrap();
+            if let Some(batch_num) = row.batch_num {
+                writeln!(journal, "    ; batch: {}", batch_num).unwrap();
+            }
+            if let Some(usd) = row.historic_usd {
+                writeln!(journal, "    ; usd: {}", usd).unwrap();
+            }
+            if let Some(fee_usd) = row.historic_fee_usd {
+                writeln!(journal, "    ; fee_usd: {}", fee_usd).unwrap();
+            }
+            for (account, amount) in postings {
+                writeln!(journal, "    {:<28}  {} {}", account, amount, commodity).unwrap();
+            }
+            journal.push('\n');
+        }
+        journal
+    }
+}
+
+/// Gets the transactions of `owner`, a Hermez Ethereum address or a BJJ
+/// key, forged within a period of time.
+///
+/// # Example
+///
+/// ```no_run
+/// use chrono::{TimeZone, Utc};
+/// use hermez_api::{export, HermezApi};
+///
+/// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+/// let owner = "hez:0xaa942cfcd25ad4d90a62358b0dd84f33b398262a";
+/// let range = Utc.with_ymd_and_hms(2021, 1, 1, 0, 0, 0).unwrap()
+///     ..Utc.with_ymd_and_hms(2022, 1, 1, 0, 0, 0).unwrap();
+/// let statement = export::history_statement(&api, owner, range).await.unwrap();
+///
+/// print!("{}", statement.to_ledger());
+/// ```
+pub async fn history_statement(
+    api: &HermezApi,
+    owner: &str,
+    range: Range<DateTime<Utc>>,
+) -> Result<Statement, ErrorKind> {
+    let mut query = HistoryQuery {
+        order: Some(PaginationOrder::Desc),
+        ..Default::default()
+    };
+    if owner.starts_with("hez:0x") {
+        query.hez_ethereum_address = Some(owner.to_owned());
+    } else {
+        query.bjj = Some(owner.to_owned());
+    }
+
+    // Newest first, so that paging stops at the first transaction older than
+    // the range rather than reading the whole history.
+    let mut transactions = Vec::new();
+    'pages: loop {
+        let (page, pending_items) = HistoryQuery::into_items(api.run(&query).await?);
+        let next_item = page
+            .last()
+            .and_then(|tx| HistoryQuery::item_id(tx).checked_sub(1));
+        for tx in page {
+            match parse_timestamp(&tx.timestamp) {
+                Ok(timestamp) if timestamp < range.start => break 'pages,
+                Ok(timestamp) if timestamp >= range.end => continue,
+                _ => transactions.push(tx),
+            }
+        }
+        match next_item {
+            Some(next_item) if pending_items > 0 => query.set_from_item(next_item),
+            _ => break,
+        }
+    }
+    Statement::new(owner, &transactions)
+}
+
+/// The transactions of `owner` within a period of time, as CSV.
+pub async fn history_csv(
+    api: &HermezApi,
+    owner: &str,
+    range: Range<DateTime<Utc>>,
+) -> Result<String, ErrorKind> {
+    Ok(history_statement(api, owner, range).await?.to_csv())
+}
+
+/// The transactions of `owner` within a period of time, as JSON Lines.
+pub async fn history_json_lines(
+    api: &HermezApi,
+    owner: &str,
+    range: Range<DateTime<Utc>>,
+) -> Result<String, ErrorKind> {
+    Ok(history_statement(api, owner, range).await?.to_json_lines())
+}
+
+/// The transactions of `owner` within a period of time, as a double-entry
+/// journal.
+pub async fn history_ledger(
+    api: &HermezApi,
+    owner: &str,
+    range: Range<DateTime<Utc>>,
+) -> Result<String, ErrorKind> {
+    Ok(history_statement(api, owner, range).await?.to_ledger())
+}
+
+fn row(owner: &str, tx: &HistoryTransaction) -> Result<StatementRow, ErrorKind> {
+    let from_owner = is_owner(owner, &tx.from_hez_ethereum_address, &tx.from_bjj);
+    let to_owner = is_owner(owner, &tx.to_hez_ethereum_address, &tx.to_bjj);
+    let direction = match tx.r#type {
+        TransactionType::Deposit | TransactionType::CreateAccountDeposit => Direction::In,
+        TransactionType::Exit | TransactionType::ForceExit => Direction::Out,
+        _ if from_owner && to_owner => Direction::Internal,
+        _ if from_owner => Direction::Out,
+        _ => Direction::In,
+    };
+
+    let decimals = tx.token.decimals;
+    let amount = parse_amount(&tx.amount)?;
+    let deposit_amount = match &tx.l1_info {
+        Some(info) if info.deposit_amount_success => {
+            Some(format_amount(parse_amount(&info.deposit_amount)?, decimals))
+        }
+        _ => None,
+    };
+    let fee = match &tx.l2_info {
+        Some(info) => Some(fee_amount(amount, info.fee).ok_or_else(|| {
+            ErrorKind::Api(format!("Fee {} of {} overflows", info.fee, tx.amount))
+        })?),
+        None => None,
+    };
+
+    Ok(StatementRow {
+        timestamp: parse_timestamp(&tx.timestamp)?,
+        r#type: tx.r#type,
+        direction,
+        token: tx.token.symbol.clone(),
+        amount: format_amount(amount, decimals),
+        deposit_amount,
+        fee: fee.map(|fee| format_amount(fee, decimals)),
+        historic_usd: tx.historic_usd,
+        historic_fee_usd: tx.l2_info.as_ref().and_then(|info| info.historic_fee_usd),
+        batch_num: tx.batch_num,
+        tx_id: tx.id.clone(),
+    })
+}
+
+// Postings of the entry for a row, as accounts and signed amounts. Deposits
+// are only counted for the owner's own L1 transactions, which are the ones
+// with a deposit amount and not received from someone else.
+fn postings(row: &StatementRow) -> Vec<(&'static str, String)> {
+    let mut postings = Vec::new();
+    let mut post = |debit: &'static str, credit: &'static str, amount: &str| {
+        if amount != "0" {
+            postings.push((debit, amount.to_owned()));
+            postings.push((credit, format!("-{}", amount)));
+        }
+    };
+
+    let deposited = row.direction != Direction::In
+        || matches!(
+            row.r#type,
+            TransactionType::Deposit | TransactionType::CreateAccountDeposit
+        );
+    if let (Some(deposit_amount), true) = (&row.deposit_amount, deposited) {
+        post("Assets:Hermez", "Assets:Ethereum", deposit_amount);
+    }
+    match (row.direction, row.r#type) {
+        (Direction::Out, TransactionType::Exit | TransactionType::ForceExit) => {
+            post("Assets:Ethereum", "Assets:Hermez", &row.amount)
+        }
+        (Direction::Out, _) => post("Expenses:Hermez:Transfers", "Assets:Hermez", &row.amount),
+        (Direction::In, _) => post("Assets:Hermez", "Income:Hermez:Transfers", &row.amount),
+        (Direction::Internal, _) => {}
+    }
+    if let (Some(fee), true) = (&row.fee, row.direction != Direction::In) {
+        post("Expenses:Hermez:Fees", "Assets:Hermez", fee);
+    }
+    postings
+}
+
+// Commodities other than plain letters must be quoted.
+fn commodity(symbol: &str) -> String {
+    if !symbol.is_empty() && symbol.chars().all(|c| c.is_ascii_alphabetic()) {
+        symbol.to_owned()
+    } else {
+        format!("\"{}\"", symbol)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{history_statement, Direction, Statement};
+    use crate::test_support;
+    use crate::transactions_history::HistoryTransaction;
+    use crate::HermezApi;
+    use chrono::{TimeZone, Utc};
+    use std::sync::{Arc, Mutex};
+
+    const OWNER: &str = "hez:0xaa942cfcd25ad4d90a62358b0dd84f33b398262a";
+    const OTHER: &str = "hez:0x0000000000000000000000000000000000000001";
+
+    fn transaction(
+        r#type: &str,
+        from: &str,
+        to: &str,
+        amount: u128,
+        deposit_amount: Option<u128>,
+    ) -> HistoryTransaction {
+        let id = format!("0x{}", r#type);
+        let mut tx = test_support::transaction(&id, r#type, "hez:ETH:256", "hez:ETH:257", amount);
+        tx["fromHezEthereumAddress"] = serde_json::json!(from);
+        tx["toHezEthereumAddress"] = serde_json::json!(to);
+        tx["batchNum"] = serde_json::json!(5);
+        tx["historicUSD"] = serde_json::json!(12.5);
+        tx["timestamp"] = serde_json::json!("2021-03-10T12:00:00.000Z");
+        let tx = match deposit_amount {
+            Some(deposit_amount) => test_support::deposit(tx, deposit_amount),
+            None => {
+                tx["L2Info"] =
+                    serde_json::json!({ "fee": 128, "historicFeeUSD": 0.05, "nonce": 0 });
+                tx
+            }
+        };
+        serde_json::from_value(tx).unwrap()
+    }
+
+    #[test]
+    fn test_statement() {
+        let statement = Statement::new(
+            OWNER,
+            &[
+                transaction("Deposit", OWNER, OWNER, 0, Some(2_000_000_000_000_000_000)),
+                transaction("Transfer", OWNER, OTHER, 1_500_000_000_000_000_000, None),
+                transaction("Transfer", OTHER, OWNER, 250_000_000_000_000_000, None),
+            ],
+        )
+        .unwrap();
+
+        let rows = &statement.rows;
+        assert_eq!(
+            vec![Direction::In, Direction::Out, Direction::In],
+            rows.iter().map(|row| row.direction).collect::<Vec<_>>()
+        );
+        assert_eq!(Some("2"), rows[0].deposit_amount.as_deref());
+        assert_eq!("1.5", rows[1].amount);
+        assert!(rows[1].fee.is_some());
+
+        let csv = statement.to_csv();
+        assert_eq!(4, csv.lines().count());
+        assert!(csv.starts_with("timestamp,type,direction,token,amount,"));
+        assert!(csv
+            .lines()
+            .nth(2)
+            .unwrap()
+            .contains(",Transfer,Out,ETH,1.5,"));
+
+        assert_eq!(3, statement.to_json_lines().lines().count());
+
+        let empty = Statement::new(OWNER, &[]).unwrap();
+        assert_eq!(
+            csv.lines().next().unwrap(),
+            empty.to_csv().trim_end_matches('\n')
+        );
+
+        let ledger = statement.to_ledger();
+        assert!(ledger.contains("2021/03/10 * Deposit 0xDeposit\n"));
+        assert!(ledger.contains("Expenses:Hermez:Transfers     1.5 ETH\n"));
+        assert!(ledger.contains("Income:Hermez:Transfers       -0.25 ETH\n"));
+        assert_eq!(1, ledger.matches("Expenses:Hermez:Fees").count());
+    }
+
+    #[test]
+    fn test_history_statement() {
+        // Transactions 1 to 8 of the owner, forged on March 1 to 8, served
+        // newest first by pages of 3.
+        let from_items = Arc::new(Mutex::new(Vec::new()));
+        let url = test_support::serve({
+            let from_items = from_items.clone();
+            move |request| {
+                assert_eq!("transactions-history", request.path());
+                assert_eq!(Some(OWNER), request.param("hezEthereumAddress").as_deref());
+                assert_eq!(Some("DESC"), request.param("order").as_deref());
+                let from_item = request.param("fromItem").map(|item| item.parse().unwrap());
+                from_items.lock().unwrap().push(from_item);
+                let items: Vec<u64> = (1..=from_item.unwrap_or(8)).rev().collect();
+                let transactions: Vec<_> = items
+                    .iter()
+                    .take(3)
+                    .map(|&item| {
+                        let id = format!("0x{:02x}", item);
+                        let mut tx = test_support::transaction(
+                            &id,
+                            "Transfer",
+                            "hez:ETH:256",
+                            "hez:ETH:257",
+                            1,
+                        );
+                        tx["itemId"] = serde_json::json!(item);
+                        tx["fromHezEthereumAddress"] = serde_json::json!(OWNER);
+                        tx["timestamp"] =
+                            serde_json::json!(format!("2021-03-{:02}T10:00:00Z", item));
+                        tx
+                    })
+                    .collect();
+                let pending_items = items.len().saturating_sub(3);
+                (
+                    200,
+                    serde_json::json!({ "transactions": transactions, "pendingItems": pending_items }),
+                )
+            }
+        });
+        let api = HermezApi::new(&url).unwrap();
+
+        // From March 3 included to March 7 excluded.
+        let range = Utc.with_ymd_and_hms(2021, 3, 3, 0, 0, 0).unwrap()
+            ..Utc.with_ymd_and_hms(2021, 3, 7, 0, 0, 0).unwrap();
+        let statement = async_std::task::block_on(history_statement(&api, OWNER, range)).unwrap();
+
+        assert_eq!(
+            vec!["0x03", "0x04", "0x05", "0x06"],
+            statement
+                .rows
+                .iter()
+                .map(|row| row.tx_id.as_str())
+                .collect::<Vec<_>>()
+        );
+        // Pages follow from the last item, and stop at the first transaction
+        // before the range, although the node has more.
+        assert_eq!(vec![None, Some(5), Some(2)], *from_items.lock().unwrap());
+    }
+}
@@ -1,6 +1,6 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct Health {
     #[serde(rename = "historyDB")]
     pub history_db: ConnectionStatus,
@@ -11,7 +11,7 @@ pub struct Health {
     pub version: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct ConnectionStatus {
     pub last_migration: String,
     pub status: String,
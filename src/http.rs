@@ -5,6 +5,13 @@ use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::time::Duration;
+
+use super::cache::Cache;
+use super::rate_limit::{RateLimit, RateLimitStats, RateLimiter};
+use super::Endpoint;
 use super::ErrorKind;
 
 impl From<surf::Error> for ErrorKind {
@@ -15,6 +22,26 @@ impl From<surf::Error> for ErrorKind {
 
 pub struct Http {
     client: surf::Client,
+    limiter: Option<RateLimiter>,
+    cache: Option<Cache>,
+}
+
+/// Settings used to create the underlying HTTP client.
+#[derive(Clone, Debug, Default)]
+pub struct HttpConfig {
+    pub timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    pub user_agent: Option<String>,
+    pub headers: Vec<(String, String)>,
+    pub proxy: Option<Url>,
+    pub rate_limit: Option<RateLimit>,
+    pub endpoint_rate_limits: HashMap<Endpoint, RateLimit>,
+    pub cache: Option<Cache>,
+}
+
+// Characters allowed in a header name (RFC 7230 token).
+fn is_header_name_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "!#$%&'*+-.^_`|~".contains(c)
 }
 
 #[derive(Deserialize)]
@@ -27,41 +54,182 @@ impl Http {
     pub fn new() -> Self {
         Self {
             client: surf::Client::new(),
+            limiter: None,
+            cache: None,
+        }
+    }
+
+    pub fn with_config(config: &HttpConfig) -> Result<Self, ErrorKind> {
+        let mut surf_config = surf::Config::new().set_timeout(config.timeout);
+
+        if let Some(user_agent) = &config.user_agent {
+            surf_config = surf_config
+                .add_header("User-Agent", user_agent.as_str())
+                .map_err(|err| ErrorKind::Config(err.to_string()))?;
+        }
+        for (name, value) in &config.headers {
+            if name.is_empty() || !name.chars().all(is_header_name_char) {
+                return Err(ErrorKind::Config(format!("Invalid header name: {}", name)));
+            }
+            surf_config = surf_config
+                .add_header(name.as_str(), value.as_str())
+                .map_err(|err| ErrorKind::Config(err.to_string()))?;
+        }
+
+        let client = Self::transport(surf_config, config)?.try_into().map_err(
+            |err: <surf::Client as std::convert::TryFrom<surf::Config>>::Error| {
+                ErrorKind::Config(err.to_string())
+            },
+        )?;
+
+        let limiter = if config.rate_limit.is_some() || !config.endpoint_rate_limits.is_empty() {
+            Some(RateLimiter::new(
+                config.rate_limit,
+                &config.endpoint_rate_limits,
+            ))
+        } else {
+            None
+        };
+
+        Ok(Self {
+            client,
+            limiter,
+            cache: config.cache.clone(),
+        })
+    }
+
+    // Connection timeouts and proxies are only exposed by the curl transport.
+    #[cfg(feature = "curl-client")]
+    fn transport(
+        surf_config: surf::Config,
+        config: &HttpConfig,
+    ) -> Result<surf::Config, ErrorKind> {
+        use isahc::config::Configurable;
+
+        let mut builder = isahc::HttpClient::builder();
+        if let Some(timeout) = config.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(connect_timeout) = config.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(proxy) = &config.proxy {
+            let proxy: isahc::http::Uri = proxy
+                .as_str()
+                .parse()
+                .map_err(|err: isahc::http::uri::InvalidUri| ErrorKind::Config(err.to_string()))?;
+            builder = builder.proxy(proxy);
+        }
+        let client = builder
+            .build()
+            .map_err(|err| ErrorKind::Config(err.to_string()))?;
+
+        Ok(surf_config.set_http_client(http_client::isahc::IsahcClient::from_client(client)))
+    }
+
+    #[cfg(not(feature = "curl-client"))]
+    fn transport(
+        surf_config: surf::Config,
+        config: &HttpConfig,
+    ) -> Result<surf::Config, ErrorKind> {
+        if config.connect_timeout.is_some() || config.proxy.is_some() {
+            return Err(ErrorKind::Config(String::from(
+                "Connect timeouts and proxies require the curl-client transport",
+            )));
         }
+        Ok(surf_config)
     }
 
-    pub async fn get<T>(&self, url: &Url) -> Result<T, ErrorKind>
+    pub fn rate_limit_stats(&self) -> HashMap<Endpoint, RateLimitStats> {
+        match &self.limiter {
+            Some(limiter) => limiter.stats(),
+            None => HashMap::new(),
+        }
+    }
+
+    async fn throttle(&self, endpoint: Endpoint) {
+        if let Some(limiter) = &self.limiter {
+            limiter.acquire(endpoint).await;
+        }
+    }
+
+    pub async fn get<T>(&self, endpoint: Endpoint, url: &Url) -> Result<T, ErrorKind>
     where
         //T: for<'de> Deserialize<'de>,
         T: DeserializeOwned,
     {
-        let mut response = self.client.get(url).send().await?;
-        match response.status() {
-            surf::StatusCode::Ok => Ok(response.body_json().await?),
-            code @ _ => {
-                let Message { message } = response.body_json().await?;
-                Err(ErrorKind::Http(code as u16, message))
+        self.throttle(endpoint).await;
+        let mut response = send(self.client.get(url)).await?;
+        let body = response_body(&mut response).await?;
+        serde_json::from_str(&body).map_err(|err| ErrorKind::Json(err.to_string()))
+    }
+
+    /// Like `get()`, but serves the response from the cache when there is one.
+    pub async fn get_cached<T>(&self, endpoint: Endpoint, url: &Url) -> Result<T, ErrorKind>
+    where
+        T: DeserializeOwned,
+    {
+        let cache = match &self.cache {
+            Some(cache) if cache.is_cached(endpoint) => cache,
+            _ => return self.get(endpoint, url).await,
+        };
+
+        let key = url.as_str();
+        let body = match cache.lookup(key) {
+            Some(body) => body,
+            None => {
+                self.throttle(endpoint).await;
+                let mut response = send(self.client.get(url)).await?;
+                let body = response_body(&mut response).await?;
+                cache.store(endpoint, key, &body);
+                body
             }
-        }
+        };
+
+        serde_json::from_str(&body).map_err(|err| {
+            cache.remove(key);
+            ErrorKind::Json(err.to_string())
+        })
+    }
+
+    pub fn cache(&self) -> Option<&Cache> {
+        self.cache.as_ref()
     }
 
-    pub async fn post<U, T>(&self, url: &Url, body: &U) -> Result<T, ErrorKind>
+    pub async fn post<U, T>(&self, endpoint: Endpoint, url: &Url, body: &U) -> Result<T, ErrorKind>
     where
         U: Serialize,
         T: for<'de> Deserialize<'de>,
     {
-        let mut response = self
+        self.throttle(endpoint).await;
+        let request = self
             .client
             .post(url)
-            .body(serde_json::to_string(body).unwrap())
-            .send()
-            .await?;
-        match response.status() {
-            surf::StatusCode::Ok => Ok(response.body_json().await?),
-            code @ _ => {
-                let Message { message } = response.body_json().await?;
-                Err(ErrorKind::Http(code as u16, message))
-            }
-        }
+            .body(serde_json::to_string(body).unwrap());
+        let mut response = send(request).await?;
+        let body = response_body(&mut response).await?;
+        serde_json::from_str(&body).map_err(|err| ErrorKind::Json(err.to_string()))
+    }
+}
+
+// Sends a request. Failing to get a response says nothing about the request,
+// so it is a transport error.
+async fn send(request: surf::RequestBuilder) -> Result<surf::Response, ErrorKind> {
+    request.await.map_err(|_| ErrorKind::Transport)
+}
+
+// The body of a successful response, or the error of the node with its
+// message, or the body as is when it has no message.
+async fn response_body(response: &mut surf::Response) -> Result<String, ErrorKind> {
+    let body = response
+        .body_string()
+        .await
+        .map_err(|_| ErrorKind::Transport)?;
+    match response.status() {
+        surf::StatusCode::Ok => Ok(body),
+        code => match serde_json::from_str::<Message>(&body) {
+            Ok(Message { message }) => Err(ErrorKind::Http(code as u16, message)),
+            Err(_) => Err(ErrorKind::Http(code as u16, body)),
+        },
     }
 }
@@ -0,0 +1,962 @@
+//! A local SQLite index of the node's data, with the `indexer` feature.
+//!
+//! Batches with their transactions, accounts, tokens, exits, slots and bids
+//! are fetched through the API and stored in a database. Each call to
+//! `Indexer::sync()` carries on from where the previous one stopped, after
+//! undoing batches the node has rolled back.
+//!
+//! Objects are stored as returned by the node, along with the columns used
+//! for filtering. Amounts are kept as decimal text, zero padded to 39 digits
+//! so that they compare in order.
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::stream::{self, StreamExt};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::path::Path;
+
+use super::accounts::Account;
+use super::batches::{Batch, BatchesQuery, FullBatch};
+use super::bids::{Bid, BidsQuery};
+use super::exits::{Exit, ExitsQuery};
+use super::reorg::{find_rollback, BatchFingerprint};
+use super::slots::{Slot, SlotsQuery};
+use super::tokens::{Token, TokensQuery};
+use super::transactions_history::{HistoryTransaction, TransactionType};
+use super::{parse_amount, parse_timestamp, ErrorKind, HermezApi, PaginationOrder};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS checkpoints (
+    name TEXT PRIMARY KEY,
+    value INTEGER NOT NULL
+);
+CREATE TABLE IF NOT EXISTS batches (
+    batch_num INTEGER PRIMARY KEY,
+    ethereum_block_hash TEXT NOT NULL,
+    state_root TEXT NOT NULL,
+    forger_addr TEXT NOT NULL,
+    slot_num INTEGER NOT NULL,
+    timestamp TEXT NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS batches_timestamp ON batches (timestamp);
+CREATE TABLE IF NOT EXISTS transactions (
+    id TEXT PRIMARY KEY,
+    batch_num INTEGER NOT NULL,
+    position INTEGER NOT NULL,
+    type TEXT NOT NULL,
+    token_id INTEGER NOT NULL,
+    from_account_index TEXT,
+    to_account_index TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS transactions_batch ON transactions (batch_num, position);
+CREATE INDEX IF NOT EXISTS transactions_timestamp ON transactions (timestamp);
+CREATE INDEX IF NOT EXISTS transactions_from ON transactions (from_account_index);
+CREATE INDEX IF NOT EXISTS transactions_to ON transactions (to_account_index);
+CREATE TABLE IF NOT EXISTS accounts (
+    account_index TEXT PRIMARY KEY,
+    token_id INTEGER NOT NULL,
+    hez_ethereum_address TEXT NOT NULL,
+    bjj TEXT NOT NULL,
+    balance TEXT NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS tokens (
+    id INTEGER PRIMARY KEY,
+    symbol TEXT NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS exits (
+    batch_num INTEGER NOT NULL,
+    account_index TEXT NOT NULL,
+    item_id INTEGER NOT NULL,
+    token_id INTEGER NOT NULL,
+    balance TEXT NOT NULL,
+    pending INTEGER NOT NULL,
+    json TEXT NOT NULL,
+    PRIMARY KEY (batch_num, account_index)
+);
+CREATE TABLE IF NOT EXISTS slots (
+    slot_num INTEGER PRIMARY KEY,
+    json TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS bids (
+    item_id INTEGER PRIMARY KEY,
+    slot_num INTEGER NOT NULL,
+    bidder_addr TEXT NOT NULL,
+    bid_value TEXT NOT NULL,
+    timestamp TEXT NOT NULL,
+    json TEXT NOT NULL
+);
+CREATE INDEX IF NOT EXISTS bids_timestamp ON bids (timestamp);
+";
+
+/// What a call to `Indexer::sync()` did.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct SyncReport {
+    /// Batches from this one on were rolled back by the node, and removed.
+    pub rolled_back_from: Option<u32>,
+    pub batches: u64,
+    pub transactions: u64,
+    pub accounts: u64,
+    pub tokens: u64,
+    pub exits: u64,
+    pub slots: u64,
+    pub bids: u64,
+}
+
+/// Filter for `Indexer::transactions()`, all conditions applying together.
+///
+/// Times are those of the transactions, and amounts are in the smallest unit
+/// of the token.
+#[derive(Clone, Debug, Default)]
+pub struct TransactionFilter {
+    /// Transactions sent from or to the account.
+    pub account_index: Option<String>,
+    pub token_id: Option<u32>,
+    pub r#type: Option<TransactionType>,
+    pub from_time: Option<DateTime<Utc>>,
+    /// Excluded.
+    pub to_time: Option<DateTime<Utc>>,
+    pub min_amount: Option<u128>,
+    pub max_amount: Option<u128>,
+    pub limit: Option<u64>,
+}
+
+/// Keeps a local copy of the node's data in SQLite.
+///
+/// # Example
+///
+/// ```no_run
+/// use chrono::{TimeZone, Utc};
+/// use hermez_api::indexer::{Indexer, TransactionFilter};
+/// use hermez_api::HermezApi;
+///
+/// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+/// let mut indexer = Indexer::open(&api, "hermez.db").unwrap();
+/// indexer.sync().await.unwrap();
+///
+/// let large_transfers = indexer
+///     .transactions(&TransactionFilter {
+///         token_id: Some(0),
+///         from_time: Some(Utc.with_ymd_and_hms(2021, 3, 1, 0, 0, 0).unwrap()),
+///         min_amount: Some(10_000_000_000_000_000_000),
+///         ..Default::default()
+///     })
+///     .unwrap();
+/// ```
+pub struct Indexer {
+    api: HermezApi,
+    conn: Connection,
+    depth: u32,
+}
+
+impl Indexer {
+    /// Opens the database at `path`, creating it if needed.
+    pub fn open<P: AsRef<Path>>(api: &HermezApi, path: P) -> Result<Self, ErrorKind> {
+        Self::with_connection(api, Connection::open(path).map_err(db)?)
+    }
+
+    /// An index kept in memory, lost when dropped.
+    pub fn open_in_memory(api: &HermezApi) -> Result<Self, ErrorKind> {
+        Self::with_connection(api, Connection::open_in_memory().map_err(db)?)
+    }
+
+    fn with_connection(api: &HermezApi, conn: Connection) -> Result<Self, ErrorKind> {
+        conn.execute_batch(SCHEMA).map_err(db)?;
+        Ok(Self {
+            api: api.clone(),
+            conn,
+            depth: 64,
+        })
+    }
+
+    /// Number of recent batches checked for rollbacks on each sync, 64 by default.
+    pub fn depth(&mut self, depth: u32) -> &mut Self {
+        self.depth = depth;
+        self
+    }
+
+    /// The database, for queries not covered here.
+    pub fn connection(&self) -> &Connection {
+        &self.conn
+    }
+
+    /// The last batch stored, 0 if none.
+    pub fn last_batch(&self) -> Result<u32, ErrorKind> {
+        Ok(checkpoint(&self.conn, "last_batch")? as u32)
+    }
+
+    /// Brings the index up to date with the node.
+    ///
+    /// Batches are stored one at a time, so an interrupted sync loses
+    /// nothing already fetched. Slots and bids are stored once their auction
+    /// is closed, and exits are updated once withdrawn.
+    pub async fn sync(&mut self) -> Result<SyncReport, ErrorKind> {
+        let mut report = SyncReport::default();
+        let state = self.api.get_state().await?;
+
+        let mut touched = BTreeSet::new();
+        let last = state.network.last_batch.batch_num;
+        if let Some(from_batch) = self.check_rollback(last).await? {
+            touched = self.rollback(from_batch)?;
+            report.rolled_back_from = Some(from_batch);
+        }
+
+        let first = self.last_batch()? + 1;
+        let api = self.api.clone();
+        let mut batches = stream::iter(first..=last)
+            .map(|batch_num| {
+                let api = api.clone();
+                async move { api.get_full_batch(batch_num).await }
+            })
+            .buffered(api.inner.concurrency);
+        while let Some(batch) = batches.next().await {
+            let batch = batch?;
+            for tx in &batch.transactions {
+                touched.extend(tx.from_account_index.iter().cloned());
+                touched.insert(tx.to_account_index.clone());
+            }
+            report.batches += 1;
+            report.transactions += batch.transactions.len() as u64;
+            self.store_batch(&batch)?;
+        }
+
+        report.accounts = self.sync_accounts(touched).await?;
+        report.tokens = self.sync_tokens().await?;
+        report.exits = self.sync_exits().await?;
+        let last_closed_slot =
+            state.network.current_slot as u64 + state.auction.closed_auction_slots;
+        let (slots, bids) = self.sync_slots(last_closed_slot as u32).await?;
+        report.slots = slots;
+        report.bids = bids;
+        Ok(report)
+    }
+
+    // The first stored batch which the node no longer has, or has changed.
+    // Only batches up to `node_last_batch`, the last one the node has
+    // forged, are compared: a node behind the index has not rolled back.
+    async fn check_rollback(&mut self, node_last_batch: u32) -> Result<Option<u32>, ErrorKind> {
+        let stored = self.last_batch()?;
+        let first = stored.saturating_sub(self.depth).max(1);
+        let last = stored.min(node_last_batch);
+        if last < first {
+            return Ok(None);
+        }
+        let seen: BTreeMap<u32, BatchFingerprint> = self
+            .select::<Batch>(
+                "SELECT json FROM batches WHERE batch_num >= ?1 AND batch_num <= ?2
+                 ORDER BY batch_num",
+                vec![Value::Integer(first as i64), Value::Integer(last as i64)],
+            )?
+            .iter()
+            .map(|batch| (batch.batch_num, BatchFingerprint::from(batch)))
+            .collect();
+
+        // The node takes both bounds as exclusive.
+        let query = BatchesQuery {
+            min_batch_num: Some(first - 1),
+            max_batch_num: Some(last + 1),
+            order: Some(PaginationOrder::Asc),
+            ..Default::default()
+        };
+        let latest = self
+            .api
+            .run_all(&query)
+            .await?
+            .iter()
+            .map(|batch| (batch.batch_num, BatchFingerprint::from(batch)))
+            .collect();
+        Ok(find_rollback(&seen, &latest))
+    }
+
+    // Removes batches from `from_batch` on, with their transactions and
+    // exits, and returns the accounts those transactions touched.
+    fn rollback(&mut self, from_batch: u32) -> Result<BTreeSet<String>, ErrorKind> {
+        let from = from_batch as i64;
+        let mut touched = BTreeSet::new();
+        {
+            let mut stmt = self
+                .conn
+                .prepare(
+                    "SELECT from_account_index, to_account_index FROM transactions
+                     WHERE batch_num >= ?1",
+                )
+                .map_err(db)?;
+            let rows = stmt
+                .query_map(params![from], |row| {
+                    Ok((row.get::<_, Option<String>>(0)?, row.get::<_, String>(1)?))
+                })
+                .map_err(db)?;
+            for row in rows {
+                let (from_account_index, to_account_index) = row.map_err(db)?;
+                touched.extend(from_account_index);
+                touched.insert(to_account_index);
+            }
+        }
+
+        let tx = self.conn.transaction().map_err(db)?;
+        tx.execute("DELETE FROM batches WHERE batch_num >= ?1", params![from])
+            .map_err(db)?;
+        tx.execute(
+            "DELETE FROM transactions WHERE batch_num >= ?1",
+            params![from],
+        )
+        .map_err(db)?;
+        tx.execute("DELETE FROM exits WHERE batch_num >= ?1", params![from])
+            .map_err(db)?;
+        let last_exit_item: i64 = tx
+            .query_row("SELECT COALESCE(MAX(item_id), 0) FROM exits", [], |row| {
+                row.get(0)
+            })
+            .map_err(db)?;
+        set_checkpoint(&tx, "last_batch", from_batch as i64 - 1)?;
+        set_checkpoint(&tx, "last_exit_item", last_exit_item)?;
+        tx.commit().map_err(db)?;
+        Ok(touched)
+    }
+
+    fn store_batch(&mut self, batch: &FullBatch) -> Result<(), ErrorKind> {
+        let tx = self.conn.transaction().map_err(db)?;
+        let b = &batch.batch;
+        tx.execute(
+            "INSERT OR REPLACE INTO batches
+             (batch_num, ethereum_block_hash, state_root, forger_addr, slot_num, timestamp, json)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                b.batch_num,
+                b.ethereum_block_hash,
+                b.state_root,
+                b.forger_addr,
+                b.slot_num,
+                time(&b.timestamp)?,
+                json(b)?
+            ],
+        )
+        .map_err(db)?;
+        for t in &batch.transactions {
+            tx.execute(
+                "INSERT OR REPLACE INTO transactions
+                 (id, batch_num, position, type, token_id, from_account_index,
+                  to_account_index, amount, timestamp, json)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+                params![
+                    t.id,
+                    b.batch_num,
+                    t.position as i64,
+                    type_name(t.r#type),
+                    t.token.id,
+                    t.from_account_index,
+                    t.to_account_index,
+                    padded(parse_amount(&t.amount)?),
+                    time(&t.timestamp)?,
+                    json(t)?
+                ],
+            )
+            .map_err(db)?;
+        }
+        set_checkpoint(&tx, "last_batch", b.batch_num as i64)?;
+        tx.commit().map_err(db)
+    }
+
+    // Fetches the accounts touched by new or rolled back transactions.
+    async fn sync_accounts(&mut self, touched: BTreeSet<String>) -> Result<u64, ErrorKind> {
+        // Numbers below 256 are not user accounts.
+        let touched = touched.into_iter().filter(|index| {
+            matches!(index.rsplit(':').next().map(str::parse::<u64>), Some(Ok(n)) if n >= 256)
+        });
+        let api = self.api.clone();
+        let fetched: Vec<(String, Result<Account, ErrorKind>)> = stream::iter(touched)
+            .map(|index| {
+                let api = api.clone();
+                async move {
+                    let account = api.get_account(&index).await;
+                    (index, account)
+                }
+            })
+            .buffered(api.inner.concurrency)
+            .collect()
+            .await;
+
+        let tx = self.conn.transaction().map_err(db)?;
+        let mut count = 0;
+        for (index, account) in fetched {
+            match account {
+                Ok(account) => {
+                    tx.execute(
+                        "INSERT OR REPLACE INTO accounts
+                         (account_index, token_id, hez_ethereum_address, bjj, balance, json)
+                         VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                        params![
+                            account.account_index,
+                            account.token.id,
+                            account.hez_ethereum_address,
+                            account.bjj,
+                            padded(parse_amount(&account.balance)?),
+                            json(&account)?
+                        ],
+                    )
+                    .map_err(db)?;
+                    count += 1;
+                }
+                // Created by a batch that was rolled back.
+                Err(ErrorKind::Http(404, _)) => {
+                    tx.execute(
+                        "DELETE FROM accounts WHERE account_index = ?1",
+                        params![index],
+                    )
+                    .map_err(db)?;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        tx.commit().map_err(db)?;
+        Ok(count)
+    }
+
+    async fn sync_tokens(&mut self) -> Result<u64, ErrorKind> {
+        let tokens = self.api.run_all(&TokensQuery::default()).await?;
+        let tx = self.conn.transaction().map_err(db)?;
+        for token in &tokens {
+            tx.execute(
+                "INSERT OR REPLACE INTO tokens (id, symbol, json) VALUES (?1, ?2, ?3)",
+                params![token.id, token.symbol, json(token)?],
+            )
+            .map_err(db)?;
+        }
+        tx.commit().map_err(db)?;
+        Ok(tokens.len() as u64)
+    }
+
+    // Fetches new exits, and those stored as pending which the node no
+    // longer lists as such.
+    async fn sync_exits(&mut self) -> Result<u64, ErrorKind> {
+        let query = ExitsQuery {
+            from_item: Some(checkpoint(&self.conn, "last_exit_item")? + 1),
+            order: Some(PaginationOrder::Asc),
+            ..Default::default()
+        };
+        let mut exits = self.api.run_all(&query).await?;
+
+        let query = ExitsQuery {
+            only_pending_withdraws: Some(true),
+            ..Default::default()
+        };
+        let pending: HashSet<(u32, String)> = self
+            .api
+            .run_all(&query)
+            .await?
+            .into_iter()
+            .map(|exit| (exit.batch_num, exit.account_index))
+            .collect();
+        let withdrawn: Vec<(u32, String)> = self
+            .select::<Exit>("SELECT json FROM exits WHERE pending = 1", Vec::new())?
+            .into_iter()
+            .map(|exit| (exit.batch_num, exit.account_index))
+            .filter(|exit| !pending.contains(exit))
+            .collect();
+        let api = self.api.clone();
+        let refreshed: Vec<Result<Exit, ErrorKind>> = stream::iter(withdrawn)
+            .map(|(batch_num, account_index)| {
+                let api = api.clone();
+                async move { api.get_exit(batch_num, &account_index).await }
+            })
+            .buffered(api.inner.concurrency)
+            .collect()
+            .await;
+        for exit in refreshed {
+            exits.push(exit?);
+        }
+
+        let tx = self.conn.transaction().map_err(db)?;
+        let mut last_exit_item = checkpoint(&tx, "last_exit_item")?;
+        for exit in &exits {
+            let pending = exit.instant_withdraw.is_none() && exit.delayed_withdraw.is_none();
+            tx.execute(
+                "INSERT OR REPLACE INTO exits
+                 (batch_num, account_index, item_id, token_id, balance, pending, json)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+                params![
+                    exit.batch_num,
+                    exit.account_index,
+                    exit.item_id as i64,
+                    exit.token.id,
+                    padded(parse_amount(&exit.balance)?),
+                    pending,
+                    json(exit)?
+                ],
+            )
+            .map_err(db)?;
+            last_exit_item = last_exit_item.max(exit.item_id);
+        }
+        set_checkpoint(&tx, "last_exit_item", last_exit_item as i64)?;
+        tx.commit().map_err(db)?;
+        Ok(exits.len() as u64)
+    }
+
+    // Fetches slots whose auction closed since the last sync, with their bids.
+    async fn sync_slots(&mut self, last_closed_slot: u32) -> Result<(u64, u64), ErrorKind> {
+        let next_slot = checkpoint(&self.conn, "next_slot")? as u32;
+        if next_slot > last_closed_slot {
+            return Ok((0, 0));
+        }
+        let query = SlotsQuery {
+            min_slot_num: Some(next_slot),
+            max_slot_num: Some(last_closed_slot),
+            order: Some(PaginationOrder::Asc),
+            ..Default::default()
+        };
+        let slots = self.api.run_all(&query).await?;
+
+        let bid_slots = slots
+            .iter()
+            .filter(|slot| slot.best_bid.is_some())
+            .map(|slot| slot.slot_num);
+        let api = self.api.clone();
+        let pages: Vec<Result<Vec<Bid>, ErrorKind>> = stream::iter(bid_slots)
+            .map(|slot_num| {
+                let api = api.clone();
+                async move {
+                    let query = BidsQuery {
+                        slot_num: Some(slot_num),
+                        ..Default::default()
+                    };
+                    api.run_all(&query).await
+                }
+            })
+            .buffered(api.inner.concurrency)
+            .collect()
+            .await;
+
+        let tx = self.conn.transaction().map_err(db)?;
+        for slot in &slots {
+            tx.execute(
+                "INSERT OR REPLACE INTO slots (slot_num, json) VALUES (?1, ?2)",
+                params![slot.slot_num, json(slot)?],
+            )
+            .map_err(db)?;
+        }
+        let mut bids = 0;
+        for page in pages {
+            for bid in page? {
+                tx.execute(
+                    "INSERT OR REPLACE INTO bids
+                     (item_id, slot_num, bidder_addr, bid_value, timestamp, json)
+                     VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                    params![
+                        bid.item_id as i64,
+                        bid.slot_num,
+                        bid.bidder_addr,
+                        padded(parse_amount(&bid.bid_value)?),
+                        time(&bid.timestamp)?,
+                        json(&bid)?
+                    ],
+                )
+                .map_err(db)?;
+                bids += 1;
+            }
+        }
+        set_checkpoint(&tx, "next_slot", last_closed_slot as i64 + 1)?;
+        tx.commit().map_err(db)?;
+        Ok((slots.len() as u64, bids))
+    }
+
+    fn select<T: DeserializeOwned>(
+        &self,
+        sql: &str,
+        values: Vec<Value>,
+    ) -> Result<Vec<T>, ErrorKind> {
+        let mut stmt = self.conn.prepare(sql).map_err(db)?;
+        let rows = stmt
+            .query_map(params_from_iter(values), |row| row.get::<_, String>(0))
+            .map_err(db)?;
+        rows.map(|row| {
+            let row = row.map_err(db)?;
+            serde_json::from_str(&row).map_err(|err| ErrorKind::Json(err.to_string()))
+        })
+        .collect()
+    }
+
+    /// Transactions matching a filter, in the order they were forged.
+    pub fn transactions(
+        &self,
+        filter: &TransactionFilter,
+    ) -> Result<Vec<HistoryTransaction>, ErrorKind> {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        let mut condition = |sql: &str, value: Value| {
+            values.push(value);
+            conditions.push(sql.replace('?', &format!("?{}", values.len())));
+        };
+        if let Some(account_index) = &filter.account_index {
+            condition(
+                "(from_account_index = ? OR to_account_index = ?)",
+                Value::Text(account_index.clone()),
+            );
+        }
+        if let Some(token_id) = filter.token_id {
+            condition("token_id = ?", Value::Integer(token_id as i64));
+        }
+        if let Some(r#type) = filter.r#type {
+            condition("type = ?", Value::Text(type_name(r#type)));
+        }
+        if let Some(from_time) = filter.from_time {
+            condition("timestamp >= ?", Value::Text(format_time(from_time)));
+        }
+        if let Some(to_time) = filter.to_time {
+            condition("timestamp < ?", Value::Text(format_time(to_time)));
+        }
+        if let Some(min_amount) = filter.min_amount {
+            condition("amount >= ?", Value::Text(padded(min_amount)));
+        }
+        if let Some(max_amount) = filter.max_amount {
+            condition("amount <= ?", Value::Text(padded(max_amount)));
+        }
+
+        let mut sql = String::from("SELECT json FROM transactions");
+        if !conditions.is_empty() {
+            sql += &format!(" WHERE {}", conditions.join(" AND "));
+        }
+        sql += " ORDER BY batch_num, position";
+        if let Some(limit) = filter.limit {
+            sql += &format!(" LIMIT {}", limit);
+        }
+        self.select(&sql, values)
+    }
+
+    /// Total amount of a token transferred, deposited or withdrawn between
+    /// two times, the second excluded.
+    pub fn token_volume(
+        &self,
+        token_id: u32,
+        from_time: DateTime<Utc>,
+        to_time: DateTime<Utc>,
+    ) -> Result<u128, ErrorKind> {
+        let transactions = self.transactions(&TransactionFilter {
+            token_id: Some(token_id),
+            from_time: Some(from_time),
+            to_time: Some(to_time),
+            ..Default::default()
+        })?;
+        transactions.iter().try_fold(0u128, |total, tx| {
+            Ok(total.saturating_add(parse_amount(&tx.amount)?))
+        })
+    }
+
+    /// Batches forged between two times, the second excluded.
+    pub fn batches_between(
+        &self,
+        from_time: DateTime<Utc>,
+        to_time: DateTime<Utc>,
+    ) -> Result<Vec<Batch>, ErrorKind> {
+        self.select(
+            "SELECT json FROM batches WHERE timestamp >= ?1 AND timestamp < ?2
+             ORDER BY batch_num",
+            vec![
+                Value::Text(format_time(from_time)),
+                Value::Text(format_time(to_time)),
+            ],
+        )
+    }
+
+    /// Accounts of a token holding at least `min_balance`, largest first.
+    pub fn accounts_above(
+        &self,
+        token_id: u32,
+        min_balance: u128,
+    ) -> Result<Vec<Account>, ErrorKind> {
+        self.select(
+            "SELECT json FROM accounts WHERE token_id = ?1 AND balance >= ?2
+             ORDER BY balance DESC",
+            vec![
+                Value::Integer(token_id as i64),
+                Value::Text(padded(min_balance)),
+            ],
+        )
+    }
+
+    /// Exits of at least `min_balance`, of a token if given.
+    pub fn exits_above(
+        &self,
+        token_id: Option<u32>,
+        min_balance: u128,
+        only_pending: bool,
+    ) -> Result<Vec<Exit>, ErrorKind> {
+        self.select(
+            "SELECT json FROM exits
+             WHERE (?1 IS NULL OR token_id = ?1) AND balance >= ?2 AND (?3 = 0 OR pending = 1)
+             ORDER BY batch_num, account_index",
+            vec![
+                token_id.map_or(Value::Null, |id| Value::Integer(id as i64)),
+                Value::Text(padded(min_balance)),
+                Value::Integer(only_pending as i64),
+            ],
+        )
+    }
+
+    /// Bids placed between two times, the second excluded.
+    pub fn bids_between(
+        &self,
+        from_time: DateTime<Utc>,
+        to_time: DateTime<Utc>,
+    ) -> Result<Vec<Bid>, ErrorKind> {
+        self.select(
+            "SELECT json FROM bids WHERE timestamp >= ?1 AND timestamp < ?2 ORDER BY item_id",
+            vec![
+                Value::Text(format_time(from_time)),
+                Value::Text(format_time(to_time)),
+            ],
+        )
+    }
+
+    /// Stored slots, from `min_slot_num` to `max_slot_num`.
+    pub fn slots(&self, min_slot_num: u32, max_slot_num: u32) -> Result<Vec<Slot>, ErrorKind> {
+        self.select(
+            "SELECT json FROM slots WHERE slot_num >= ?1 AND slot_num <= ?2 ORDER BY slot_num",
+            vec![
+                Value::Integer(min_slot_num as i64),
+                Value::Integer(max_slot_num as i64),
+            ],
+        )
+    }
+
+    /// A stored token.
+    pub fn token(&self, id: u32) -> Result<Option<Token>, ErrorKind> {
+        let json: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT json FROM tokens WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(db)?;
+        json.map(|json| serde_json::from_str(&json).map_err(|err| ErrorKind::Json(err.to_string())))
+            .transpose()
+    }
+}
+
+fn db(err: rusqlite::Error) -> ErrorKind {
+    ErrorKind::Database(err.to_string())
+}
+
+fn json<T: Serialize>(value: &T) -> Result<String, ErrorKind> {
+    serde_json::to_string(value).map_err(|err| ErrorKind::Json(err.to_string()))
+}
+
+fn checkpoint(conn: &Connection, name: &str) -> Result<u64, ErrorKind> {
+    let value: Option<i64> = conn
+        .query_row(
+            "SELECT value FROM checkpoints WHERE name = ?1",
+            params![name],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(db)?;
+    Ok(value.unwrap_or(0).max(0) as u64)
+}
+
+fn set_checkpoint(conn: &Connection, name: &str, value: i64) -> Result<(), ErrorKind> {
+    conn.execute(
+        "INSERT OR REPLACE INTO checkpoints (name, value) VALUES (?1, ?2)",
+        params![name, value],
+    )
+    .map_err(db)?;
+    Ok(())
+}
+
+// Amounts are compared as text, so they are padded to the width of u128::MAX.
+fn padded(amount: u128) -> String {
+    format!("{:039}", amount)
+}
+
+fn type_name(r#type: TransactionType) -> String {
+    format!("{:?}", r#type)
+}
+
+// Times are stored in UTC, in a format that compares in order.
+fn format_time(time: DateTime<Utc>) -> String {
+    time.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+fn time(timestamp: &str) -> Result<String, ErrorKind> {
+    parse_timestamp(timestamp).map(format_time)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Indexer, TransactionFilter};
+    use crate::batches::FullBatch;
+    use crate::test_support;
+    use crate::transactions_history::TransactionType;
+    use crate::HermezApi;
+    use chrono::{TimeZone, Utc};
+
+    fn batch(batch_num: u32, amounts: &[u128]) -> FullBatch {
+        let transactions = amounts
+            .iter()
+            .enumerate()
+            .map(|(position, &amount)| {
+                let id = format!("0x{}{}", batch_num, position);
+                let mut tx = test_support::transaction(
+                    &id,
+                    "Transfer",
+                    "hez:ETH:256",
+                    "hez:ETH:257",
+                    amount,
+                );
+                tx["itemId"] = serde_json::json!(position);
+                tx["position"] = serde_json::json!(position);
+                tx["batchNum"] = serde_json::json!(batch_num);
+                tx["timestamp"] =
+                    serde_json::json!(format!("2021-03-0{}T10:00:00+02:00", batch_num));
+                tx
+            })
+            .collect();
+        test_support::full_batch(batch_num, transactions)
+    }
+
+    #[test]
+    fn test_indexer() {
+        let api = HermezApi::new(HermezApi::LOCALHOST_URL).unwrap();
+        let mut indexer = Indexer::open_in_memory(&api).unwrap();
+
+        indexer
+            .store_batch(&batch(1, &[5, 1_000_000_000_000_000_000_000]))
+            .unwrap();
+        indexer.store_batch(&batch(2, &[7, 100])).unwrap();
+        indexer.store_batch(&batch(3, &[9])).unwrap();
+        assert_eq!(3, indexer.last_batch().unwrap());
+
+        let amounts = |indexer: &Indexer, filter: TransactionFilter| -> Vec<String> {
+            let transactions = indexer.transactions(&filter).unwrap();
+            transactions.into_iter().map(|tx| tx.amount).collect()
+        };
+        assert_eq!(
+            vec!["1000000000000000000000", "100", "9"],
+            amounts(
+                &indexer,
+                TransactionFilter {
+                    min_amount: Some(9),
+                    ..Default::default()
+                }
+            )
+        );
+        assert_eq!(
+            vec!["7", "100"],
+            amounts(
+                &indexer,
+                TransactionFilter {
+                    account_index: Some(String::from("hez:ETH:257")),
+                    from_time: Some(Utc.with_ymd_and_hms(2021, 3, 2, 8, 0, 0).unwrap()),
+                    to_time: Some(Utc.with_ymd_and_hms(2021, 3, 3, 8, 0, 0).unwrap()),
+                    r#type: Some(TransactionType::Transfer),
+                    ..Default::default()
+                }
+            )
+        );
+        assert_eq!(
+            107,
+            indexer
+                .token_volume(
+                    0,
+                    Utc.with_ymd_and_hms(2021, 3, 2, 0, 0, 0).unwrap(),
+                    Utc.with_ymd_and_hms(2021, 3, 3, 0, 0, 0).unwrap()
+                )
+                .unwrap()
+        );
+
+        let touched = indexer.rollback(2).unwrap();
+        assert_eq!(1, indexer.last_batch().unwrap());
+        assert!(touched.contains("hez:ETH:256"));
+        assert_eq!(2, amounts(&indexer, TransactionFilter::default()).len());
+    }
+
+    // Serves the batches of `full_batches` as a node would, taking
+    // minBatchNum and maxBatchNum as exclusive bounds, and returns its URL.
+    fn serve_batches(full_batches: &[FullBatch]) -> String {
+        let batches: Vec<serde_json::Value> = full_batches
+            .iter()
+            .map(|full_batch| serde_json::to_value(&full_batch.batch).unwrap())
+            .collect();
+        test_support::serve(move |request| {
+            let bound = |name: &str| {
+                request
+                    .param(name)
+                    .map(|value| value.parse::<u64>().unwrap())
+            };
+            let (min, max) = (bound("minBatchNum"), bound("maxBatchNum"));
+            let page: Vec<&serde_json::Value> = batches
+                .iter()
+                .filter(|batch| {
+                    let batch_num = batch["batchNum"].as_u64().unwrap();
+                    batch_num > min.unwrap_or(0) && batch_num < max.unwrap_or(u64::MAX)
+                })
+                .collect();
+            (
+                200,
+                serde_json::json!({ "batches": page, "pendingItems": 0 }),
+            )
+        })
+    }
+
+    #[test]
+    fn test_check_rollback() {
+        let batches: Vec<FullBatch> = (1..=3).map(|batch_num| batch(batch_num, &[1])).collect();
+        let api = HermezApi::new(&serve_batches(&batches)).unwrap();
+        let mut indexer = Indexer::open_in_memory(&api).unwrap();
+        for batch in &batches {
+            indexer.store_batch(batch).unwrap();
+        }
+        assert_eq!(
+            None,
+            async_std::task::block_on(indexer.check_rollback(3)).unwrap()
+        );
+
+        // The node forged another batch 3.
+        let mut forked: Vec<FullBatch> = (1..=3).map(|batch_num| batch(batch_num, &[1])).collect();
+        forked[2].batch.state_root = String::from("1");
+        indexer.api = HermezApi::new(&serve_batches(&forked)).unwrap();
+        assert_eq!(
+            Some(3),
+            async_std::task::block_on(indexer.check_rollback(3)).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_check_rollback_node_behind() {
+        let batches: Vec<FullBatch> = (1..=3).map(|batch_num| batch(batch_num, &[1])).collect();
+        let api = HermezApi::new(&serve_batches(&batches[..1])).unwrap();
+        let mut indexer = Indexer::open_in_memory(&api).unwrap();
+        for batch in &batches {
+            indexer.store_batch(batch).unwrap();
+        }
+
+        // A node that has only reached batch 1 agrees on it, and batches 2
+        // and 3 are kept.
+        assert_eq!(
+            None,
+            async_std::task::block_on(indexer.check_rollback(1)).unwrap()
+        );
+        assert_eq!(
+            None,
+            async_std::task::block_on(indexer.check_rollback(0)).unwrap()
+        );
+
+        // Until it forges a different batch 2.
+        let mut forked: Vec<FullBatch> = (1..=2).map(|batch_num| batch(batch_num, &[1])).collect();
+        forked[1].batch.state_root = String::from("1");
+        indexer.api = HermezApi::new(&serve_batches(&forked)).unwrap();
+        assert_eq!(
+            Some(2),
+            async_std::task::block_on(indexer.check_rollback(2)).unwrap()
+        );
+    }
+}
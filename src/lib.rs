@@ -8,6 +8,12 @@
 //!
 //! Note that some of the interactions with the rollup must be done using the Ethereum network directly. Another way to integrate with the rollup is to deploy a node and connect directly to its PostgreSQL database.
 //!
+//! L2 transactions can be signed with a BJJ key, `babyjubjub::PrivateKey`, through `TransactionsPoolPostOptions::sign()`.
+//!
+//! With the `indexer` feature, `indexer::Indexer` keeps a local SQLite copy of the node's data, synced through the API, for queries the API filters cannot express.
+//!
+//! With the `cli` feature, the `hermez` binary gives access to every endpoint from the command line, printing JSON, YAML or a table. Its `wallet` subcommands keep a BJJ key in an encrypted keystore, and sign and send transfers and exits with it.
+//!
 //! # Usage
 //!
 //! All access is done though a `HermezApi` object. Create an instance by calling
@@ -18,6 +24,12 @@
 //! * TESTNET_URL: Use with the Rinkeby test net
 //! * LOCALHOST_URL: Use with a locally hosted node. Use for unit tests.
 //!
+//! `HermezApi` is cheap to clone and is `Send + Sync`, so a single instance can
+//! be shared between tasks.
+//!
+//! The HTTP transport is chosen with one of the features `curl-client` (default),
+//! `h1-client`, `h1-client-rustls` or `hyper-client`.
+//!
 //! # Examples
 //!
 //! ```no_run
@@ -33,14 +45,51 @@
 //! [Hermez Home](https://hermez.io)
 //!
 
+use chrono::{DateTime, Utc};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use url::{ParseError, Url};
 
+use std::collections::HashMap;
 use std::fmt;
-
+use std::sync::Arc;
+
+#[cfg(not(any(
+    feature = "curl-client",
+    feature = "h1-client",
+    feature = "h1-client-rustls",
+    feature = "hyper-client"
+)))]
+compile_error!("hermez-api requires one of the transport features: curl-client, h1-client, h1-client-rustls or hyper-client");
+
+pub mod analytics;
+pub mod auction;
+pub mod babyjubjub;
+mod blake512;
+mod builder;
+mod bulk;
+pub mod cache;
+pub mod cluster;
+pub mod consistency;
+pub mod export;
 mod http;
+#[cfg(feature = "indexer")]
+pub mod indexer;
 mod macros;
+pub mod portfolio;
+mod poseidon;
+pub mod rate_limit;
+pub mod reorg;
+pub mod replay;
+pub mod schedule;
+#[cfg(test)]
+mod test_support;
+pub mod watch;
+pub mod withdrawal;
 
+pub use builder::HermezApiBuilder;
 use http::Http;
+use rate_limit::RateLimitStats;
 
 pub mod account_creation_authorization;
 pub mod accounts;
@@ -52,6 +101,7 @@ pub mod exits;
 pub mod health;
 pub mod slots;
 pub mod state;
+pub mod state_tree;
 pub mod tokens;
 pub mod transactions_history;
 pub mod transactions_pool;
@@ -62,7 +112,7 @@ use batches::{Batch, BatchesGetOptions, FullBatch};
 use bids::BidsGetOptions;
 use config::Config;
 use coordinators::CoordinatorsGetOptions;
-use exits::ExitsGetOptions;
+use exits::{Exit, ExitsGetOptions};
 use health::Health;
 use slots::{Slot, SlotsGetOptions};
 use state::State;
@@ -76,6 +126,8 @@ use transactions_pool::{
 //----------------------------------------------------------------------------
 
 /// Pagination order used with most requests
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
 pub enum PaginationOrder {
     Asc,
     Desc,
@@ -97,7 +149,7 @@ impl fmt::Display for PaginationOrder {
 //----------------------------------------------------------------------------
 
 /// Encapsulates the verious errors that can be encountered
-#[derive(Debug)]
+#[derive(Clone, Debug)]
 pub enum ErrorKind {
     /// Request returned an error, supplies a HTTP response code, and a description.
     Http(u16, String),
@@ -107,6 +159,140 @@ pub enum ErrorKind {
     Json(String),
     /// Error produced by the API.
     Api(String),
+    /// The client could not be configured as requested.
+    Config(String),
+    /// Error from the local database of the indexer.
+    Database(String),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(code, description) => write!(f, "HTTP error {}: {}", code, description),
+            Self::Transport => write!(f, "Request failed"),
+            Self::Json(description) => write!(f, "Invalid JSON: {}", description),
+            Self::Api(description) => write!(f, "API error: {}", description),
+            Self::Config(description) => write!(f, "Configuration error: {}", description),
+            Self::Database(description) => write!(f, "Database error: {}", description),
+        }
+    }
+}
+
+impl std::error::Error for ErrorKind {}
+
+/// Parses an amount in base units, as the node sends it: a decimal string,
+/// since amounts can be larger than a u64.
+pub fn parse_amount(amount: &str) -> Result<u128, ErrorKind> {
+    amount
+        .parse()
+        .map_err(|_| ErrorKind::Api(format!("Invalid amount {}", amount)))
+}
+
+/// An amount in units of the token, like "1.05" for 1050000000000000000 with
+/// 18 decimals.
+pub fn format_amount(amount: u128, decimals: u32) -> String {
+    let digits = format!("{:0width$}", amount, width = decimals as usize + 1);
+    let (units, fraction) = digits.split_at(digits.len() - decimals as usize);
+    let fraction = fraction.trim_end_matches('0');
+    if fraction.is_empty() {
+        units.to_owned()
+    } else {
+        format!("{}.{}", units, fraction)
+    }
+}
+
+/// The inverse of `format_amount()`, 1050000000000000000 for "1.05" with 18
+/// decimals. Amounts with more decimals than the token are rejected.
+pub fn parse_formatted_amount(amount: &str, decimals: u32) -> Result<u128, ErrorKind> {
+    let invalid = || ErrorKind::Api(format!("Invalid amount {}", amount));
+    let (units, fraction) = match amount.split_once('.') {
+        Some((units, fraction)) => (units, fraction),
+        None => (amount, ""),
+    };
+    if fraction.len() > decimals as usize
+        || !units
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+        || units.len() + fraction.len() == 0
+    {
+        return Err(invalid());
+    }
+    let digits = format!("{}{:0<width$}", units, fraction, width = decimals as usize);
+    digits.parse().map_err(|_| invalid())
+}
+
+// Parses a time as the node sends it, in RFC 3339.
+pub(crate) fn parse_timestamp(timestamp: &str) -> Result<DateTime<Utc>, ErrorKind> {
+    DateTime::parse_from_rfc3339(timestamp)
+        .map(|time| time.with_timezone(&Utc))
+        .map_err(|err| ErrorKind::Api(format!("Invalid timestamp {}: {}", timestamp, err)))
+}
+
+// Whether a side of a transaction belongs to `owner`, a `hez:` Ethereum
+// address or BJJ key. Ethereum addresses may differ in case, BJJ keys may not.
+pub(crate) fn is_owner(owner: &str, address: &Option<String>, bjj: &Option<String>) -> bool {
+    matches!(address, Some(address) if address.eq_ignore_ascii_case(owner))
+        || bjj.as_deref() == Some(owner)
+}
+
+//----------------------------------------------------------------------------
+
+/// The endpoints of the API, used to configure per endpoint behaviour such
+/// as rate limits.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Endpoint {
+    Health,
+    Config,
+    Tokens,
+    Accounts,
+    Exits,
+    History,
+    Batches,
+    Slots,
+    Bids,
+    State,
+    Coordinators,
+    AccountCreationAuthorization,
+    /// Getting transactions from the pool.
+    Pool,
+    /// Posting transactions to the pool.
+    PoolPost,
+}
+
+//----------------------------------------------------------------------------
+
+/// An owned description of a request to one of the list endpoints.
+///
+/// Queries hold no reference to the client, so they can be stored, sent to
+/// other tasks, or serialized and rebuilt later. Run one with `HermezApi::run()`.
+pub trait Query {
+    /// The response page returned by the endpoint.
+    type Output: DeserializeOwned;
+
+    /// The endpoint the query is sent to.
+    const ENDPOINT: Endpoint;
+
+    /// Builds the request URL relative to the API base URL, checking that
+    /// the filters are compatible with one another.
+    fn url(&self, base: &Url) -> Result<Url, ErrorKind>;
+}
+
+/// A query on an endpoint that returns its results a page at a time.
+///
+/// Run one with `HermezApi::run_all()` to get every matching item.
+pub trait PagedQuery: Query + Clone {
+    /// The items listed in a page.
+    type Item;
+
+    /// Splits a page into its items and the number of items left after them.
+    fn into_items(page: Self::Output) -> (Vec<Self::Item>, u64);
+
+    fn item_id(item: &Self::Item) -> u64;
+
+    fn order(&self) -> Option<PaginationOrder>;
+
+    fn set_from_item(&mut self, from_item: u64);
 }
 
 //----------------------------------------------------------------------------
@@ -123,9 +309,20 @@ pub enum ErrorKind {
 ///
 /// println!("{}", state.network.current_slot);
 /// ```
+///
+/// `HermezApi` is cheap to clone, and all clones share the same connection.
+/// It is `Send` and `Sync`, so it can be kept in application state or moved
+/// into spawned tasks.
+#[derive(Clone)]
 pub struct HermezApi {
+    inner: Arc<ApiInner>,
+}
+
+struct ApiInner {
     url: Url,
-    http: Http,
+    version: String,
+    http: Arc<Http>,
+    concurrency: usize,
 }
 
 impl HermezApi {
@@ -141,6 +338,10 @@ impl HermezApi {
     /// API version
     pub const VERSION: &'static str = "v1";
 
+    /// Maximum number of requests in flight during bulk lookups, unless set
+    /// with `HermezApiBuilder::concurrency()`.
+    pub const DEFAULT_CONCURRENCY: usize = 8;
+
     /// Constructs a new HermezApi instance
     /// # Examples
     ///
@@ -151,16 +352,175 @@ impl HermezApi {
     /// let api_other = HermezApi::new("https://some.other.domain/hermez").unwrap();
     /// ```
     pub fn new(base_api_url: &str) -> Result<Self, ParseError> {
-        let url_prefix = format!("{}/{}/", base_api_url, Self::VERSION);
-        let url = Url::parse(&url_prefix)?;
+        Self::from_parts(
+            base_api_url,
+            Self::VERSION,
+            Arc::new(http::Http::new()),
+            Self::DEFAULT_CONCURRENCY,
+        )
+    }
+
+    /// Returns a builder to construct a HermezApi instance with custom
+    /// timeouts, headers, proxy or API version.
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use hermez_api::HermezApi;
+    /// use std::time::Duration;
+    ///
+    /// let api = HermezApi::builder(HermezApi::TESTNET_URL)
+    ///     .timeout(Duration::from_secs(10))
+    ///     .user_agent("my-wallet/0.1")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    pub fn builder(base_api_url: &str) -> HermezApiBuilder {
+        HermezApiBuilder::new(base_api_url)
+    }
+
+    /// URL of the API, including the version.
+    pub fn url(&self) -> &Url {
+        &self.inner.url
+    }
+
+    /// Returns a HermezApi instance for another node, such as a coordinator,
+    /// sharing this instance's settings and connections.
+    pub fn with_base_url(&self, base_api_url: &str) -> Result<Self, ParseError> {
+        Self::from_parts(
+            base_api_url,
+            &self.inner.version,
+            self.inner.http.clone(),
+            self.inner.concurrency,
+        )
+    }
+
+    fn from_parts(
+        base_api_url: &str,
+        version: &str,
+        http: Arc<Http>,
+        concurrency: usize,
+    ) -> Result<Self, ParseError> {
+        let url = Url::parse(&format!("{}/{}/", base_api_url, version))?;
         Ok(Self {
-            url,
-            http: http::Http::new(),
+            inner: Arc::new(ApiInner {
+                url,
+                version: version.to_owned(),
+                http,
+                concurrency,
+            }),
         })
     }
 
+    /// Returns the number of requests sent, and the time spent waiting for
+    /// the rate limiter, for each endpoint. Empty when no rate limit has been set.
+    pub fn rate_limit_stats(&self) -> HashMap<Endpoint, RateLimitStats> {
+        self.inner.http.rate_limit_stats()
+    }
+
+    /// Removes the cached responses of an endpoint. Does nothing when no
+    /// cache has been set.
+    pub fn invalidate_cache(&self, endpoint: Endpoint) {
+        let paths: &[&str] = match endpoint {
+            Endpoint::Batches => &["batches/", "full-batches/"],
+            Endpoint::Tokens => &["tokens/"],
+            Endpoint::Config => &["config"],
+            Endpoint::State => &["state"],
+            _ => &[],
+        };
+        if let Some(cache) = self.inner.http.cache() {
+            let prefixes: Vec<String> = paths
+                .iter()
+                .map(|path| self.inner.url.join(path).unwrap().to_string())
+                .collect();
+            cache.remove_if(|key| prefixes.iter().any(|prefix| key.starts_with(prefix)));
+        }
+    }
+
+    /// Removes the cached batches, and full batches, numbered `batch_num` or above.
+    pub fn invalidate_batches_from(&self, batch_num: u32) {
+        if let Some(cache) = self.inner.http.cache() {
+            let prefixes = [
+                self.inner.url.join("batches/").unwrap().to_string(),
+                self.inner.url.join("full-batches/").unwrap().to_string(),
+            ];
+            cache.remove_if(|key| {
+                prefixes.iter().any(|prefix| {
+                    key.strip_prefix(prefix.as_str())
+                        .and_then(|num| num.parse::<u32>().ok())
+                        .is_some_and(|num| num >= batch_num)
+                })
+            });
+        }
+    }
+
+    /// Removes every cached response.
+    pub fn clear_cache(&self) {
+        if let Some(cache) = self.inner.http.cache() {
+            cache.clear();
+        }
+    }
+
     //-----------------------------------------------------
 
+    /// Runs a query against the node.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use hermez_api::HermezApi;
+    /// use hermez_api::accounts::AccountsQuery;
+    ///
+    /// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+    /// let query = AccountsQuery {
+    ///     token_ids: Some(vec![0, 1]),
+    ///     ..Default::default()
+    /// };
+    /// let page = api.run(&query).await.unwrap();
+    ///
+    /// println!("{:?}, {}", page.accounts, page.pending_items);
+    /// ```
+    pub async fn run<Q: Query>(&self, query: &Q) -> Result<Q::Output, ErrorKind> {
+        self.inner
+            .http
+            .get(Q::ENDPOINT, &query.url(&self.inner.url)?)
+            .await
+    }
+
+    /// Runs a query, following the pages until every matching item is fetched.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use hermez_api::bids::BidsQuery;
+    /// use hermez_api::HermezApi;
+    ///
+    /// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+    /// let query = BidsQuery {
+    ///     slot_num: Some(12400),
+    ///     ..Default::default()
+    /// };
+    /// let bids = api.run_all(&query).await.unwrap();
+    /// ```
+    pub async fn run_all<Q: PagedQuery>(&self, query: &Q) -> Result<Vec<Q::Item>, ErrorKind> {
+        let mut query = query.clone();
+        let mut items = Vec::new();
+        loop {
+            let (page, pending_items) = Q::into_items(self.run(&query).await?);
+            let next_item = match (page.last(), query.order()) {
+                (Some(last), Some(PaginationOrder::Desc)) => Q::item_id(last).checked_sub(1),
+                (Some(last), _) => Some(Q::item_id(last) + 1),
+                (None, _) => None,
+            };
+            items.extend(page);
+            match next_item {
+                Some(next_item) if pending_items > 0 => query.set_from_item(next_item),
+                _ => break,
+            }
+        }
+        Ok(items)
+    }
+
     /// Returns the health status of the hermez node
     ///
     /// # Example
@@ -174,7 +534,10 @@ impl HermezApi {
     /// println!("{}, {}", health.status, health.version);
     /// ```
     pub async fn get_health(&self) -> Result<Health, ErrorKind> {
-        self.http.get(&self.url.join("health").unwrap()).await
+        self.inner
+            .http
+            .get(Endpoint::Health, &self.inner.url.join("health").unwrap())
+            .await
     }
 
     /// Returns a builder for the bids endpoint.
@@ -192,8 +555,8 @@ impl HermezApi {
     ///
     /// println!("{:?}", bids);
     /// ```
-    pub fn bids_get_options(&self) -> BidsGetOptions<'_> {
-        BidsGetOptions::new(&self.http, &self.url)
+    pub fn bids_get_options(&self) -> BidsGetOptions {
+        BidsGetOptions::new(self)
     }
 
     /// Return constant configuration of the network
@@ -209,7 +572,10 @@ impl HermezApi {
     /// println!("{:?}", config);
     /// ```
     pub async fn get_config(&self) -> Result<Config, ErrorKind> {
-        self.http.get(&self.url.join("config").unwrap()).await
+        self.inner
+            .http
+            .get_cached(Endpoint::Config, &self.inner.url.join("config").unwrap())
+            .await
     }
 
     /// Get information of a token supported by Hermez Network.
@@ -225,8 +591,16 @@ impl HermezApi {
     /// println!("{:?}", config);
     /// ```
     pub async fn get_token(&self, token_id: u32) -> Result<Token, ErrorKind> {
-        self.http
-            .get(&self.url.join(&format!("tokens/{}", token_id)).unwrap())
+        self.inner
+            .http
+            .get_cached(
+                Endpoint::Tokens,
+                &self
+                    .inner
+                    .url
+                    .join(&format!("tokens/{}", token_id))
+                    .unwrap(),
+            )
             .await
     }
 
@@ -242,15 +616,18 @@ impl HermezApi {
     ///
     /// println!("{:?}", tokens);
     /// ```
-    pub fn tokens_get_options(&self) -> TokensGetOptions<'_> {
-        TokensGetOptions::new(&self.http, &self.url)
+    pub fn tokens_get_options(&self) -> TokensGetOptions {
+        TokensGetOptions::new(self)
     }
 
     /// Get an account by its index.
     pub async fn get_account(&self, account_index: &str) -> Result<Account, ErrorKind> {
-        self.http
+        self.inner
+            .http
             .get(
+                Endpoint::Accounts,
                 &self
+                    .inner
                     .url
                     .join(&format!("accounts/{}", account_index))
                     .unwrap(),
@@ -262,21 +639,20 @@ impl HermezApi {
     ///
     /// The following parameters are incompatible with one another (cannot set more than one).
     /// * hez_ethereum_address and bjj
-    pub fn accounts_get_options(&self) -> AccountsGetOptions<'_> {
-        AccountsGetOptions::new(&self.http, &self.url)
+    pub fn accounts_get_options(&self) -> AccountsGetOptions {
+        AccountsGetOptions::new(self)
     }
 
     /// Get specific exit information.
     ///
     /// Get exit information form a specific exit tree and account. This information is required to perform a withdraw. Exits are identified with accounIndex and batchNum since every batch that has exits has a different exit tree.
-    pub async fn get_exit(
-        &self,
-        batch_num: u32,
-        account_index: &str,
-    ) -> Result<Account, ErrorKind> {
-        self.http
+    pub async fn get_exit(&self, batch_num: u32, account_index: &str) -> Result<Exit, ErrorKind> {
+        self.inner
+            .http
             .get(
+                Endpoint::Exits,
                 &self
+                    .inner
                     .url
                     .join(&format!("exits/{}/{}", batch_num, account_index))
                     .unwrap(),
@@ -289,8 +665,8 @@ impl HermezApi {
     /// The following parameters are incompatible with one another (cannot set more than one)
     /// * bjj, hez_ethereum address and account_index
     /// * token_id and account_index
-    pub fn exits_get_options(&self) -> ExitsGetOptions<'_> {
-        ExitsGetOptions::new(&self.http, &self.url)
+    pub fn exits_get_options(&self) -> ExitsGetOptions {
+        ExitsGetOptions::new(self)
     }
 
     /// Get details and status of a historical transaction.
@@ -299,9 +675,12 @@ impl HermezApi {
     /// types of transactions except those that are still in the pool of any
     /// coordinator.
     pub async fn get_transaction_history(&self, id: &str) -> Result<HistoryTransaction, ErrorKind> {
-        self.http
+        self.inner
+            .http
             .get(
+                Endpoint::History,
                 &self
+                    .inner
                     .url
                     .join(&format!("transaction-history/{}", id))
                     .unwrap(),
@@ -315,14 +694,22 @@ impl HermezApi {
     /// types of forged transactions, this means that:
     /// * Transactions that are still in the transaction pool of any coordinator are not included. These transactions can be fetched using HermezApi::get_transactions_pool().
     /// * L1 transactions sent by users that have not been forged yet are not included. These transactions can be fetched using HermezApi::get_transactions_history().
-    pub fn transactions_history_get_options(&self) -> TransactionsHistoryGetOptions<'_> {
-        TransactionsHistoryGetOptions::new(&self.http, &self.url)
+    pub fn transactions_history_get_options(&self) -> TransactionsHistoryGetOptions {
+        TransactionsHistoryGetOptions::new(self)
     }
 
     /// Get a specific batch.
     pub async fn get_batch(&self, batch_num: u32) -> Result<Batch, ErrorKind> {
-        self.http
-            .get(&self.url.join(&format!("batches/{}", batch_num)).unwrap())
+        self.inner
+            .http
+            .get_cached(
+                Endpoint::Batches,
+                &self
+                    .inner
+                    .url
+                    .join(&format!("batches/{}", batch_num))
+                    .unwrap(),
+            )
             .await
     }
 
@@ -333,9 +720,12 @@ impl HermezApi {
     /// devloping a front end, you may consider using a combinaton of get_batch()
     /// and get_transaction_history.
     pub async fn get_full_batch(&self, batch_num: u32) -> Result<FullBatch, ErrorKind> {
-        self.http
-            .get(
+        self.inner
+            .http
+            .get_cached(
+                Endpoint::Batches,
                 &self
+                    .inner
                     .url
                     .join(&format!("full-batches/{}", batch_num))
                     .unwrap(),
@@ -344,20 +734,24 @@ impl HermezApi {
     }
 
     /// Get information about forged batches.
-    pub fn batches_get_options(&self) -> BatchesGetOptions<'_> {
-        BatchesGetOptions::new(&self.http, &self.url)
+    pub fn batches_get_options(&self) -> BatchesGetOptions {
+        BatchesGetOptions::new(self)
     }
 
     /// Get information about a specific slot.
     pub async fn get_slot(&self, slot_num: u32) -> Result<Slot, ErrorKind> {
-        self.http
-            .get(&self.url.join(&format!("slots/{}", slot_num)).unwrap())
+        self.inner
+            .http
+            .get(
+                Endpoint::Slots,
+                &self.inner.url.join(&format!("slots/{}", slot_num)).unwrap(),
+            )
             .await
     }
 
     /// Get information about slots.
-    pub fn slots_get_options(&self) -> SlotsGetOptions<'_> {
-        SlotsGetOptions::new(&self.http, &self.url)
+    pub fn slots_get_options(&self) -> SlotsGetOptions {
+        SlotsGetOptions::new(self)
     }
 
     /// Return information that represents the current state of the network.
@@ -375,21 +769,27 @@ impl HermezApi {
     /// println!("{}", state.network.current_slot);
     /// ```
     pub async fn get_state(&self) -> Result<State, ErrorKind> {
-        self.http.get(&self.url.join("state").unwrap()).await
+        self.inner
+            .http
+            .get_cached(Endpoint::State, &self.inner.url.join("state").unwrap())
+            .await
     }
 
     /// Get information about coordinators.
-    pub fn coordinators_get_options(&self) -> CoordinatorsGetOptions<'_> {
-        CoordinatorsGetOptions::new(&self.http, &self.url)
+    pub fn coordinators_get_options(&self) -> CoordinatorsGetOptions {
+        CoordinatorsGetOptions::new(self)
     }
 
     pub async fn get_account_creation_authorization(
         &self,
         hez_ethereum_address: &str,
     ) -> Result<AccountCreationAuthorization, ErrorKind> {
-        self.http
+        self.inner
+            .http
             .get(
+                Endpoint::AccountCreationAuthorization,
                 &self
+                    .inner
                     .url
                     .join(&format!(
                         "account-creation-authorization/{}",
@@ -406,11 +806,14 @@ impl HermezApi {
         bjj: &str,
         signature: &str,
     ) -> Result<(), ErrorKind> {
-        self.http
+        self.inner
+            .http
             .post::<PostAccoutCreation, Success>(
+                Endpoint::AccountCreationAuthorization,
                 &self
+                    .inner
                     .url
-                    .join(&format!("account-creation-authorization"))
+                    .join("account-creation-authorization")
                     .unwrap(),
                 &PostAccoutCreation {
                     hez_ethereum_address,
@@ -423,29 +826,40 @@ impl HermezApi {
     }
 
     pub async fn get_transactions_pool(&self, id: &str) -> Result<PoolL2Transaction, ErrorKind> {
-        self.http
-            .get(&self.url.join(&format!("transactions_pool/{}", id)).unwrap())
+        self.inner
+            .http
+            .get(
+                Endpoint::Pool,
+                &self
+                    .inner
+                    .url
+                    .join(&format!("transactions-pool/{}", id))
+                    .unwrap(),
+            )
             .await
     }
 
-    pub fn transactions_pool_get_options(&self) -> TransactionsPoolGetOptions<'_> {
-        TransactionsPoolGetOptions::new(&self.http, &self.url)
+    pub fn transactions_pool_get_options(&self) -> TransactionsPoolGetOptions {
+        TransactionsPoolGetOptions::new(self)
     }
 
-    pub fn transactions_pool_post_options(&self) -> TransactionsPoolPostOptions<'_> {
-        TransactionsPoolPostOptions::new(&self.http, &self.url)
+    pub fn transactions_pool_post_options(&self) -> TransactionsPoolPostOptions {
+        TransactionsPoolPostOptions::new(self)
     }
 }
 
 pub mod prelude {
     pub use super::HermezApi;
+    pub use super::HermezApiBuilder;
+    pub use super::PagedQuery;
+    pub use super::Query;
     #[doc(no_inline)]
     pub use url::ParseError;
 }
 
 #[cfg(test)]
 mod tests {
-    use super::HermezApi;
+    use super::{format_amount, parse_formatted_amount, HermezApi};
 
     #[test]
     fn test_make_api() {
@@ -456,7 +870,7 @@ mod tests {
 
         assert_eq!(
             format!("{}/{}/", API_BASE_URL, API_VERSION),
-            api.url.as_str()
+            api.inner.url.as_str()
         );
     }
 
@@ -464,4 +878,82 @@ mod tests {
     fn test_make_api_with_bad_url() {
         assert!(HermezApi::new("xyz").is_err());
     }
+
+    #[test]
+    fn test_send_sync() {
+        fn assert_send<T: Send>(_: T) {}
+        fn assert_send_sync<T: Send + Sync + Clone + 'static>() {}
+
+        assert_send_sync::<HermezApi>();
+
+        let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+
+        assert_send(api.get_state());
+        assert_send(api.get_full_batch(1));
+        assert_send(api.run(&super::accounts::AccountsQuery::default()));
+        assert_send(api.get_accounts_many(&["hez:ETH:256"]));
+        assert_send(api.get_tokens_many(&[0, 1]));
+
+        let cluster = super::cluster::HermezCluster::from_nodes(vec![api.clone()]);
+        assert_send(cluster.get_account("hez:ETH:256"));
+        assert_send(cluster.refresh());
+
+        let options = api.transactions_history_get_options();
+        assert_send(options.fetch());
+
+        let mut options = api.transactions_pool_post_options();
+        options.id("0x00");
+        assert_send(options.fetch());
+        assert_send(options.send_to_next_forgers());
+
+        let check = super::consistency::ConsistencyCheck::new(std::slice::from_ref(&api));
+        assert_send(check.run());
+
+        assert_send(api.watch_batches(1).stream_full());
+        assert_send(api.watch_accounts(Default::default()).stream());
+        assert_send(super::reorg::ReorgDetector::new(&api).stream());
+    }
+
+    #[test]
+    fn test_query() {
+        use super::exits::ExitsQuery;
+        use super::{PaginationOrder, Query};
+
+        let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+
+        let query = ExitsQuery {
+            hez_ethereum_address: Some("hez:0xaa942cfcd25ad4d90a62358b0dd84f33b398262a".to_owned()),
+            only_pending_withdraws: Some(true),
+            order: Some(PaginationOrder::Desc),
+            ..Default::default()
+        };
+
+        let json = serde_json::to_string(&query).unwrap();
+        let query: ExitsQuery = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(
+            "https://api.testnet.hermez.io/v1/exits?hezEthereumAddress=hez%3A0xaa942cfcd25ad4d90a62358b0dd84f33b398262a&onlyPendingWithdraws=true&order=DESC",
+            query.url(&api.inner.url).unwrap().as_str()
+        );
+
+        let query: ExitsQuery =
+            serde_json::from_str(r#"{"account_index":"hez:ETH:256","bjj":"hez:dEZ"}"#).unwrap();
+        assert!(query.url(&api.inner.url).is_err());
+    }
+
+    #[test]
+    fn test_amounts() {
+        assert_eq!(
+            1050000000000000000,
+            parse_formatted_amount("1.05", 18).unwrap()
+        );
+        assert_eq!(7, parse_formatted_amount("7", 0).unwrap());
+        assert_eq!(
+            "1.05",
+            format_amount(parse_formatted_amount("1.050", 18).unwrap(), 18)
+        );
+        assert!(parse_formatted_amount("0.001", 2).is_err());
+        assert!(parse_formatted_amount("1e3", 18).is_err());
+        assert!(parse_formatted_amount(".", 18).is_err());
+    }
 }
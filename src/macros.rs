@@ -3,36 +3,36 @@
 // Internal use only
 
 macro_rules! setter {
-    ($name: ident, &'a [u32]) => {
-        pub fn $name(&mut self, $name: &'a [u32]) -> &mut Self {
-            self.$name = Some($name);
+    ($name: ident, &[u32]) => {
+        pub fn $name(&mut self, $name: &[u32]) -> &mut Self {
+            self.query.$name = Some($name.to_vec());
             self
         }
     };
-    ($name: ident, &'a [&'a str]) => {
-        pub fn $name(&mut self, $name: &'a [&'a str]) -> &mut Self {
-            self.$name = Some($name);
+    ($name: ident, &[&str]) => {
+        pub fn $name(&mut self, $name: &[&str]) -> &mut Self {
+            self.query.$name = Some($name.iter().map(|s| s.to_string()).collect());
             self
         }
     };
-    ($name: ident, &'a str) => {
-        pub fn $name(&mut self, $name: &'a str) -> &mut Self {
-            self.$name = Some($name);
+    ($name: ident, &str) => {
+        pub fn $name(&mut self, $name: &str) -> &mut Self {
+            self.query.$name = Some($name.to_owned());
             self
         }
     };
     ($name: ident, $type: ident) => {
         pub fn $name(&mut self, $name: $type) -> &mut Self {
-            self.$name = Some($name);
+            self.query.$name = Some($name);
             self
         }
     };
 }
 
 macro_rules! setter_body {
-    ($name: ident, &'a str) => {
-        pub fn $name(&mut self, $name: &'a str) -> &mut Self {
-            self.body.$name = Some($name);
+    ($name: ident, &str) => {
+        pub fn $name(&mut self, $name: &str) -> &mut Self {
+            self.body.$name = Some($name.to_owned());
             self
         }
     };
@@ -86,6 +86,30 @@ macro_rules! pagination_fetch_stmts {
     };
 }
 
+macro_rules! paged_query {
+    ($query: ident, $item: ident, $items: ident) => {
+        impl $crate::PagedQuery for $query {
+            type Item = $item;
+
+            fn into_items(page: Self::Output) -> (Vec<$item>, u64) {
+                (page.$items, page.pending_items)
+            }
+
+            fn item_id(item: &$item) -> u64 {
+                item.item_id
+            }
+
+            fn order(&self) -> Option<$crate::PaginationOrder> {
+                self.order
+            }
+
+            fn set_from_item(&mut self, from_item: u64) {
+                self.from_item = Some(from_item);
+            }
+        }
+    };
+}
+
 macro_rules! is_more_than_one_defined {
     ( $( $x:expr ),+ ) => {
         (
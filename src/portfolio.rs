@@ -0,0 +1,335 @@
+//! The holdings of an address across its accounts, valued in USD.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use super::accounts::{Account, AccountsQuery};
+use super::tokens::{Token, TokensQuery};
+use super::transactions_pool::{fee_amount, PoolL2Transaction, PoolL2TransactionState, PoolQuery};
+use super::{is_owner, parse_amount, parse_timestamp, ErrorKind, HermezApi};
+
+/// The holdings of one token, over all the accounts of the owner in it.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TokenHolding {
+    pub token: Token,
+    /// Account indexes of the owner for the token.
+    pub accounts: Vec<String>,
+    pub balance: u128,
+    /// Amounts of the transactions in the pool not forged yet. Outgoing
+    /// amounts include their fee.
+    pub pending_incoming: u128,
+    pub pending_outgoing: u128,
+    /// USD values, `None` if the token has no price.
+    pub value_usd: Option<f64>,
+    pub pending_incoming_usd: Option<f64>,
+    pub pending_outgoing_usd: Option<f64>,
+    /// When the price was last updated.
+    pub price_updated: Option<DateTime<Utc>>,
+    /// The token has a price, but it has not been updated recently.
+    pub stale_price: bool,
+}
+
+/// The holdings of an Ethereum address or BJJ key, by token id.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Portfolio {
+    pub owner: String,
+    /// Time the prices are checked against.
+    pub as_of: DateTime<Utc>,
+    pub holdings: BTreeMap<u32, TokenHolding>,
+    /// Sums of the USD values of the tokens with a price.
+    pub total_usd: f64,
+    pub pending_incoming_usd: f64,
+    pub pending_outgoing_usd: f64,
+}
+
+impl Portfolio {
+    /// Prices not updated for longer are flagged as stale by
+    /// `HermezApi::portfolio()`.
+    pub const DEFAULT_MAX_PRICE_AGE: Duration = Duration::from_secs(3600);
+
+    /// Builds the portfolio of `owner` from its accounts, the tokens they
+    /// hold, and the transactions of the pool involving it.
+    ///
+    /// Tokens in `tokens` take precedence over those embedded in accounts
+    /// and transactions, as their prices may be more recent.
+    pub fn new(
+        owner: &str,
+        accounts: &[Account],
+        tokens: &[Token],
+        pool: &[PoolL2Transaction],
+        as_of: DateTime<Utc>,
+    ) -> Result<Self, ErrorKind> {
+        let tokens: BTreeMap<u32, &Token> = tokens.iter().map(|token| (token.id, token)).collect();
+        let mut holdings = BTreeMap::new();
+
+        for account in accounts {
+            let holding = holding(&mut holdings, &tokens, &account.token);
+            holding.accounts.push(account.account_index.clone());
+            holding.balance += parse_amount(&account.balance)?;
+        }
+
+        let pending = pool.iter().filter(|tx| {
+            matches!(
+                tx.state,
+                PoolL2TransactionState::Pend | PoolL2TransactionState::Fing
+            )
+        });
+        for tx in pending {
+            let amount = parse_amount(&tx.amount)?;
+            let holding = holding(&mut holdings, &tokens, &tx.token);
+            if is_owner(owner, &tx.from_hez_ethereum_address, &tx.from_bjj) {
+                let fee = fee_amount(amount, tx.fee as u32).ok_or_else(|| {
+                    ErrorKind::Api(format!("Fee {} of {} overflows", tx.fee, tx.amount))
+                })?;
+                holding.pending_outgoing += amount + fee;
+            }
+            if is_owner(owner, &tx.to_hez_ethereum_address, &tx.to_bjj) {
+                holding.pending_incoming += amount;
+            }
+        }
+
+        let mut portfolio = Self {
+            owner: owner.to_owned(),
+            as_of,
+            holdings,
+            total_usd: 0.0,
+            pending_incoming_usd: 0.0,
+            pending_outgoing_usd: 0.0,
+        };
+        for holding in portfolio.holdings.values_mut() {
+            let token = &holding.token;
+            if let Some(usd) = token.usd {
+                let value = |amount: u128| amount as f64 / 10f64.powi(token.decimals as i32) * usd;
+                holding.value_usd = Some(value(holding.balance));
+                holding.pending_incoming_usd = Some(value(holding.pending_incoming));
+                holding.pending_outgoing_usd = Some(value(holding.pending_outgoing));
+                portfolio.total_usd += value(holding.balance);
+                portfolio.pending_incoming_usd += value(holding.pending_incoming);
+                portfolio.pending_outgoing_usd += value(holding.pending_outgoing);
+            }
+            holding.price_updated = match &token.fiat_update {
+                Some(fiat_update) => Some(parse_timestamp(fiat_update)?),
+                None => None,
+            };
+        }
+        portfolio.mark_stale_prices(Self::DEFAULT_MAX_PRICE_AGE);
+        Ok(portfolio)
+    }
+
+    /// Flags again the prices updated longer than `max_price_age` before
+    /// `as_of`, or never.
+    pub fn mark_stale_prices(&mut self, max_price_age: Duration) -> &mut Self {
+        let max_price_age =
+            chrono::Duration::from_std(max_price_age).unwrap_or(chrono::Duration::MAX);
+        for holding in self.holdings.values_mut() {
+            holding.stale_price = holding.token.usd.is_some()
+                && match holding.price_updated {
+                    Some(updated) => self.as_of.signed_duration_since(updated) > max_price_age,
+                    None => true,
+                };
+        }
+        self
+    }
+
+    /// Whether any of the prices used is stale.
+    pub fn has_stale_prices(&self) -> bool {
+        self.holdings.values().any(|holding| holding.stale_price)
+    }
+}
+
+impl HermezApi {
+    /// Get the accounts of an Ethereum address or BJJ key, the tokens they
+    /// hold and the transactions pending in the pool, and value them in USD.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use hermez_api::HermezApi;
+    ///
+    /// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+    /// let portfolio = api
+    ///     .portfolio("hez:0xaa942cfcd25ad4d90a62358b0dd84f33b398262a")
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// for holding in portfolio.holdings.values() {
+    ///     println!("{}: {:?} USD", holding.token.symbol, holding.value_usd);
+    /// }
+    /// println!("Total: {} USD", portfolio.total_usd);
+    /// ```
+    pub async fn portfolio(&self, owner: &str) -> Result<Portfolio, ErrorKind> {
+        let mut accounts_query = AccountsQuery::default();
+        let mut pool_query = PoolQuery::default();
+        if owner.starts_with("hez:0x") {
+            accounts_query.hez_ethereum_address = Some(owner.to_owned());
+            pool_query.hez_ethereum_address = Some(owner.to_owned());
+        } else {
+            accounts_query.bjj = Some(owner.to_owned());
+            pool_query.bjj = Some(owner.to_owned());
+        }
+        let pending_query = PoolQuery {
+            state: Some(PoolL2TransactionState::Pend),
+            ..pool_query.clone()
+        };
+        let forging_query = PoolQuery {
+            state: Some(PoolL2TransactionState::Fing),
+            ..pool_query
+        };
+        let (accounts, pending, forging) = futures::join!(
+            self.run_all(&accounts_query),
+            self.run_all(&pending_query),
+            self.run_all(&forging_query)
+        );
+        let accounts = accounts?;
+        let mut pool = pending?;
+        pool.extend(forging?);
+
+        let mut token_ids: Vec<u32> = accounts
+            .iter()
+            .map(|account| account.token.id)
+            .chain(pool.iter().map(|tx| tx.token.id))
+            .collect();
+        token_ids.sort_unstable();
+        token_ids.dedup();
+        let tokens = if token_ids.is_empty() {
+            Vec::new()
+        } else {
+            let query = TokensQuery {
+                ids: Some(token_ids),
+                ..Default::default()
+            };
+            self.run_all(&query).await?
+        };
+
+        Portfolio::new(owner, &accounts, &tokens, &pool, Utc::now())
+    }
+}
+
+// The holding of a token, added if missing.
+fn holding<'a>(
+    holdings: &'a mut BTreeMap<u32, TokenHolding>,
+    tokens: &BTreeMap<u32, &Token>,
+    token: &Token,
+) -> &'a mut TokenHolding {
+    holdings.entry(token.id).or_insert_with(|| TokenHolding {
+        token: tokens.get(&token.id).copied().unwrap_or(token).clone(),
+        accounts: Vec::new(),
+        balance: 0,
+        pending_incoming: 0,
+        pending_outgoing: 0,
+        value_usd: None,
+        pending_incoming_usd: None,
+        pending_outgoing_usd: None,
+        price_updated: None,
+        stale_price: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Portfolio;
+    use crate::accounts::Account;
+    use crate::tokens::Token;
+    use crate::transactions_pool::PoolL2Transaction;
+    use chrono::{TimeZone, Utc};
+    use std::time::Duration;
+
+    const OWNER: &str = "hez:0xaa942cfcd25ad4d90a62358b0dd84f33b398262a";
+    const OTHER: &str = "hez:0x0000000000000000000000000000000000000001";
+
+    fn token(id: u32, usd: Option<f64>, fiat_update: &str) -> serde_json::Value {
+        serde_json::json!({
+            "id": id,
+            "ethereumAddress": "0x0000000000000000000000000000000000000000",
+            "itemId": id,
+            "name": "Token",
+            "symbol": format!("TK{}", id),
+            "decimals": 18,
+            "ethereumBlockNum": 0,
+            "USD": usd,
+            "fiatUpdate": fiat_update,
+        })
+    }
+
+    fn account(account_index: &str, token_id: u32, balance: &str) -> Account {
+        serde_json::from_value(serde_json::json!({
+            "itemId": 1,
+            "accountIndex": account_index,
+            "nonce": 0,
+            "balance": balance,
+            "bjj": "hez:rR7LXKal-av7I56Y0dEBCVmwc9zpoLY5ERhy5w7G-xwe",
+            "hezEthereumAddress": OWNER,
+            "token": token(token_id, None, "2021-03-10T12:00:00Z"),
+        }))
+        .unwrap()
+    }
+
+    fn pool_tx(from: &str, to: &str, amount: &str, state: &str) -> PoolL2Transaction {
+        serde_json::from_value(serde_json::json!({
+            "id": "0x02",
+            "itemId": 1,
+            "type": "Transfer",
+            "fromAccountIndex": "hez:TK0:256",
+            "fromHezEthereumAddress": from,
+            "fromBJJ": null,
+            "toAccountIndex": "hez:TK0:257",
+            "toHezEthereumAddress": to,
+            "toBJJ": null,
+            "amount": amount,
+            "fee": 0,
+            "nonce": 0,
+            "state": state,
+            "info": "",
+            "requestFromAccountIndex": "",
+            "requestFromHezEthereumAddress": null,
+            "requestFromBJJ": null,
+            "requestToAccountIndex": null,
+            "requestToHezEthereumAddress": null,
+            "requestToBJJ": null,
+            "requestAmount": null,
+            "requestFee": null,
+            "requestNonce": null,
+            "token": token(0, None, "2021-03-10T12:00:00Z"),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_portfolio() {
+        let tokens: Vec<Token> = vec![
+            serde_json::from_value(token(0, Some(2000.0), "2021-03-10T11:30:00Z")).unwrap(),
+            serde_json::from_value(token(1, Some(1.0), "2021-03-09T12:00:00Z")).unwrap(),
+        ];
+        let accounts = [
+            account("hez:TK0:256", 0, "1000000000000000000"),
+            account("hez:TK0:300", 0, "500000000000000000"),
+            account("hez:TK1:301", 1, "3000000000000000000"),
+        ];
+        let pool = [
+            pool_tx(OWNER, OTHER, "100000000000000000", "pend"),
+            pool_tx(OTHER, OWNER, "200000000000000000", "fing"),
+            pool_tx(OWNER, OTHER, "300000000000000000", "invl"),
+        ];
+        let as_of = Utc.with_ymd_and_hms(2021, 3, 10, 12, 0, 0).unwrap();
+        let mut portfolio = Portfolio::new(OWNER, &accounts, &tokens, &pool, as_of).unwrap();
+
+        let eth = &portfolio.holdings[&0];
+        assert_eq!(2, eth.accounts.len());
+        assert_eq!(1_500_000_000_000_000_000, eth.balance);
+        assert_eq!(100_000_000_000_000_000, eth.pending_outgoing);
+        assert_eq!(200_000_000_000_000_000, eth.pending_incoming);
+        assert_eq!(Some(3000.0), eth.value_usd);
+        assert!(!eth.stale_price);
+        assert!(portfolio.holdings[&1].stale_price);
+        assert_eq!(3003.0, portfolio.total_usd);
+        assert_eq!(400.0, portfolio.pending_incoming_usd);
+
+        portfolio.mark_stale_prices(Duration::from_secs(2 * 86400));
+        assert!(!portfolio.has_stale_prices());
+        portfolio.mark_stale_prices(Duration::from_secs(60));
+        assert!(portfolio.holdings[&0].stale_price);
+    }
+}
@@ -0,0 +1,204 @@
+// The Poseidon hash over the scalar field of BN254, as used by circomlib and
+// the Hermez circuits.
+//
+// Round constants and MDS matrices are not copied in: they are generated with
+// the Grain LFSR of the reference implementation, from the same parameters,
+// which gives the published constants.
+
+use num_bigint::BigUint;
+
+use std::collections::HashMap;
+use std::sync::{Mutex, OnceLock};
+
+const MODULUS: &str =
+    "21888242871839275222246405745257275088548364400416034343698204186575808495617";
+const FIELD_BITS: usize = 254;
+const FULL_ROUNDS: usize = 8;
+// Partial rounds for t = 2, 3, ... 17.
+const PARTIAL_ROUNDS: [usize; 16] = [
+    56, 57, 56, 60, 60, 63, 64, 63, 60, 66, 60, 65, 70, 60, 64, 68,
+];
+
+pub(crate) fn modulus() -> &'static BigUint {
+    static MODULUS_INT: OnceLock<BigUint> = OnceLock::new();
+    MODULUS_INT.get_or_init(|| MODULUS.parse().unwrap())
+}
+
+struct Constants {
+    round_constants: Vec<BigUint>,
+    mds: Vec<Vec<BigUint>>,
+}
+
+/// Hashes 1 to 16 field elements, which must be below the modulus.
+pub(crate) fn poseidon(inputs: &[BigUint]) -> BigUint {
+    assert!(!inputs.is_empty() && inputs.len() <= PARTIAL_ROUNDS.len());
+    let t = inputs.len() + 1;
+    let constants = constants(t);
+    let p = modulus();
+    let partial_rounds = PARTIAL_ROUNDS[t - 2];
+
+    let mut state = Vec::with_capacity(t);
+    state.push(BigUint::from(0u32));
+    state.extend(inputs.iter().cloned());
+
+    for round in 0..FULL_ROUNDS + partial_rounds {
+        for (i, element) in state.iter_mut().enumerate() {
+            *element = (&*element + &constants.round_constants[round * t + i]) % p;
+        }
+        let full = round < FULL_ROUNDS / 2 || round >= FULL_ROUNDS / 2 + partial_rounds;
+        for element in state.iter_mut().take(if full { t } else { 1 }) {
+            let square = &*element * &*element % p;
+            *element = &square * &square % p * &*element % p;
+        }
+        state = constants
+            .mds
+            .iter()
+            .map(|row| row.iter().zip(&state).map(|(m, s)| m * s).sum::<BigUint>() % p)
+            .collect();
+    }
+    state.swap_remove(0)
+}
+
+fn constants(t: usize) -> &'static Constants {
+    static CONSTANTS: OnceLock<Mutex<HashMap<usize, &'static Constants>>> = OnceLock::new();
+    let mut constants = CONSTANTS
+        .get_or_init(|| Mutex::new(HashMap::new()))
+        .lock()
+        .unwrap();
+    constants
+        .entry(t)
+        .or_insert_with(|| Box::leak(Box::new(generate(t))))
+}
+
+fn generate(t: usize) -> Constants {
+    let p = modulus();
+    let partial_rounds = PARTIAL_ROUNDS[t - 2];
+    let mut grain = Grain::new(t, partial_rounds);
+
+    let round_constants = (0..(FULL_ROUNDS + partial_rounds) * t)
+        .map(|_| loop {
+            let value = grain.next_int();
+            if &value < p {
+                break value;
+            }
+        })
+        .collect();
+
+    // A Cauchy matrix, 1 / (x_i + y_j), with distinct x and y.
+    let mds = loop {
+        let values: Vec<BigUint> = (0..2 * t).map(|_| grain.next_int() % p).collect();
+        let distinct = values
+            .iter()
+            .enumerate()
+            .all(|(i, value)| !values[..i].contains(value));
+        if !distinct {
+            continue;
+        }
+        let (xs, ys) = values.split_at(t);
+        let sums: Vec<Vec<BigUint>> = xs
+            .iter()
+            .map(|x| ys.iter().map(|y| (x + y) % p).collect())
+            .collect();
+        if sums.iter().flatten().any(|sum| sum == &BigUint::from(0u32)) {
+            continue;
+        }
+        let exponent = p - 2u32;
+        break sums
+            .iter()
+            .map(|row| row.iter().map(|sum| sum.modpow(&exponent, p)).collect())
+            .collect();
+    };
+
+    Constants {
+        round_constants,
+        mds,
+    }
+}
+
+// The self-shrinking Grain LFSR of the Poseidon reference implementation.
+struct Grain {
+    state: [bool; 80],
+}
+
+impl Grain {
+    fn new(t: usize, partial_rounds: usize) -> Self {
+        let mut bits = Vec::with_capacity(80);
+        let mut push = |value: usize, width: usize| {
+            for i in (0..width).rev() {
+                bits.push((value >> i) & 1 == 1);
+            }
+        };
+        // Prime field, x^5 S-box, field size, t, full and partial rounds.
+        push(1, 2);
+        push(0, 4);
+        push(FIELD_BITS, 12);
+        push(t, 12);
+        push(FULL_ROUNDS, 10);
+        push(partial_rounds, 10);
+        push((1 << 30) - 1, 30);
+
+        let mut grain = Self { state: [false; 80] };
+        grain.state.copy_from_slice(&bits);
+        for _ in 0..160 {
+            grain.step();
+        }
+        grain
+    }
+
+    fn step(&mut self) -> bool {
+        let s = &self.state;
+        let bit = s[62] ^ s[51] ^ s[38] ^ s[23] ^ s[13] ^ s[0];
+        self.state.copy_within(1.., 0);
+        self.state[79] = bit;
+        bit
+    }
+
+    // Bits are taken in pairs, the second one kept when the first is set.
+    fn next_bit(&mut self) -> bool {
+        loop {
+            let keep = self.step();
+            let bit = self.step();
+            if keep {
+                return bit;
+            }
+        }
+    }
+
+    fn next_int(&mut self) -> BigUint {
+        let mut value = BigUint::from(0u32);
+        for _ in 0..FIELD_BITS {
+            value <<= 1u32;
+            if self.next_bit() {
+                value += 1u32;
+            }
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::poseidon;
+    use num_bigint::BigUint;
+
+    fn hash(inputs: &[u32]) -> String {
+        let inputs: Vec<BigUint> = inputs.iter().map(|&i| BigUint::from(i)).collect();
+        poseidon(&inputs).to_string()
+    }
+
+    #[test]
+    fn test_poseidon() {
+        assert_eq!(
+            "18586133768512220936620570745912940619677854269274689475585506675881198879027",
+            hash(&[1])
+        );
+        assert_eq!(
+            "7853200120776062878684798364095072458815029376092732009249414926327459813530",
+            hash(&[1, 2])
+        );
+        assert_eq!(
+            "18821383157269793795438455681495246036402687001665670618754263018637548127333",
+            hash(&[1, 2, 3, 4])
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! Client side rate limiting.
+//!
+//! Requests are queued until the token bucket for their endpoint, and the
+//! global bucket, allow them through. Nothing is rejected.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::Endpoint;
+
+/// A token bucket budget: `rate` requests per second on average, with bursts
+/// of up to `burst` requests.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RateLimit {
+    pub rate: f64,
+    pub burst: u32,
+}
+
+impl RateLimit {
+    pub fn new(rate: f64, burst: u32) -> Self {
+        Self {
+            rate,
+            burst: burst.max(1),
+        }
+    }
+
+    /// `requests` per second, with bursts of the same size.
+    pub fn per_second(requests: u32) -> Self {
+        Self::new(requests as f64, requests)
+    }
+
+    /// `requests` per minute, with bursts of the same size.
+    pub fn per_minute(requests: u32) -> Self {
+        Self::new(requests as f64 / 60.0, requests)
+    }
+}
+
+/// Time spent waiting for the rate limiter.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimitStats {
+    /// Number of requests sent.
+    pub requests: u64,
+    /// Number of requests that had to wait.
+    pub delayed: u64,
+    /// Total time spent waiting.
+    pub total_wait: Duration,
+    /// Longest single wait.
+    pub max_wait: Duration,
+}
+
+struct Bucket {
+    limit: RateLimit,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(limit: RateLimit, now: Instant) -> Self {
+        Self {
+            limit,
+            tokens: limit.burst as f64,
+            updated: now,
+        }
+    }
+
+    // Takes a token, going into debt if none are left, and returns how long
+    // the caller has to wait for its token to become available.
+    fn reserve(&mut self, now: Instant) -> Duration {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.limit.rate).min(self.limit.burst as f64);
+        self.updated = now;
+        self.tokens -= 1.0;
+
+        if self.tokens >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.limit.rate)
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct RateLimiter {
+    global: Option<Mutex<Bucket>>,
+    endpoints: HashMap<Endpoint, Mutex<Bucket>>,
+    stats: Mutex<HashMap<Endpoint, RateLimitStats>>,
+}
+
+impl RateLimiter {
+    pub fn new(global: Option<RateLimit>, endpoints: &HashMap<Endpoint, RateLimit>) -> Self {
+        let now = Instant::now();
+        Self {
+            global: global.map(|limit| Mutex::new(Bucket::new(limit, now))),
+            endpoints: endpoints
+                .iter()
+                .map(|(endpoint, limit)| (*endpoint, Mutex::new(Bucket::new(*limit, now))))
+                .collect(),
+            stats: Default::default(),
+        }
+    }
+
+    // Takes a token from the bucket of `endpoint`, and returns how long to
+    // wait for it.
+    fn reserve_endpoint(&self, endpoint: Endpoint, now: Instant) -> Duration {
+        match self.endpoints.get(&endpoint) {
+            Some(bucket) => bucket.lock().unwrap().reserve(now),
+            None => Duration::from_secs(0),
+        }
+    }
+
+    // Takes a token from the global bucket, and returns how long to wait for it.
+    fn reserve_global(&self, now: Instant) -> Duration {
+        match &self.global {
+            Some(bucket) => bucket.lock().unwrap().reserve(now),
+            None => Duration::from_secs(0),
+        }
+    }
+
+    fn record(&self, endpoint: Endpoint, wait: Duration) {
+        let mut stats = self.stats.lock().unwrap();
+        let stats = stats.entry(endpoint).or_default();
+        stats.requests += 1;
+        if wait > Duration::from_secs(0) {
+            stats.delayed += 1;
+            stats.total_wait += wait;
+            stats.max_wait = stats.max_wait.max(wait);
+        }
+    }
+
+    /// Waits until a request to `endpoint` is allowed through.
+    ///
+    /// The global token is only taken once the endpoint's wait is over, so
+    /// that requests queued on a throttled endpoint do not use up the global
+    /// budget of the others.
+    pub async fn acquire(&self, endpoint: Endpoint) {
+        let local = self.reserve_endpoint(endpoint, Instant::now());
+        if local > Duration::from_secs(0) {
+            futures_timer::Delay::new(local).await;
+        }
+        let global = self.reserve_global(Instant::now());
+        if global > Duration::from_secs(0) {
+            futures_timer::Delay::new(global).await;
+        }
+        self.record(endpoint, local + global);
+    }
+
+    pub fn stats(&self) -> HashMap<Endpoint, RateLimitStats> {
+        self.stats.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RateLimit, RateLimiter};
+    use crate::Endpoint;
+    use std::collections::HashMap;
+    use std::time::{Duration, Instant};
+
+    // What `acquire()` does, at `now` rather than the current time.
+    fn reserve(limiter: &RateLimiter, endpoint: Endpoint, now: Instant) -> Duration {
+        let local = limiter.reserve_endpoint(endpoint, now);
+        let global = limiter.reserve_global(now + local);
+        limiter.record(endpoint, local + global);
+        local + global
+    }
+
+    #[test]
+    fn test_rate_limiter() {
+        let mut endpoints = HashMap::new();
+        endpoints.insert(Endpoint::History, RateLimit::new(2.0, 2));
+        let limiter = RateLimiter::new(Some(RateLimit::new(10.0, 10)), &endpoints);
+        let now = Instant::now();
+
+        // The burst goes straight through, then requests are spaced at the endpoint's rate.
+        let waits: Vec<Duration> = (0..4)
+            .map(|_| reserve(&limiter, Endpoint::History, now))
+            .collect();
+        assert_eq!(
+            vec![
+                Duration::from_secs(0),
+                Duration::from_secs(0),
+                Duration::from_millis(500),
+                Duration::from_millis(1000),
+            ],
+            waits
+        );
+
+        // Other endpoints only see the global budget.
+        let later = now + Duration::from_secs(1);
+        assert_eq!(
+            Duration::from_secs(0),
+            reserve(&limiter, Endpoint::State, later)
+        );
+
+        // Tokens come back over time.
+        let later = now + Duration::from_secs(3);
+        assert_eq!(
+            Duration::from_secs(0),
+            reserve(&limiter, Endpoint::History, later)
+        );
+
+        let stats = limiter.stats()[&Endpoint::History];
+        assert_eq!(5, stats.requests);
+        assert_eq!(2, stats.delayed);
+        assert_eq!(Duration::from_millis(1500), stats.total_wait);
+        assert_eq!(Duration::from_millis(1000), stats.max_wait);
+    }
+
+    #[test]
+    fn test_throttled_endpoint() {
+        let mut endpoints = HashMap::new();
+        endpoints.insert(Endpoint::History, RateLimit::new(1.0, 1));
+        let limiter = RateLimiter::new(Some(RateLimit::new(1.0, 2)), &endpoints);
+        let now = Instant::now();
+        let second = Duration::from_secs(1);
+
+        assert_eq!(
+            Duration::from_secs(0),
+            reserve(&limiter, Endpoint::History, now)
+        );
+        // Waits on its endpoint, without taking a global token yet.
+        let queued = limiter.reserve_endpoint(Endpoint::History, now);
+        assert_eq!(second, queued);
+        // So the last global token is left for another endpoint.
+        assert_eq!(
+            Duration::from_secs(0),
+            reserve(&limiter, Endpoint::State, now)
+        );
+
+        // The queued request takes the token that came back meanwhile.
+        assert_eq!(Duration::from_secs(0), limiter.reserve_global(now + queued));
+        assert_eq!(second, reserve(&limiter, Endpoint::State, now + second));
+    }
+}
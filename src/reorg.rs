@@ -0,0 +1,189 @@
+//! Detection of batches reverted by an Ethereum reorganisation.
+
+use futures::stream::{self, Stream};
+use futures_timer::Delay;
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use super::batches::Batch;
+use super::{ErrorKind, HermezApi, PaginationOrder};
+
+/// What identifies a forged batch.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatchFingerprint {
+    pub ethereum_block_hash: String,
+    pub state_root: String,
+}
+
+impl From<&Batch> for BatchFingerprint {
+    fn from(batch: &Batch) -> Self {
+        Self {
+            ethereum_block_hash: batch.ethereum_block_hash.clone(),
+            state_root: batch.state_root.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ReorgEvent {
+    /// Batches numbered `from_batch` and above were reverted, or replaced by
+    /// different ones.
+    Rollback { from_batch: u32 },
+}
+
+/// Remembers the most recent batches, and checks on each poll that the node
+/// still has them unchanged.
+///
+/// When a rollback is found, cached batches from the first changed one on
+/// are removed, with `HermezApi::invalidate_batches_from()`.
+///
+/// # Example
+///
+/// ```no_run
+/// use futures::StreamExt;
+/// use hermez_api::reorg::{ReorgDetector, ReorgEvent};
+/// use hermez_api::HermezApi;
+///
+/// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+/// let mut events = ReorgDetector::new(&api).stream().boxed();
+///
+/// while let Some(event) = events.next().await {
+///     if let Ok(ReorgEvent::Rollback { from_batch }) = event {
+///         // drop what was derived from batches from_batch and above
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct ReorgDetector {
+    api: HermezApi,
+    depth: u64,
+    interval: Duration,
+    seen: BTreeMap<u32, BatchFingerprint>,
+}
+
+impl ReorgDetector {
+    pub fn new(api: &HermezApi) -> Self {
+        Self {
+            api: api.clone(),
+            depth: 64,
+            interval: Duration::from_secs(30),
+            seen: BTreeMap::new(),
+        }
+    }
+
+    /// Number of recent batches remembered and checked, 64 by default.
+    pub fn depth(&mut self, depth: u64) -> &mut Self {
+        self.depth = depth;
+        self
+    }
+
+    /// The interval between polls of `stream()`, thirty seconds by default.
+    pub fn interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+        self
+    }
+
+    /// The batches currently remembered.
+    pub fn seen(&self) -> &BTreeMap<u32, BatchFingerprint> {
+        &self.seen
+    }
+
+    /// Fetches the most recent batches and compares them with those seen
+    /// on the previous check.
+    pub async fn check(&mut self) -> Result<Option<ReorgEvent>, ErrorKind> {
+        let (batches, _) = self
+            .api
+            .batches_get_options()
+            .order(PaginationOrder::Desc)
+            .limit(self.depth)
+            .fetch()
+            .await?;
+
+        let latest: BTreeMap<u32, BatchFingerprint> = batches
+            .iter()
+            .map(|batch| (batch.batch_num, BatchFingerprint::from(batch)))
+            .collect();
+
+        let rollback = find_rollback(&self.seen, &latest);
+        self.seen = latest;
+
+        Ok(rollback.map(|from_batch| {
+            self.api.invalidate_batches_from(from_batch);
+            ReorgEvent::Rollback { from_batch }
+        }))
+    }
+
+    /// A stream of events, which never ends.
+    ///
+    /// Errors are returned as they happen, and polling goes on after them.
+    pub fn stream(&self) -> impl Stream<Item = Result<ReorgEvent, ErrorKind>> + Send + 'static {
+        stream::unfold(
+            (self.clone(), true),
+            |(mut detector, mut first)| async move {
+                loop {
+                    if !first {
+                        Delay::new(detector.interval).await;
+                    }
+                    first = false;
+                    match detector.check().await {
+                        Ok(None) => {}
+                        Ok(Some(event)) => return Some((Ok(event), (detector, first))),
+                        Err(err) => return Some((Err(err), (detector, first))),
+                    }
+                }
+            },
+        )
+    }
+}
+
+// The first batch seen before that the node no longer has, or has changed.
+// Seen batches older than those just fetched are not compared.
+pub(crate) fn find_rollback(
+    seen: &BTreeMap<u32, BatchFingerprint>,
+    latest: &BTreeMap<u32, BatchFingerprint>,
+) -> Option<u32> {
+    let first = *latest.keys().next().unwrap_or(&0);
+    seen.range(first..)
+        .find(|(batch_num, fingerprint)| latest.get(batch_num) != Some(fingerprint))
+        .map(|(batch_num, _)| *batch_num)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find_rollback, BatchFingerprint};
+    use std::collections::BTreeMap;
+
+    fn batches(range: std::ops::Range<u32>, root: &str) -> BTreeMap<u32, BatchFingerprint> {
+        range
+            .map(|batch_num| {
+                let fingerprint = BatchFingerprint {
+                    ethereum_block_hash: format!("0x{}", batch_num),
+                    state_root: root.to_owned(),
+                };
+                (batch_num, fingerprint)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_find_rollback() {
+        let seen = batches(100..164, "1");
+
+        assert_eq!(None, find_rollback(&BTreeMap::new(), &seen));
+        assert_eq!(None, find_rollback(&seen, &seen));
+        assert_eq!(None, find_rollback(&seen, &batches(110..174, "1")));
+
+        // Batches 160 to 163 were reverted.
+        assert_eq!(Some(160), find_rollback(&seen, &batches(96..160, "1")));
+
+        // Batches from 150 on were replaced.
+        let mut latest = batches(100..150, "1");
+        latest.extend(batches(150..170, "2"));
+        assert_eq!(Some(150), find_rollback(&seen, &latest));
+
+        // The node has no batches any more.
+        assert_eq!(Some(100), find_rollback(&seen, &BTreeMap::new()));
+    }
+}
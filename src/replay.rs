@@ -0,0 +1,746 @@
+//! Independent check of balances, by replaying forged transactions.
+//!
+//! Batches are fetched in order with `HermezApi::get_full_batch()`, and their
+//! transactions applied to an account table kept in memory. The table is then
+//! compared with the accounts returned by `HermezApi::get_account()`.
+//!
+//! Fees are taken from the senders, but the API does not say which accounts
+//! of the coordinator collect them. The fees collected in a batch are
+//! credited to the accounts given to `Replay::fee_accounts()` for its forger.
+//! Others are totalled in `ReplayReport::uncredited_fees` instead, and the
+//! fee accounts will show as discrepancies.
+//!
+//! With `Replay::verify_state_root()`, the state root of each batch is also
+//! computed with a `StateTree`, and checked against `Batch::state_root`.
+
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+
+use std::collections::BTreeMap;
+
+use super::batches::FullBatch;
+use super::state_tree::StateTree;
+use super::transactions_history::{HistoryTransaction, L1OrL2, TransactionType};
+use super::transactions_pool::fee_amount;
+use super::{parse_amount, ErrorKind, HermezApi};
+
+/// An account as rebuilt from the transactions.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ReplayedAccount {
+    pub account_index: String,
+    pub token_id: u32,
+    pub balance: u128,
+    pub nonce: u128,
+    pub bjj: String,
+    pub hez_ethereum_address: String,
+    /// The last batch and transaction which changed the account.
+    pub last_batch: u32,
+    pub last_tx: String,
+}
+
+/// Something that should not happen found while applying a transaction.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Anomaly {
+    pub batch_num: u32,
+    pub tx_id: String,
+    pub description: String,
+}
+
+/// An account whose balance or nonce differs from the replayed one.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Discrepancy {
+    pub account_index: String,
+    /// `"balance"`, `"nonce"`, or `"account"` when the node does not have it.
+    pub field: String,
+    pub replayed: String,
+    pub node: String,
+    /// The last batch and transaction which changed the account in the replay.
+    pub last_batch: u32,
+    pub last_tx: String,
+}
+
+/// A batch whose reported figures differ from the replayed ones.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct BatchMismatch {
+    pub batch_num: u32,
+    /// `"state_root"`, or `"num_accounts"` for the accounts created by the batch.
+    pub field: String,
+    pub replayed: String,
+    pub node: String,
+}
+
+/// The outcome of `Replay::run()`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ReplayReport {
+    /// The last batch replayed.
+    pub last_batch: u32,
+    pub accounts: usize,
+    pub anomalies: Vec<Anomaly>,
+    pub discrepancies: Vec<Discrepancy>,
+    /// Only checked with `Replay::verify_state_root()`.
+    pub batch_mismatches: Vec<BatchMismatch>,
+    /// Fees paid and not credited to a known fee account, by token id.
+    pub uncredited_fees: BTreeMap<u32, u128>,
+    /// Accounts that could not be fetched from the node, with the error.
+    pub errors: Vec<(String, String)>,
+}
+
+impl ReplayReport {
+    pub fn is_consistent(&self) -> bool {
+        self.anomalies.is_empty()
+            && self.discrepancies.is_empty()
+            && self.batch_mismatches.is_empty()
+            && self.errors.is_empty()
+    }
+}
+
+/// Rebuilds account balances and nonces from the forged transactions.
+///
+/// A replay can be resumed: `replay_to()` carries on from the batch after the
+/// last one applied.
+///
+/// # Example
+///
+/// ```no_run
+/// use hermez_api::replay::Replay;
+/// use hermez_api::HermezApi;
+///
+/// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+/// let report = Replay::new(&api).run().await.unwrap();
+///
+/// for discrepancy in &report.discrepancies {
+///     println!(
+///         "{} {}: replayed {}, node {} (last changed by {} in batch {})",
+///         discrepancy.account_index,
+///         discrepancy.field,
+///         discrepancy.replayed,
+///         discrepancy.node,
+///         discrepancy.last_tx,
+///         discrepancy.last_batch
+///     );
+/// }
+/// ```
+#[derive(Clone)]
+pub struct Replay {
+    api: HermezApi,
+    accounts: BTreeMap<String, ReplayedAccount>,
+    last_batch: u32,
+    anomalies: Vec<Anomaly>,
+    uncredited_fees: BTreeMap<u32, u128>,
+    // Fee accounts by forger address, in lower case.
+    fee_accounts: BTreeMap<String, Vec<String>>,
+    state_tree: Option<StateTree>,
+    batch_mismatches: Vec<BatchMismatch>,
+}
+
+impl Replay {
+    pub fn new(api: &HermezApi) -> Self {
+        Self {
+            api: api.clone(),
+            accounts: BTreeMap::new(),
+            last_batch: 0,
+            anomalies: Vec::new(),
+            uncredited_fees: BTreeMap::new(),
+            fee_accounts: BTreeMap::new(),
+            state_tree: None,
+            batch_mismatches: Vec::new(),
+        }
+    }
+
+    /// The accounts collecting the fees of batches forged by `forger_addr`,
+    /// one per token.
+    pub fn fee_accounts(&mut self, forger_addr: &str, account_indexes: &[&str]) -> &mut Self {
+        self.fee_accounts
+            .entry(forger_addr.to_lowercase())
+            .or_default()
+            .extend(account_indexes.iter().map(|index| index.to_string()));
+        self
+    }
+
+    /// Whether to compute the state root after each batch, and check it
+    /// against the one reported. Off by default, as hashing is slow.
+    pub fn verify_state_root(&mut self, verify: bool) -> &mut Self {
+        self.state_tree = if verify {
+            let mut tree = StateTree::new();
+            for account in self.accounts.values() {
+                // Accounts with invalid keys are reported by the next batch.
+                let _ = tree.set(account);
+            }
+            Some(tree)
+        } else {
+            None
+        };
+        self
+    }
+
+    /// The accounts rebuilt so far.
+    pub fn accounts(&self) -> &BTreeMap<String, ReplayedAccount> {
+        &self.accounts
+    }
+
+    /// The last batch applied, 0 if none.
+    pub fn last_batch(&self) -> u32 {
+        self.last_batch
+    }
+
+    /// Replays the batches after the last one applied, up to `batch_num`.
+    pub async fn replay_to(&mut self, batch_num: u32) -> Result<(), ErrorKind> {
+        if batch_num <= self.last_batch {
+            return Ok(());
+        }
+        let api = self.api.clone();
+        let mut batches = stream::iter(self.last_batch + 1..=batch_num)
+            .map(|batch_num| {
+                let api = api.clone();
+                async move { api.get_full_batch(batch_num).await }
+            })
+            .buffered(api.inner.concurrency);
+        while let Some(batch) = batches.next().await {
+            self.apply(&batch?)?;
+        }
+        Ok(())
+    }
+
+    /// Applies the transactions of a batch.
+    ///
+    /// Batches are expected in order, without gaps.
+    pub fn apply(&mut self, batch: &FullBatch) -> Result<(), ErrorKind> {
+        let batch_num = batch.batch.batch_num;
+        if batch_num != self.last_batch + 1 {
+            return Err(ErrorKind::Api(format!(
+                "Replay: expected batch {}, got {}",
+                self.last_batch + 1,
+                batch_num
+            )));
+        }
+
+        check_amounts(batch)?;
+
+        let accounts = self.accounts.len();
+        let mut transactions: Vec<&HistoryTransaction> = batch.transactions.iter().collect();
+        transactions.sort_by_key(|tx| tx.position);
+        for tx in transactions {
+            if let Err(description) = self.apply_tx(batch_num, tx)? {
+                self.anomalies.push(Anomaly {
+                    batch_num,
+                    tx_id: tx.id.clone(),
+                    description,
+                });
+            }
+        }
+        self.collect_fees(batch)?;
+        self.last_batch = batch_num;
+
+        if let Some(tree) = &mut self.state_tree {
+            for account in self.accounts.values() {
+                if account.last_batch == batch_num {
+                    tree.set(account)?;
+                }
+            }
+            let checks = [
+                ("state_root", tree.root(), batch.batch.state_root.clone()),
+                (
+                    "num_accounts",
+                    (self.accounts.len() - accounts).to_string(),
+                    batch.batch.num_accounts.to_string(),
+                ),
+            ];
+            for (field, replayed, node) in checks.iter().cloned() {
+                if replayed != node {
+                    self.batch_mismatches.push(BatchMismatch {
+                        batch_num,
+                        field: field.to_owned(),
+                        replayed,
+                        node,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Credits the fees collected in a batch to the forger's fee accounts.
+    fn collect_fees(&mut self, batch: &FullBatch) -> Result<(), ErrorKind> {
+        let batch_num = batch.batch.batch_num;
+        let fee_accounts = match self
+            .fee_accounts
+            .get(&batch.batch.forger_addr.to_lowercase())
+        {
+            Some(fee_accounts) => fee_accounts,
+            None => return Ok(()),
+        };
+        for (token_id, amount) in &batch.batch.collected_fees {
+            let amount = parse_amount(amount)?;
+            let token_id: u32 = token_id
+                .parse()
+                .map_err(|_| ErrorKind::Api(format!("Invalid token id {}", token_id)))?;
+            let accounts = &mut self.accounts;
+            let index = fee_accounts.iter().find(|index| {
+                matches!(accounts.get(*index), Some(account) if account.token_id == token_id)
+            });
+            if let Some(account) = index.and_then(|index| accounts.get_mut(index)) {
+                account.balance += amount;
+                account.last_batch = batch_num;
+                account.last_tx = String::from("collected fees");
+                let uncredited = self.uncredited_fees.entry(token_id).or_insert(0);
+                *uncredited = uncredited.saturating_sub(amount);
+            }
+        }
+        Ok(())
+    }
+
+    // Malformed amounts are errors, caught by `check_amounts()` before the
+    // batch is applied, while transactions that cannot be applied are
+    // anomalies.
+    fn apply_tx(
+        &mut self,
+        batch_num: u32,
+        tx: &HistoryTransaction,
+    ) -> Result<Result<(), String>, ErrorKind> {
+        let amount = parse_amount(&tx.amount)?;
+        let token_id = tx.token.id;
+        let from = match &tx.from_account_index {
+            Some(from) => from.clone(),
+            None => return Ok(Err(String::from("No sender account"))),
+        };
+        let to = match account_number(&tx.to_account_index) {
+            // Index 0 means no transfer, index 1 an exit.
+            Some(0) | Some(1) => None,
+            _ => Some(tx.to_account_index.clone()),
+        };
+
+        match tx.l1_or_l2 {
+            L1OrL2::L1 => {
+                if let TransactionType::CreateAccountDeposit
+                | TransactionType::CreateAccountDepositTransfer = tx.r#type
+                {
+                    if self.accounts.contains_key(&from) {
+                        return Ok(Err(format!("Account {} already exists", from)));
+                    }
+                    self.accounts.insert(
+                        from.clone(),
+                        ReplayedAccount {
+                            account_index: from.clone(),
+                            token_id,
+                            balance: 0,
+                            nonce: 0,
+                            bjj: tx.from_bjj.clone().unwrap_or_default(),
+                            hez_ethereum_address: tx
+                                .from_hez_ethereum_address
+                                .clone()
+                                .unwrap_or_default(),
+                            last_batch: batch_num,
+                            last_tx: tx.id.clone(),
+                        },
+                    );
+                }
+
+                let info = match &tx.l1_info {
+                    Some(info) => info,
+                    None => return Ok(Err(String::from("L1 transaction without L1 info"))),
+                };
+                let deposit = if info.deposit_amount_success {
+                    parse_amount(&info.deposit_amount)?
+                } else {
+                    0
+                };
+                let account = match self.account(&from, token_id) {
+                    Ok(account) => account,
+                    Err(description) => return Ok(Err(description)),
+                };
+                if deposit > 0 {
+                    account.balance += deposit;
+                    touch(account, batch_num, tx);
+                }
+
+                // The rollup nullifies L1 transfers and exits that cannot be
+                // made, rather than rejecting them.
+                if amount == 0 || account.balance < amount {
+                    return Ok(Ok(()));
+                }
+                match &to {
+                    None if is_exit(tx.r#type) => {
+                        account.balance -= amount;
+                        touch(account, batch_num, tx);
+                    }
+                    None => {}
+                    Some(to) => {
+                        let valid =
+                            matches!(self.accounts.get(to), Some(to) if to.token_id == token_id);
+                        if valid {
+                            self.debit(&from, amount, batch_num, tx);
+                            self.credit(to, amount, batch_num, tx);
+                        }
+                    }
+                }
+                Ok(Ok(()))
+            }
+            L1OrL2::L2 => {
+                let info = match &tx.l2_info {
+                    Some(info) => info,
+                    None => return Ok(Err(String::from("L2 transaction without L2 info"))),
+                };
+                let fee = match fee_amount(amount, info.fee) {
+                    Some(fee) => fee,
+                    None => return Ok(Err(format!("Fee {} of {} overflows", info.fee, amount))),
+                };
+
+                let account = match self.account(&from, token_id) {
+                    Ok(account) => account,
+                    Err(description) => return Ok(Err(description)),
+                };
+                if info.nonce != account.nonce {
+                    return Ok(Err(format!(
+                        "Nonce {} of {}, expected {}",
+                        info.nonce, from, account.nonce
+                    )));
+                }
+                if account.balance < amount + fee {
+                    return Ok(Err(format!(
+                        "Balance {} of {} is less than {} plus a fee of {}",
+                        account.balance, from, amount, fee
+                    )));
+                }
+                account.balance -= amount + fee;
+                account.nonce += 1;
+                touch(account, batch_num, tx);
+                *self.uncredited_fees.entry(token_id).or_insert(0) += fee;
+
+                match &to {
+                    None if is_exit(tx.r#type) => Ok(Ok(())),
+                    None => Ok(Err(format!(
+                        "Recipient {} is not an account",
+                        tx.to_account_index
+                    ))),
+                    Some(to) => {
+                        if !self.accounts.contains_key(to) {
+                            return Ok(Err(format!("Unknown recipient {}", to)));
+                        }
+                        self.credit(to, amount, batch_num, tx);
+                        Ok(Ok(()))
+                    }
+                }
+            }
+        }
+    }
+
+    // The sender of a transaction.
+    fn account(
+        &mut self,
+        account_index: &str,
+        token_id: u32,
+    ) -> Result<&mut ReplayedAccount, String> {
+        let account = self
+            .accounts
+            .get_mut(account_index)
+            .ok_or_else(|| format!("Unknown sender {}", account_index))?;
+        if account.token_id != token_id {
+            return Err(format!(
+                "Token {} of {} differs from the transaction's {}",
+                account.token_id, account_index, token_id
+            ));
+        }
+        Ok(account)
+    }
+
+    fn debit(
+        &mut self,
+        account_index: &str,
+        amount: u128,
+        batch_num: u32,
+        tx: &HistoryTransaction,
+    ) {
+        if let Some(account) = self.accounts.get_mut(account_index) {
+            account.balance -= amount;
+            touch(account, batch_num, tx);
+        }
+    }
+
+    fn credit(
+        &mut self,
+        account_index: &str,
+        amount: u128,
+        batch_num: u32,
+        tx: &HistoryTransaction,
+    ) {
+        if let Some(account) = self.accounts.get_mut(account_index) {
+            account.balance += amount;
+            touch(account, batch_num, tx);
+        }
+    }
+
+    /// Compares the accounts rebuilt so far with those of the node.
+    ///
+    /// The node may have forged batches since the last one replayed, so
+    /// accounts are best compared right after `replay_to()`.
+    pub async fn compare(&self) -> (Vec<Discrepancy>, Vec<(String, String)>) {
+        let fetched: Vec<_> = stream::iter(self.accounts.values())
+            .map(|account| {
+                let api = self.api.clone();
+                async move { (account, api.get_account(&account.account_index).await) }
+            })
+            .buffered(self.api.inner.concurrency)
+            .collect()
+            .await;
+
+        let mut discrepancies = Vec::new();
+        let mut errors = Vec::new();
+        for (replayed, node) in fetched {
+            let discrepancy =
+                |field: &str, replayed_value: String, node_value: String| Discrepancy {
+                    account_index: replayed.account_index.clone(),
+                    field: field.to_owned(),
+                    replayed: replayed_value,
+                    node: node_value,
+                    last_batch: replayed.last_batch,
+                    last_tx: replayed.last_tx.clone(),
+                };
+            match node {
+                Ok(node) => {
+                    if parse_amount(&node.balance).ok() != Some(replayed.balance) {
+                        discrepancies.push(discrepancy(
+                            "balance",
+                            replayed.balance.to_string(),
+                            node.balance,
+                        ));
+                    }
+                    if node.nonce != replayed.nonce {
+                        discrepancies.push(discrepancy(
+                            "nonce",
+                            replayed.nonce.to_string(),
+                            node.nonce.to_string(),
+                        ));
+                    }
+                }
+                Err(ErrorKind::Http(404, _)) => discrepancies.push(discrepancy(
+                    "account",
+                    String::from("exists"),
+                    String::from("missing"),
+                )),
+                Err(err) => errors.push((replayed.account_index.clone(), err.to_string())),
+            }
+        }
+        (discrepancies, errors)
+    }
+
+    /// Replays up to the last batch of the node, and compares the accounts.
+    pub async fn run(&mut self) -> Result<ReplayReport, ErrorKind> {
+        let state = self.api.get_state().await?;
+        self.replay_to(state.network.last_batch.batch_num).await?;
+        let (discrepancies, errors) = self.compare().await;
+
+        Ok(ReplayReport {
+            last_batch: self.last_batch,
+            accounts: self.accounts.len(),
+            anomalies: self.anomalies.clone(),
+            discrepancies,
+            batch_mismatches: self.batch_mismatches.clone(),
+            uncredited_fees: self.uncredited_fees.clone(),
+            errors,
+        })
+    }
+}
+
+// Marks an account as last changed by a transaction.
+fn touch(account: &mut ReplayedAccount, batch_num: u32, tx: &HistoryTransaction) {
+    account.last_batch = batch_num;
+    account.last_tx = tx.id.clone();
+}
+
+fn is_exit(r#type: TransactionType) -> bool {
+    matches!(r#type, TransactionType::Exit | TransactionType::ForceExit)
+}
+
+// The number in an account index like "hez:ETH:256".
+fn account_number(account_index: &str) -> Option<u64> {
+    account_index.rsplit(':').next()?.parse().ok()
+}
+
+// Parses the amounts of a batch, so that a malformed one rejects the batch
+// before any of its transactions is applied, rather than halfway through.
+fn check_amounts(batch: &FullBatch) -> Result<(), ErrorKind> {
+    for tx in &batch.transactions {
+        parse_amount(&tx.amount)?;
+        if let Some(info) = &tx.l1_info {
+            if info.deposit_amount_success {
+                parse_amount(&info.deposit_amount)?;
+            }
+        }
+    }
+    for (token_id, amount) in &batch.batch.collected_fees {
+        parse_amount(amount)?;
+        token_id
+            .parse::<u32>()
+            .map_err(|_| ErrorKind::Api(format!("Invalid token id {}", token_id)))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{account_number, Replay};
+    use crate::test_support::{self, full_batch};
+    use crate::HermezApi;
+
+    const ETHER: u128 = 1_000_000_000_000_000_000;
+
+    #[test]
+    fn test_account_number() {
+        assert_eq!(Some(256), account_number("hez:ETH:256"));
+    }
+
+    fn transaction(
+        id: &str,
+        r#type: &str,
+        from: &str,
+        to: &str,
+        amount: u128,
+        deposit: Option<u128>,
+        nonce: u128,
+    ) -> serde_json::Value {
+        let mut tx = test_support::transaction(id, r#type, from, to, amount);
+        match deposit {
+            Some(deposit) => test_support::deposit(tx, deposit),
+            None => {
+                tx["L2Info"]["nonce"] = serde_json::json!(nonce);
+                tx
+            }
+        }
+    }
+
+    #[test]
+    fn test_apply() {
+        let api = HermezApi::new(HermezApi::LOCALHOST_URL).unwrap();
+        let mut replay = Replay::new(&api);
+
+        replay
+            .apply(&full_batch(
+                1,
+                vec![
+                    transaction(
+                        "a",
+                        "CreateAccountDeposit",
+                        "hez:ETH:256",
+                        "hez:ETH:0",
+                        0,
+                        Some(5 * ETHER),
+                        0,
+                    ),
+                    transaction(
+                        "b",
+                        "CreateAccountDeposit",
+                        "hez:ETH:257",
+                        "hez:ETH:0",
+                        0,
+                        Some(0),
+                        0,
+                    ),
+                ],
+            ))
+            .unwrap();
+        replay
+            .apply(&full_batch(
+                2,
+                vec![
+                    transaction(
+                        "c",
+                        "Transfer",
+                        "hez:ETH:256",
+                        "hez:ETH:257",
+                        2 * ETHER,
+                        None,
+                        0,
+                    ),
+                    transaction("d", "Exit", "hez:ETH:256", "hez:ETH:1", ETHER, None, 1),
+                    transaction(
+                        "e",
+                        "Transfer",
+                        "hez:ETH:257",
+                        "hez:ETH:256",
+                        3 * ETHER,
+                        None,
+                        0,
+                    ),
+                    // Nullified for lack of funds.
+                    transaction(
+                        "f",
+                        "ForceExit",
+                        "hez:ETH:257",
+                        "hez:ETH:1",
+                        3 * ETHER,
+                        Some(0),
+                        0,
+                    ),
+                ],
+            ))
+            .unwrap();
+        assert!(replay.apply(&full_batch(4, vec![])).is_err());
+
+        let sender = &replay.accounts()["hez:ETH:256"];
+        assert_eq!(
+            (2 * ETHER, 2, "d"),
+            (sender.balance, sender.nonce, sender.last_tx.as_str())
+        );
+        let recipient = &replay.accounts()["hez:ETH:257"];
+        assert_eq!(
+            (2 * ETHER, 0, "c"),
+            (
+                recipient.balance,
+                recipient.nonce,
+                recipient.last_tx.as_str()
+            )
+        );
+
+        assert_eq!(1, replay.anomalies.len());
+        assert_eq!(2, replay.anomalies[0].batch_num);
+        assert_eq!("e", replay.anomalies[0].tx_id);
+    }
+
+    #[test]
+    fn test_apply_malformed() {
+        let api = HermezApi::new(HermezApi::LOCALHOST_URL).unwrap();
+        let mut replay = Replay::new(&api);
+        let deposit = |id, from| {
+            transaction(
+                id,
+                "CreateAccountDeposit",
+                from,
+                "hez:ETH:0",
+                0,
+                Some(ETHER),
+                0,
+            )
+        };
+        replay
+            .apply(&full_batch(
+                1,
+                vec![deposit("a", "hez:ETH:256"), deposit("b", "hez:ETH:257")],
+            ))
+            .unwrap();
+
+        let transfer = |id, amount| {
+            transaction(
+                id,
+                "Transfer",
+                "hez:ETH:256",
+                "hez:ETH:257",
+                amount,
+                None,
+                0,
+            )
+        };
+        let mut malformed = transfer("d", 1);
+        malformed["amount"] = serde_json::json!("1e18");
+        let mut transactions = vec![transfer("c", ETHER), malformed, transfer("e", 1)];
+        assert!(replay.apply(&full_batch(2, transactions.clone())).is_err());
+        assert_eq!(1, replay.last_batch());
+        assert_eq!(ETHER, replay.accounts()["hez:ETH:256"].balance);
+        assert_eq!(0, replay.accounts()["hez:ETH:256"].nonce);
+
+        // Retrying the batch applies its transactions once.
+        transactions.remove(1);
+        replay.apply(&full_batch(2, transactions)).unwrap();
+        assert_eq!(0, replay.accounts()["hez:ETH:256"].balance);
+        assert_eq!(2 * ETHER, replay.accounts()["hez:ETH:257"].balance);
+    }
+}
@@ -0,0 +1,192 @@
+//! Conversions between Ethereum block numbers, auction slots and time.
+
+use chrono::{DateTime, Duration, Utc};
+
+use std::ops::RangeInclusive;
+
+use super::config::Config;
+use super::state::State;
+use super::{parse_timestamp, ErrorKind, HermezApi};
+
+/// Maps blocks to slots and back, and estimates when blocks are mined.
+///
+/// Slots are `blocks_per_slot` blocks long, the first one starting at
+/// `genesis_block_num`, as given by `ConfigAuction`. Times are estimated
+/// from the last batch and the periods of the next forgers listed in
+/// `StateNetwork`, assuming a constant block time.
+///
+/// # Example
+///
+/// ```no_run
+/// use hermez_api::HermezApi;
+///
+/// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+/// let schedule = api.get_schedule().await.unwrap();
+///
+/// let slot_num = schedule.slot_of_block(8_300_000).unwrap();
+/// println!("Slot {} starts at {}", slot_num + 1, schedule.slot_start(slot_num + 1));
+/// ```
+#[derive(Clone, Debug)]
+pub struct Schedule {
+    blocks_per_slot: u64,
+    genesis_block_num: u128,
+    anchor_block: u128,
+    anchor_time: DateTime<Utc>,
+    // Seconds per block.
+    block_time: f64,
+}
+
+impl Schedule {
+    /// Block time assumed when the state does not allow estimating it, in seconds.
+    pub const DEFAULT_BLOCK_TIME: f64 = 13.0;
+
+    pub fn new(config: &Config, state: &State) -> Result<Self, ErrorKind> {
+        let last_batch = &state.network.last_batch;
+        let mut anchors = vec![(last_batch.ethereum_block_num, last_batch.timestamp.as_str())];
+        for forger in &state.network.next_forgers {
+            let period = &forger.period;
+            anchors.push((period.from_block, period.from_timestamp.as_str()));
+            anchors.push((period.to_block, period.to_timestamp.as_str()));
+        }
+
+        let anchors = anchors
+            .into_iter()
+            .map(|(block, timestamp)| parse_timestamp(timestamp).map(|time| (block, time)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self::with_anchors(
+            config.auction.blocks_per_slot,
+            config.auction.genesis_block_num,
+            &anchors,
+        ))
+    }
+
+    // Times are extrapolated from the first anchor, at the block time
+    // measured across all of them.
+    fn with_anchors(
+        blocks_per_slot: u64,
+        genesis_block_num: u128,
+        anchors: &[(u128, DateTime<Utc>)],
+    ) -> Self {
+        let (anchor_block, anchor_time) = anchors[0];
+
+        let first = anchors.iter().min_by_key(|(block, _)| *block).unwrap();
+        let last = anchors.iter().max_by_key(|(block, _)| *block).unwrap();
+        let seconds = (last.1 - first.1).num_milliseconds() as f64 / 1000.0;
+        let block_time = if last.0 > first.0 && seconds > 0.0 {
+            seconds / (last.0 - first.0) as f64
+        } else {
+            Self::DEFAULT_BLOCK_TIME
+        };
+
+        Self {
+            blocks_per_slot: blocks_per_slot.max(1),
+            genesis_block_num,
+            anchor_block,
+            anchor_time,
+            block_time,
+        }
+    }
+
+    pub fn blocks_per_slot(&self) -> u64 {
+        self.blocks_per_slot
+    }
+
+    pub fn genesis_block_num(&self) -> u128 {
+        self.genesis_block_num
+    }
+
+    /// Estimated time between blocks.
+    pub fn block_time(&self) -> Duration {
+        Duration::milliseconds((self.block_time * 1000.0).round() as i64)
+    }
+
+    /// The slot a block belongs to, `None` for blocks before the first slot.
+    pub fn slot_of_block(&self, block: u128) -> Option<u32> {
+        if block < self.genesis_block_num {
+            return None;
+        }
+        Some(((block - self.genesis_block_num) / self.blocks_per_slot as u128) as u32)
+    }
+
+    /// The first and last blocks of a slot.
+    pub fn slot_blocks(&self, slot_num: u32) -> RangeInclusive<u128> {
+        let first = self.genesis_block_num + slot_num as u128 * self.blocks_per_slot as u128;
+        first..=first + self.blocks_per_slot as u128 - 1
+    }
+
+    /// Estimated time at which a block is, or was, mined.
+    pub fn block_timestamp(&self, block: u128) -> DateTime<Utc> {
+        let blocks = block as f64 - self.anchor_block as f64;
+        self.anchor_time
+            + Duration::milliseconds((blocks * self.block_time * 1000.0).round() as i64)
+    }
+
+    /// Estimated block mined at a given time.
+    pub fn block_at(&self, time: DateTime<Utc>) -> u128 {
+        let seconds = (time - self.anchor_time).num_milliseconds() as f64 / 1000.0;
+        let block = self.anchor_block as f64 + (seconds / self.block_time).floor();
+        block.max(0.0) as u128
+    }
+
+    /// Estimated slot under way at a given time.
+    pub fn slot_at(&self, time: DateTime<Utc>) -> Option<u32> {
+        self.slot_of_block(self.block_at(time))
+    }
+
+    /// Estimated time at which a slot starts.
+    pub fn slot_start(&self, slot_num: u32) -> DateTime<Utc> {
+        self.block_timestamp(*self.slot_blocks(slot_num).start())
+    }
+
+    /// Estimated time at which a slot ends, which is when the next one starts.
+    pub fn slot_end(&self, slot_num: u32) -> DateTime<Utc> {
+        self.slot_start(slot_num + 1)
+    }
+}
+
+impl HermezApi {
+    /// Get the configuration and state, and build a `Schedule` from them.
+    pub async fn get_schedule(&self) -> Result<Schedule, ErrorKind> {
+        let (config, state) = futures::join!(self.get_config(), self.get_state());
+        Schedule::new(&config?, &state?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Schedule;
+    use chrono::{DateTime, Duration, Utc};
+
+    fn time(s: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(s).unwrap().with_timezone(&Utc)
+    }
+
+    #[test]
+    fn test_schedule() {
+        let schedule = Schedule::with_anchors(
+            40,
+            8256000,
+            &[
+                (8256400, time("2021-03-01T10:00:00Z")),
+                (8256440, time("2021-03-01T10:08:00Z")),
+                (8256479, time("2021-03-01T10:15:48Z")),
+            ],
+        );
+
+        assert_eq!(Duration::seconds(12), schedule.block_time());
+        assert_eq!(None, schedule.slot_of_block(8255999));
+        assert_eq!(Some(0), schedule.slot_of_block(8256000));
+        assert_eq!(Some(10), schedule.slot_of_block(8256439));
+        assert_eq!(8256400..=8256439, schedule.slot_blocks(10));
+
+        assert_eq!(time("2021-03-01T10:00:00Z"), schedule.slot_start(10));
+        assert_eq!(time("2021-03-01T10:16:00Z"), schedule.slot_end(11));
+        assert_eq!(
+            time("2021-03-01T09:58:00Z"),
+            schedule.block_timestamp(8256390)
+        );
+        assert_eq!(8256405, schedule.block_at(time("2021-03-01T10:01:05Z")));
+        assert_eq!(Some(11), schedule.slot_at(time("2021-03-01T10:10:00Z")));
+    }
+}
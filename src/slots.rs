@@ -1,52 +1,36 @@
-use super::http::Http;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
+use super::Endpoint;
 use super::ErrorKind;
+use super::HermezApi;
 use super::PaginationOrder;
+use super::Query;
 
 use super::bids::Bid;
 
-pub struct SlotsGetOptions<'a> {
-    http: &'a Http,
-    url: &'a Url,
+/// Owned description of a request to the slots endpoint.
+///
+/// Can be cloned, stored and serialized, and then run with `HermezApi::run()`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SlotsQuery {
+    pub min_slot_num: Option<u32>,
+    pub max_slot_num: Option<u32>,
+    pub won_by_ethereum_address: Option<String>,
+    pub finished_auction: Option<bool>,
 
-    min_slot_num: Option<u32>,
-    max_slot_num: Option<u32>,
-    won_by_ethereum_address: Option<&'a str>,
-    finished_auction: Option<bool>,
-
-    from_item: Option<u64>,
-    order: Option<PaginationOrder>,
-    limit: Option<u64>,
+    pub from_item: Option<u64>,
+    pub order: Option<PaginationOrder>,
+    pub limit: Option<u64>,
 }
 
-impl<'a> SlotsGetOptions<'a> {
-    pub fn new(http: &'a Http, url: &'a Url) -> Self {
-        Self {
-            http,
-            url,
-
-            min_slot_num: None,
-            max_slot_num: None,
-            won_by_ethereum_address: None,
-            finished_auction: None,
+impl Query for SlotsQuery {
+    type Output = Slots;
+    const ENDPOINT: Endpoint = Endpoint::Slots;
 
-            from_item: None,
-            order: None,
-            limit: None,
-        }
-    }
-
-    setter!(min_slot_num, u32);
-    setter!(max_slot_num, u32);
-    setter!(won_by_ethereum_address, &'a str);
-    setter!(finished_auction, bool);
-
-    pagination_setters!();
-
-    pub async fn fetch(&self) -> Result<(Vec<Slot>, u64), ErrorKind> {
-        let mut url = self.url.join("slots").unwrap();
+    fn url(&self, base: &Url) -> Result<Url, ErrorKind> {
+        let mut url = base.join("slots").unwrap();
         {
             let mut query_pairs = url.query_pairs_mut();
 
@@ -63,19 +47,51 @@ impl<'a> SlotsGetOptions<'a> {
 
             pagination_fetch_stmts!(self, query_pairs);
         }
-        let slots: Slots = self.http.get(&url).await?;
+        Ok(url)
+    }
+}
+
+paged_query!(SlotsQuery, Slot, slots);
+
+pub struct SlotsGetOptions {
+    api: HermezApi,
+    query: SlotsQuery,
+}
+
+impl SlotsGetOptions {
+    pub fn new(api: &HermezApi) -> Self {
+        Self {
+            api: api.clone(),
+            query: Default::default(),
+        }
+    }
+
+    setter!(min_slot_num, u32);
+    setter!(max_slot_num, u32);
+    setter!(won_by_ethereum_address, &str);
+    setter!(finished_auction, bool);
+
+    pagination_setters!();
+
+    /// The query built so far.
+    pub fn query(&self) -> &SlotsQuery {
+        &self.query
+    }
+
+    pub async fn fetch(&self) -> Result<(Vec<Slot>, u64), ErrorKind> {
+        let slots = self.api.run(&self.query).await?;
         Ok((slots.slots, slots.pending_items))
     }
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct Slots {
+pub struct Slots {
     pub pending_items: u64,
     pub slots: Vec<Slot>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Slot {
     pub item_id: u64,
@@ -1,8 +1,8 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 use super::batches::Batch;
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct State {
     pub network: StateNetwork,
@@ -14,7 +14,7 @@ pub struct State {
     pub node: Node,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct StateNetwork {
     pub last_ethereum_block: u128,
@@ -25,7 +25,7 @@ pub struct StateNetwork {
     pub pending_l1_transactions: u64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct StateAuction {
     pub ethereum_block_num: u128,
@@ -41,7 +41,7 @@ pub struct StateAuction {
     pub allocation_ratio: Vec<u64>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct StateMetrics {
     pub transactions_per_batch: f64,
@@ -53,14 +53,14 @@ pub struct StateMetrics {
     pub estimated_time_to_forge_l1: f64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct NextForger {
     pub coordinator: Coordinator,
     pub period: Period,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Coordinator {
     pub item_id: u64,
@@ -71,7 +71,7 @@ pub struct Coordinator {
     pub ethereum_block: u128,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Period {
     pub slot_num: u32,
@@ -81,14 +81,14 @@ pub struct Period {
     pub to_timestamp: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Node {
     pub forge_delay: f64,
     pub pool_load: u64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct RecommededFee {
     pub existing_account: f64,
@@ -96,7 +96,7 @@ pub struct RecommededFee {
     pub create_account_internal: f64,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct StateRollup {
     pub ethereum_block_num: u128,
@@ -107,7 +107,7 @@ pub struct StateRollup {
     pub safe_mode: bool, // ?
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Bucket {
     #[serde(rename = "ceilUSD")]
@@ -119,7 +119,7 @@ pub struct Bucket {
     pub max_withdrawals: String,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct StateWithdrawalDelayer {
     pub ethereum_block_num: u128,
@@ -0,0 +1,188 @@
+//! The account state tree of the rollup, to verify `Batch::state_root`.
+//!
+//! Accounts are the leaves of a sparse Merkle tree hashed with Poseidon, keyed
+//! by the number in their account index. A leaf hashes, as the Hermez circuits
+//! do, the token id, nonce and sign of the BabyJubJub key packed together, the
+//! balance, the y coordinate of the key and the Ethereum address. A subtree
+//! holding a single leaf is replaced by that leaf, and an empty one by zero.
+
+use num_bigint::BigUint;
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use super::babyjubjub::parse_bjj;
+use super::poseidon::poseidon;
+use super::replay::ReplayedAccount;
+use super::ErrorKind;
+
+/// Computes the state root from the accounts put in it.
+///
+/// Roots are computed again only along the paths of the accounts changed
+/// since the previous call to `root()`.
+///
+/// # Example
+///
+/// ```no_run
+/// use hermez_api::replay::Replay;
+/// use hermez_api::HermezApi;
+///
+/// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+/// let mut replay = Replay::new(&api);
+/// let report = replay.verify_state_root(true).run().await.unwrap();
+///
+/// for mismatch in &report.batch_mismatches {
+///     println!("{:?}", mismatch);
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct StateTree {
+    // Leaf hashes, keyed by the account number with its bits reversed, so
+    // that the leaves of any subtree are contiguous.
+    leaves: BTreeMap<u64, BigUint>,
+    dirty: BTreeSet<u64>,
+    // Hashes of subtrees with more than one leaf, by depth and path.
+    nodes: HashMap<(u32, u64), BigUint>,
+}
+
+impl StateTree {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Number of accounts in the tree.
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    /// Puts an account in the tree, or updates it.
+    pub fn set(&mut self, account: &ReplayedAccount) -> Result<(), ErrorKind> {
+        let index = account_number(&account.account_index)?;
+        let (sign, ay) = parse_bjj(&account.bjj)?;
+        let eth_addr = parse_eth_addr(&account.hez_ethereum_address)?;
+
+        let nonce = account.nonce & ((1 << 40) - 1);
+        let e0 = BigUint::from(account.token_id)
+            + (BigUint::from(nonce) << 32u32)
+            + (BigUint::from(sign as u32) << 72u32);
+        let value = poseidon(&[e0, BigUint::from(account.balance), ay, eth_addr]);
+        let leaf = poseidon(&[BigUint::from(index), value, BigUint::from(1u32)]);
+
+        let key = index.reverse_bits();
+        self.leaves.insert(key, leaf);
+        self.dirty.insert(key);
+        Ok(())
+    }
+
+    /// The state root, as a decimal number like `Batch::state_root`.
+    pub fn root(&mut self) -> String {
+        let keys: Vec<u64> = self.leaves.keys().copied().collect();
+        let root = self.node(&keys, 0);
+        self.dirty.clear();
+        root.to_string()
+    }
+
+    // The hash of the subtree holding `keys`, which share their first
+    // `depth` bits.
+    fn node(&mut self, keys: &[u64], depth: u32) -> BigUint {
+        match keys {
+            [] => return BigUint::from(0u32),
+            [key] => return self.leaves[key].clone(),
+            _ => {}
+        }
+
+        let path = (depth, keys[0].checked_shr(64 - depth).unwrap_or(0));
+        if !keys.iter().any(|key| self.dirty.contains(key)) {
+            if let Some(hash) = self.nodes.get(&path) {
+                return hash.clone();
+            }
+        }
+
+        let split = keys.partition_point(|key| key >> (63 - depth) & 1 == 0);
+        let left = self.node(&keys[..split], depth + 1);
+        let right = self.node(&keys[split..], depth + 1);
+        let hash = poseidon(&[left, right]);
+        self.nodes.insert(path, hash.clone());
+        hash
+    }
+}
+
+pub(crate) fn account_number(account_index: &str) -> Result<u64, ErrorKind> {
+    account_index
+        .rsplit(':')
+        .next()
+        .and_then(|number| number.parse().ok())
+        .ok_or_else(|| ErrorKind::Api(format!("Invalid account index {}", account_index)))
+}
+
+// An Ethereum address, with or without the "hez:" prefix.
+pub(crate) fn parse_eth_addr(address: &str) -> Result<BigUint, ErrorKind> {
+    let hex = address.trim_start_matches("hez:").trim_start_matches("0x");
+    if hex.is_empty() {
+        return Ok(BigUint::from(0u32));
+    }
+    if hex.len() != 40 {
+        return Err(ErrorKind::Api(format!(
+            "Invalid Ethereum address {}",
+            address
+        )));
+    }
+    BigUint::parse_bytes(hex.as_bytes(), 16)
+        .ok_or_else(|| ErrorKind::Api(format!("Invalid Ethereum address {}", address)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StateTree;
+    use crate::poseidon::poseidon;
+    use crate::replay::ReplayedAccount;
+    use num_bigint::BigUint;
+
+    fn account(account_index: &str, balance: u128) -> ReplayedAccount {
+        ReplayedAccount {
+            account_index: account_index.to_owned(),
+            token_id: 0,
+            balance,
+            nonce: 0,
+            bjj: String::new(),
+            hez_ethereum_address: String::new(),
+            last_batch: 1,
+            last_tx: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_state_tree() {
+        let mut tree = StateTree::new();
+        assert_eq!("0", tree.root());
+
+        // A single leaf is the root.
+        tree.set(&account("hez:ETH:256", 5)).unwrap();
+        let e0 = BigUint::from(0u32);
+        let value = poseidon(&[e0.clone(), BigUint::from(5u32), e0.clone(), e0]);
+        let leaf_256 = poseidon(&[BigUint::from(256u32), value, BigUint::from(1u32)]);
+        assert_eq!(leaf_256.to_string(), tree.root());
+
+        // 256 and 257 differ in their first bit, 256 and 258 in their second.
+        tree.set(&account("hez:ETH:257", 0)).unwrap();
+        tree.set(&account("hez:ETH:258", 0)).unwrap();
+        let root = tree.root();
+        let mut leaves = StateTree::new();
+        leaves.set(&account("hez:ETH:257", 0)).unwrap();
+        let leaf_257: BigUint = leaves.root().parse().unwrap();
+        leaves = StateTree::new();
+        leaves.set(&account("hez:ETH:258", 0)).unwrap();
+        let leaf_258: BigUint = leaves.root().parse().unwrap();
+        let expected = poseidon(&[poseidon(&[leaf_256, leaf_258]), leaf_257]);
+        assert_eq!(expected.to_string(), root);
+
+        // Changing an account and back gives the same root, through the cache.
+        tree.set(&account("hez:ETH:258", 7)).unwrap();
+        assert_ne!(root, tree.root());
+        tree.set(&account("hez:ETH:258", 0)).unwrap();
+        assert_eq!(root, tree.root());
+    }
+}
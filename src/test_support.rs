@@ -0,0 +1,185 @@
+// Helpers shared by the unit tests: a mock node serving JSON over HTTP, and
+// fixtures of the objects it returns.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpListener;
+use url::Url;
+
+use super::batches::FullBatch;
+
+/// A request received by a mock node.
+pub struct Request {
+    pub method: String,
+    pub url: Url,
+    pub body: String,
+}
+
+impl Request {
+    /// The path after the API version, like `batches` or `accounts/hez:ETH:256`.
+    pub fn path(&self) -> &str {
+        let path = self.url.path().trim_start_matches('/');
+        path.split_once('/').map_or("", |(_, path)| path)
+    }
+
+    /// The value of a query parameter.
+    pub fn param(&self, name: &str) -> Option<String> {
+        self.url
+            .query_pairs()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.into_owned())
+    }
+}
+
+/// Answers each request with the status and JSON body returned by `handler`,
+/// one request per connection, and returns the URL to give `HermezApi::new()`.
+pub fn serve<F>(handler: F) -> String
+where
+    F: Fn(&Request) -> (u16, serde_json::Value) + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut content_length = 0;
+            loop {
+                let mut header = String::new();
+                if reader.read_line(&mut header).unwrap() <= 2 {
+                    break;
+                }
+                if let Some((name, value)) = header.split_once(':') {
+                    if name.eq_ignore_ascii_case("content-length") {
+                        content_length = value.trim().parse().unwrap();
+                    }
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+
+            let mut parts = request_line.split_whitespace();
+            let request = Request {
+                method: parts.next().unwrap().to_owned(),
+                url: Url::parse(&format!("http://node{}", parts.next().unwrap())).unwrap(),
+                body: String::from_utf8(body).unwrap(),
+            };
+            let (status, body) = handler(&request);
+            let body = body.to_string();
+            write!(
+                stream,
+                "HTTP/1.1 {} Mock\r\nContent-Type: application/json\r\n\
+                 Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+                status,
+                body.len(),
+                body
+            )
+            .unwrap();
+        }
+    });
+    url
+}
+
+/// Ether, token 0, with no USD price.
+pub fn token() -> serde_json::Value {
+    serde_json::json!({
+        "id": 0,
+        "ethereumAddress": "0x0000000000000000000000000000000000000000",
+        "itemId": 1,
+        "name": "Ether",
+        "symbol": "ETH",
+        "decimals": 18,
+        "ethereumBlockNum": 0,
+        "USD": null,
+        "fiatUpdate": null
+    })
+}
+
+/// An account holding Ether.
+pub fn account(account_index: &str, balance: &str, nonce: u128) -> serde_json::Value {
+    serde_json::json!({
+        "itemId": 1,
+        "accountIndex": account_index,
+        "nonce": nonce,
+        "balance": balance,
+        "bjj": "hez:rR7LXKal-av7I56Y0dEBCVmwc9zpoLY5ERhy5w7G-xwe",
+        "hezEthereumAddress": "hez:0xaa942cfcd25ad4d90a62358b0dd84f33b398262a",
+        "token": token()
+    })
+}
+
+/// A forged L2 transaction of Ether in batch 1, with no fee and nonce 0.
+pub fn transaction(
+    id: &str,
+    r#type: &str,
+    from: &str,
+    to: &str,
+    amount: u128,
+) -> serde_json::Value {
+    serde_json::json!({
+        "L1orL2": "L2",
+        "id": id,
+        "itemId": 1,
+        "type": r#type,
+        "position": 0,
+        "fromAccountIndex": from,
+        "fromHezEthereumAddress": null,
+        "fromBJJ": null,
+        "toAccountIndex": to,
+        "toHezEthereumAddress": null,
+        "toBJJ": null,
+        "amount": amount.to_string(),
+        "batchNum": 1,
+        "historicUSD": null,
+        "timestamp": "2021-03-01T10:00:00Z",
+        "token": token(),
+        "L1Info": null,
+        "L2Info": { "fee": 0, "historicFeeUSD": null, "nonce": 0 }
+    })
+}
+
+/// Turns `transaction` into an L1 transaction depositing `deposit_amount`.
+pub fn deposit(mut transaction: serde_json::Value, deposit_amount: u128) -> serde_json::Value {
+    transaction["L1orL2"] = serde_json::json!("L1");
+    transaction["L1Info"] = serde_json::json!({
+        "toForgeL1TransactionsNum": 1,
+        "userOrigin": true,
+        "depositAmount": deposit_amount.to_string(),
+        "depositAmountSuccess": true,
+        "historicDepositAmountUSD": null,
+        "ethereumBlockNum": 100
+    });
+    transaction["L2Info"] = serde_json::Value::Null;
+    transaction
+}
+
+/// A batch forged on March `batch_num`, as returned by `batches`.
+pub fn batch(batch_num: u32, forged_transactions: usize) -> serde_json::Value {
+    serde_json::json!({
+        "itemId": batch_num,
+        "batchNum": batch_num,
+        "ethereumBlockNum": 100,
+        "ethereumBlockHash": "0x00",
+        "ethereumTxHash": "0x00",
+        "timestamp": format!("2021-03-{:02}T10:00:00Z", batch_num),
+        "forgerAddr": "0x00",
+        "collectedFees": {},
+        "historicTotalCollectedFeesUSD": 0.0,
+        "stateRoot": "0",
+        "numAccounts": 0,
+        "exitRoot": "0",
+        "forgeL1TransactionsNum": null,
+        "slotNum": 0,
+        "forgedTransactions": forged_transactions
+    })
+}
+
+/// A batch along with its transactions.
+pub fn full_batch(batch_num: u32, transactions: Vec<serde_json::Value>) -> FullBatch {
+    serde_json::from_value(serde_json::json!({
+        "batch": batch(batch_num, transactions.len()),
+        "transactions": transactions
+    }))
+    .unwrap()
+}
@@ -1,35 +1,66 @@
-use super::http::Http;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use url::Url;
 
+use super::Endpoint;
 use super::ErrorKind;
+use super::HermezApi;
 use super::PaginationOrder;
+use super::Query;
 
-pub struct TokensGetOptions<'a> {
-    http: &'a Http,
-    url: &'a Url,
-    ids: Option<&'a [u32]>,
-    symbols: Option<&'a [&'a str]>,
-    from_item: Option<u64>,
-    order: Option<PaginationOrder>,
-    limit: Option<u64>,
+/// Owned description of a request to the tokens endpoint.
+///
+/// Can be cloned, stored and serialized, and then run with `HermezApi::run()`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokensQuery {
+    pub ids: Option<Vec<u32>>,
+    pub symbols: Option<Vec<String>>,
+
+    pub from_item: Option<u64>,
+    pub order: Option<PaginationOrder>,
+    pub limit: Option<u64>,
+}
+
+impl Query for TokensQuery {
+    type Output = Tokens;
+    const ENDPOINT: Endpoint = Endpoint::Tokens;
+
+    fn url(&self, base: &Url) -> Result<Url, ErrorKind> {
+        let mut url = base.join("tokens").unwrap();
+        {
+            let mut query_pairs = url.query_pairs_mut();
+
+            if let Some(ids) = &self.ids {
+                query_pairs.append_pair("ids", &itertools::join(ids, ","));
+            }
+
+            if let Some(symbols) = &self.symbols {
+                query_pairs.append_pair("symbols", &symbols.join(","));
+            }
+
+            pagination_fetch_stmts!(self, query_pairs);
+        }
+        Ok(url)
+    }
 }
 
-impl<'a> TokensGetOptions<'a> {
-    pub fn new(http: &'a Http, url: &'a Url) -> Self {
+paged_query!(TokensQuery, Token, tokens);
+
+pub struct TokensGetOptions {
+    api: HermezApi,
+    query: TokensQuery,
+}
+
+impl TokensGetOptions {
+    pub fn new(api: &HermezApi) -> Self {
         Self {
-            http,
-            url,
-            ids: None,
-            symbols: None,
-            from_item: None,
-            order: None,
-            limit: None,
+            api: api.clone(),
+            query: Default::default(),
         }
     }
 
-    setter!(ids, &'a [u32]);
-    setter!(symbols, &'a [&'a str]);
+    setter!(ids, &[u32]);
+    setter!(symbols, &[&str]);
 
     pagination_setters!();
     /*
@@ -44,34 +75,26 @@ impl<'a> TokensGetOptions<'a> {
         }
 
     */
-    pub async fn fetch(&self) -> Result<(Vec<Token>, u64), ErrorKind> {
-        let mut url = self.url.join("tokens").unwrap();
-        {
-            let mut query_pairs = url.query_pairs_mut();
-
-            if let Some(ids) = &self.ids {
-                query_pairs.append_pair("ids", &itertools::join(*ids, ","));
-            }
 
-            if let Some(symbols) = &self.symbols {
-                query_pairs.append_pair("symbols", &symbols.join(","));
-            }
+    /// The query built so far.
+    pub fn query(&self) -> &TokensQuery {
+        &self.query
+    }
 
-            pagination_fetch_stmts!(self, query_pairs);
-        }
-        let tokens: Tokens = self.http.get(&url).await?;
+    pub async fn fetch(&self) -> Result<(Vec<Token>, u64), ErrorKind> {
+        let tokens = self.api.run(&self.query).await?;
         Ok((tokens.tokens, tokens.pending_items))
     }
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct Tokens {
+pub struct Tokens {
     pub pending_items: u64,
     pub tokens: Vec<Token>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Token {
     pub id: u32,
@@ -80,7 +103,7 @@ pub struct Token {
     pub name: String,
     pub symbol: String,
     pub decimals: u32,
-    ethereum_block_num: u128,
+    pub ethereum_block_num: u128,
     #[serde(rename = "USD")]
     pub usd: Option<f64>,
     pub fiat_update: Option<String>,
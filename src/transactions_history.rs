@@ -1,73 +1,43 @@
-use super::http::Http;
 use serde::{Deserialize, Serialize};
 use url::Url;
 
+use super::Endpoint;
 use super::ErrorKind;
+use super::HermezApi;
 use super::PaginationOrder;
+use super::Query;
 
 use super::tokens::Token;
 
-pub struct TransactionsHistoryGetOptions<'a> {
-    http: &'a Http,
-    url: &'a Url,
-    token_id: Option<u32>,
-    hez_ethereum_address: Option<&'a str>,
-    from_hez_ethereum_address: Option<&'a str>,
-    to_hez_ethereum_address: Option<&'a str>,
-    bjj: Option<&'a str>,
-    from_bjj: Option<&'a str>,
-    to_bjj: Option<&'a str>,
-    account_index: Option<&'a str>,
-    from_account_index: Option<&'a str>,
-    to_account_index: Option<&'a str>,
-    batch_num: Option<u32>,
-    r#type: Option<TransactionType>,
+/// Owned description of a request to the transactions history endpoint.
+///
+/// Can be cloned, stored and serialized, and then run with `HermezApi::run()`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct HistoryQuery {
+    pub token_id: Option<u32>,
+    pub hez_ethereum_address: Option<String>,
+    pub from_hez_ethereum_address: Option<String>,
+    pub to_hez_ethereum_address: Option<String>,
+    pub bjj: Option<String>,
+    pub from_bjj: Option<String>,
+    pub to_bjj: Option<String>,
+    pub account_index: Option<String>,
+    pub from_account_index: Option<String>,
+    pub to_account_index: Option<String>,
+    pub batch_num: Option<u32>,
+    pub r#type: Option<TransactionType>,
 
-    from_item: Option<u64>,
-    order: Option<PaginationOrder>,
-    limit: Option<u64>,
+    pub from_item: Option<u64>,
+    pub order: Option<PaginationOrder>,
+    pub limit: Option<u64>,
 }
 
-impl<'a> TransactionsHistoryGetOptions<'a> {
-    pub fn new(http: &'a Http, url: &'a Url) -> Self {
-        Self {
-            http,
-            url,
-            token_id: None,
-            hez_ethereum_address: None,
-            from_hez_ethereum_address: None,
-            to_hez_ethereum_address: None,
-            bjj: None,
-            from_bjj: None,
-            to_bjj: None,
-            account_index: None,
-            from_account_index: None,
-            to_account_index: None,
-            batch_num: None,
-            r#type: None,
-
-            from_item: None,
-            order: None,
-            limit: None,
-        }
-    }
+impl Query for HistoryQuery {
+    type Output = HistoryTransactions;
+    const ENDPOINT: Endpoint = Endpoint::History;
 
-    setter!(token_id, u32);
-    setter!(hez_ethereum_address, &'a str);
-    setter!(from_hez_ethereum_address, &'a str);
-    setter!(to_hez_ethereum_address, &'a str);
-    setter!(bjj, &'a str);
-    setter!(from_bjj, &'a str);
-    setter!(to_bjj, &'a str);
-    setter!(account_index, &'a str);
-    setter!(from_account_index, &'a str);
-    setter!(to_account_index, &'a str);
-    setter!(batch_num, u32);
-    setter!(r#type, TransactionType);
-
-    pagination_setters!();
-
-    pub async fn fetch(&self) -> Result<(Vec<HistoryTransaction>, u64), super::ErrorKind> {
+    fn url(&self, base: &Url) -> Result<Url, ErrorKind> {
         if is_more_than_one_defined!(
             self.hez_ethereum_address,
             self.from_hez_ethereum_address,
@@ -95,7 +65,7 @@ impl<'a> TransactionsHistoryGetOptions<'a> {
             )));
         }
 
-        let mut url = self.url.join("transactions-history").unwrap();
+        let mut url = base.join("transactions-history").unwrap();
         {
             let mut query_pairs = url.query_pairs_mut();
 
@@ -134,10 +104,51 @@ impl<'a> TransactionsHistoryGetOptions<'a> {
             );
             fetch_stmt!(self, query_pairs, to_account_index, "toAccountIndex", &str);
             fetch_stmt!(self, query_pairs, batch_num, "batchNum");
+            fetch_stmt!(self, query_pairs, r#type, "type", enum);
 
             pagination_fetch_stmts!(self, query_pairs);
         }
-        let history_transactions: HistoryTransactions = self.http.get(&url).await?;
+        Ok(url)
+    }
+}
+
+paged_query!(HistoryQuery, HistoryTransaction, transactions);
+
+pub struct TransactionsHistoryGetOptions {
+    api: HermezApi,
+    query: HistoryQuery,
+}
+
+impl TransactionsHistoryGetOptions {
+    pub fn new(api: &HermezApi) -> Self {
+        Self {
+            api: api.clone(),
+            query: Default::default(),
+        }
+    }
+
+    setter!(token_id, u32);
+    setter!(hez_ethereum_address, &str);
+    setter!(from_hez_ethereum_address, &str);
+    setter!(to_hez_ethereum_address, &str);
+    setter!(bjj, &str);
+    setter!(from_bjj, &str);
+    setter!(to_bjj, &str);
+    setter!(account_index, &str);
+    setter!(from_account_index, &str);
+    setter!(to_account_index, &str);
+    setter!(batch_num, u32);
+    setter!(r#type, TransactionType);
+
+    pagination_setters!();
+
+    /// The query built so far.
+    pub fn query(&self) -> &HistoryQuery {
+        &self.query
+    }
+
+    pub async fn fetch(&self) -> Result<(Vec<HistoryTransaction>, u64), super::ErrorKind> {
+        let history_transactions = self.api.run(&self.query).await?;
         Ok((
             history_transactions.transactions,
             history_transactions.pending_items,
@@ -147,18 +158,18 @@ impl<'a> TransactionsHistoryGetOptions<'a> {
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct HistoryTransactions {
+pub struct HistoryTransactions {
     pub pending_items: u64,
     pub transactions: Vec<HistoryTransaction>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub enum L1OrL2 {
     L1,
     L2,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub enum TransactionType {
     Exit,
     Transfer,
@@ -172,7 +183,7 @@ pub enum TransactionType {
     TransferToBJJ,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct HistoryTransaction {
     #[serde(rename = "L1orL2")]
@@ -201,7 +212,7 @@ pub struct HistoryTransaction {
     pub l2_info: Option<L2Info>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct L1Info {
     pub to_forge_l1_transactions_num: Option<u32>,
@@ -213,7 +224,7 @@ pub struct L1Info {
     pub ethereum_block_num: u128,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 pub struct L2Info {
     pub fee: u32,
     #[serde(rename = "historicFeeUSD")]
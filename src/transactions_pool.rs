@@ -1,15 +1,25 @@
+use futures::future::join_all;
+use num_bigint::BigUint;
 use serde::{Deserialize, Serialize};
+use sha3::{Digest, Keccak256};
+use std::convert::TryFrom;
 use std::fmt;
 use url::Url;
 
-use super::http::Http;
+use super::Endpoint;
 use super::ErrorKind;
+use super::HermezApi;
 use super::PaginationOrder;
+use super::Query;
 
+use super::babyjubjub::{parse_bjj, PrivateKey};
+use super::poseidon::poseidon;
+use super::state::NextForger;
+use super::state_tree::{account_number, parse_eth_addr};
 use super::tokens::Token;
 use super::transactions_history::TransactionType;
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub enum PoolL2TransactionState {
     #[serde(rename = "pend")]
     Pend,
@@ -36,69 +46,35 @@ impl fmt::Display for PoolL2TransactionState {
     }
 }
 
-pub struct TransactionsPoolGetOptions<'a> {
-    http: &'a Http,
-    url: &'a Url,
-
-    state: Option<PoolL2TransactionState>,
-    token_id: Option<u32>,
-    account_index: Option<&'a str>,
-    from_account_index: Option<&'a str>,
-    to_account_index: Option<&'a str>,
-    r#type: Option<TransactionType>,
-    hez_ethereum_address: Option<&'a str>,
-    from_hez_ethereum_address: Option<&'a str>,
-    to_hez_ethereum_address: Option<&'a str>,
-    bjj: Option<&'a str>,
-    from_bjj: Option<&'a str>,
-    to_bjj: Option<&'a str>,
-
-    from_item: Option<u64>,
-    order: Option<PaginationOrder>,
-    limit: Option<u64>,
-}
-
-impl<'a> TransactionsPoolGetOptions<'a> {
-    pub fn new(http: &'a Http, url: &'a Url) -> Self {
-        Self {
-            http,
-            url,
-
-            state: None,
-            token_id: None,
-            account_index: None,
-            from_account_index: None,
-            to_account_index: None,
-            r#type: None,
-            hez_ethereum_address: None,
-            from_hez_ethereum_address: None,
-            to_hez_ethereum_address: None,
-            bjj: None,
-            from_bjj: None,
-            to_bjj: None,
-
-            from_item: None,
-            order: None,
-            limit: None,
-        }
-    }
+/// Owned description of a request to the transactions pool endpoint.
+///
+/// Can be cloned, stored and serialized, and then run with `HermezApi::run()`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PoolQuery {
+    pub state: Option<PoolL2TransactionState>,
+    pub token_id: Option<u32>,
+    pub account_index: Option<String>,
+    pub from_account_index: Option<String>,
+    pub to_account_index: Option<String>,
+    pub r#type: Option<TransactionType>,
+    pub hez_ethereum_address: Option<String>,
+    pub from_hez_ethereum_address: Option<String>,
+    pub to_hez_ethereum_address: Option<String>,
+    pub bjj: Option<String>,
+    pub from_bjj: Option<String>,
+    pub to_bjj: Option<String>,
 
-    setter!(state, PoolL2TransactionState);
-    setter!(token_id, u32);
-    setter!(account_index, &'a str);
-    setter!(from_account_index, &'a str);
-    setter!(to_account_index, &'a str);
-    setter!(r#type, TransactionType);
-    setter!(hez_ethereum_address, &'a str);
-    setter!(from_hez_ethereum_address, &'a str);
-    setter!(to_hez_ethereum_address, &'a str);
-    setter!(bjj, &'a str);
-    setter!(from_bjj, &'a str);
-    setter!(to_bjj, &'a str);
+    pub from_item: Option<u64>,
+    pub order: Option<PaginationOrder>,
+    pub limit: Option<u64>,
+}
 
-    pagination_setters!();
+impl Query for PoolQuery {
+    type Output = PoolL2Transactions;
+    const ENDPOINT: Endpoint = Endpoint::Pool;
 
-    pub async fn fetch(&self) -> Result<(Vec<PoolL2Transaction>, u64), ErrorKind> {
+    fn url(&self, base: &Url) -> Result<Url, ErrorKind> {
         if is_more_than_one_defined!(
             self.account_index,
             self.from_account_index,
@@ -115,7 +91,7 @@ impl<'a> TransactionsPoolGetOptions<'a> {
             )));
         }
 
-        let mut url = self.url.join("transactions_pool").unwrap();
+        let mut url = base.join("transactions-pool").unwrap();
         {
             let mut query_pairs = url.query_pairs_mut();
 
@@ -158,7 +134,47 @@ impl<'a> TransactionsPoolGetOptions<'a> {
 
             pagination_fetch_stmts!(self, query_pairs);
         }
-        let transactions_pool: PoolL2Transactions = self.http.get(&url).await?;
+        Ok(url)
+    }
+}
+
+paged_query!(PoolQuery, PoolL2Transaction, transactions_pool);
+
+pub struct TransactionsPoolGetOptions {
+    api: HermezApi,
+    query: PoolQuery,
+}
+
+impl TransactionsPoolGetOptions {
+    pub fn new(api: &HermezApi) -> Self {
+        Self {
+            api: api.clone(),
+            query: Default::default(),
+        }
+    }
+
+    setter!(state, PoolL2TransactionState);
+    setter!(token_id, u32);
+    setter!(account_index, &str);
+    setter!(from_account_index, &str);
+    setter!(to_account_index, &str);
+    setter!(r#type, TransactionType);
+    setter!(hez_ethereum_address, &str);
+    setter!(from_hez_ethereum_address, &str);
+    setter!(to_hez_ethereum_address, &str);
+    setter!(bjj, &str);
+    setter!(from_bjj, &str);
+    setter!(to_bjj, &str);
+
+    pagination_setters!();
+
+    /// The query built so far.
+    pub fn query(&self) -> &PoolQuery {
+        &self.query
+    }
+
+    pub async fn fetch(&self) -> Result<(Vec<PoolL2Transaction>, u64), ErrorKind> {
+        let transactions_pool = self.api.run(&self.query).await?;
         Ok((
             transactions_pool.transactions_pool,
             transactions_pool.pending_items,
@@ -166,7 +182,7 @@ impl<'a> TransactionsPoolGetOptions<'a> {
     }
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
 pub enum TransactionTypeL2 {
     Exit,
     Transfer,
@@ -176,15 +192,16 @@ pub enum TransactionTypeL2 {
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
-struct PoolL2Transactions {
+pub struct PoolL2Transactions {
     pub pending_items: u64,
     pub transactions_pool: Vec<PoolL2Transaction>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct PoolL2Transaction {
     pub id: String,
+    pub item_id: u64,
     pub r#type: TransactionTypeL2,
     pub from_account_index: String,
     pub from_hez_ethereum_address: Option<String>,
@@ -213,74 +230,738 @@ pub struct PoolL2Transaction {
     pub token: Token,
 }
 
-#[derive(Serialize, Debug, Default)]
+#[derive(Serialize, Clone, Debug, Default)]
 #[serde(rename_all = "camelCase")]
-pub struct PostPoolL2Transaction<'a> {
-    pub id: Option<&'a str>,
+pub struct PostPoolL2Transaction {
+    pub id: Option<String>,
     pub r#type: Option<TransactionTypeL2>,
     pub token_id: Option<u32>,
-    pub from_account_index: Option<&'a str>,
-    pub to_account_index: Option<&'a str>,
-    pub to_hez_ethereum_address: Option<&'a str>,
-    pub to_bjj: Option<&'a str>,
-    pub amount: Option<&'a str>,
+    pub from_account_index: Option<String>,
+    pub to_account_index: Option<String>,
+    pub to_hez_ethereum_address: Option<String>,
+    pub to_bjj: Option<String>,
+    pub amount: Option<String>,
     pub fee: Option<u16>,
     pub nonce: Option<u128>,
-    pub signature: Option<&'a str>,
+    pub signature: Option<String>,
 
-    pub request_from_account_index: Option<&'a str>,
-    pub request_to_account_index: Option<&'a str>,
-    pub request_to_hez_ethereum_address: Option<&'a str>,
-    pub request_to_bjj: Option<&'a str>,
+    pub request_from_account_index: Option<String>,
+    pub request_to_account_index: Option<String>,
+    pub request_to_hez_ethereum_address: Option<String>,
+    pub request_to_bjj: Option<String>,
     pub request_token_id: Option<u32>,
-    pub request_amount: Option<&'a str>,
+    pub request_amount: Option<String>,
     pub request_fee: Option<u16>,
     pub request_nonce: Option<u128>,
 }
 
-pub struct TransactionsPoolPostOptions<'a> {
-    http: &'a Http,
-    url: &'a Url,
+impl PostPoolL2Transaction {
+    /// The id of the transaction, derived as the node does from the sender,
+    /// token, amount, nonce and fee.
+    pub fn tx_id(&self) -> Result<String, ErrorKind> {
+        let from_idx = account_number(required(&self.from_account_index, "from_account_index")?)?;
+        let mut bytes = Vec::with_capacity(21);
+        bytes.extend_from_slice(&from_idx.to_be_bytes()[2..]);
+        bytes.extend_from_slice(&required(&self.token_id, "token_id")?.to_be_bytes());
+        bytes.extend_from_slice(&self.float40_amount()?.to_be_bytes()[3..]);
+        bytes.extend_from_slice(&self.nonce_bytes()?);
+        bytes.push(self.fee_selector()?);
+
+        let hash = Keccak256::digest(&bytes);
+        let hex: String = hash.iter().map(|byte| format!("{:02x}", byte)).collect();
+        Ok(format!("0x02{}", hex))
+    }
+
+    /// The field element signed by the sender, the Poseidon hash of the
+    /// fields of the transaction. Atomic transactions, with `request_*`
+    /// fields, are not supported.
+    pub fn hash_to_sign(&self, chain_id: u16) -> Result<BigUint, ErrorKind> {
+        if self.request_from_account_index.is_some()
+            || self.request_to_account_index.is_some()
+            || self.request_to_hez_ethereum_address.is_some()
+            || self.request_to_bjj.is_some()
+            || self.request_token_id.is_some()
+            || self.request_amount.is_some()
+            || self.request_fee.is_some()
+            || self.request_nonce.is_some()
+        {
+            return Err(ErrorKind::Api(String::from(
+                "Signing atomic transactions is not supported",
+            )));
+        }
+
+        let from_idx = account_number(required(&self.from_account_index, "from_account_index")?)?;
+        let to_idx = match &self.to_account_index {
+            Some(to_account_index) => account_number(to_account_index)?,
+            None => 0,
+        };
+        let (to_bjj_sign, to_bjj_ay) = parse_bjj(self.to_bjj.as_deref().unwrap_or(""))?;
+        let to_eth_addr = parse_eth_addr(self.to_hez_ethereum_address.as_deref().unwrap_or(""))?;
+
+        // Packed as sign, fee, nonce, token id, to and from indexes, chain
+        // id and a constant, on 1, 1, 5, 4, 6, 6, 2 and 4 bytes.
+        let mut compressed = vec![to_bjj_sign as u8, self.fee_selector()?];
+        compressed.extend_from_slice(&self.nonce_bytes()?);
+        compressed.extend_from_slice(&required(&self.token_id, "token_id")?.to_be_bytes());
+        compressed.extend_from_slice(&to_idx.to_be_bytes()[2..]);
+        compressed.extend_from_slice(&from_idx.to_be_bytes()[2..]);
+        compressed.extend_from_slice(&chain_id.to_be_bytes());
+        compressed.extend_from_slice(&SIGNATURE_CONSTANT.to_be_bytes());
+
+        // The amount on 5 bytes followed by the recipient Ethereum address.
+        let e1 = (BigUint::from(self.float40_amount()?) << 160u32) + to_eth_addr;
+
+        let zero = BigUint::from(0u32);
+        Ok(poseidon(&[
+            BigUint::from_bytes_be(&compressed),
+            e1,
+            to_bjj_ay,
+            zero.clone(),
+            zero.clone(),
+            zero,
+        ]))
+    }
+
+    fn float40_amount(&self) -> Result<u64, ErrorKind> {
+        let amount = super::parse_amount(required(&self.amount, "amount")?)?;
+        float40(amount).ok_or_else(|| {
+            ErrorKind::Api(format!(
+                "Amount {} cannot be sent exactly, the closest lower amount is {}",
+                amount,
+                float40_floor(amount)
+            ))
+        })
+    }
+
+    fn nonce_bytes(&self) -> Result<[u8; 5], ErrorKind> {
+        let nonce = *required(&self.nonce, "nonce")?;
+        if nonce >= 1 << 40 {
+            return Err(ErrorKind::Api(format!("Nonce {} is too large", nonce)));
+        }
+        let mut bytes = [0; 5];
+        bytes.copy_from_slice(&nonce.to_be_bytes()[11..]);
+        Ok(bytes)
+    }
 
-    body: PostPoolL2Transaction<'a>,
+    fn fee_selector(&self) -> Result<u8, ErrorKind> {
+        let fee = *required(&self.fee, "fee")?;
+        u8::try_from(fee).map_err(|_| ErrorKind::Api(format!("Fee selector {} is too large", fee)))
+    }
 }
 
-impl<'a> TransactionsPoolPostOptions<'a> {
-    pub fn new(http: &'a Http, url: &'a Url) -> Self {
+// Ends the compressed data of L2 transactions.
+const SIGNATURE_CONSTANT: u32 = 3322668559;
+
+fn required<'a, T>(field: &'a Option<T>, name: &str) -> Result<&'a T, ErrorKind> {
+    field
+        .as_ref()
+        .ok_or_else(|| ErrorKind::Api(format!("{} is a required parameter", name)))
+}
+
+/// Encodes an amount as sent in L2 transactions, a 35 bit mantissa times a
+/// power of ten up to 10^31, with the exponent in the top 5 bits. `None` if
+/// the amount has no exact encoding.
+// `is_multiple_of` would need Rust 1.87.
+#[allow(clippy::manual_is_multiple_of)]
+pub fn float40(amount: u128) -> Option<u64> {
+    let mut mantissa = amount;
+    let mut exponent = 0;
+    while mantissa >= 1 << 35 && mantissa % 10 == 0 {
+        mantissa /= 10;
+        exponent += 1;
+    }
+    if mantissa >= 1 << 35 || exponent > 31 {
+        return None;
+    }
+    Some((exponent << 35) | mantissa as u64)
+}
+
+/// The highest amount not above `amount` with an exact encoding by
+/// `float40()`.
+pub fn float40_floor(amount: u128) -> u128 {
+    let mut mantissa = amount;
+    let mut exponent = 0;
+    while mantissa >= 1 << 35 {
+        mantissa /= 10;
+        exponent += 1;
+    }
+    mantissa * 10u128.pow(exponent)
+}
+
+// The fee factor of each selector below 224, times 2^60 and rounded down, as
+// in the node and the circuits. Selectors 1 to 32 stand for 10^(-24 + i/2),
+// and 33 to 223 for 10^(-8 + 0.041666666666667 (i - 32)).
+const FEE_FACTOR_LSH60: [u128; 224] = [
+    0,
+    0,
+    0,
+    0,
+    0,
+    0,
+    0,
+    0,
+    0,
+    0,
+    0,
+    0,
+    1,
+    3,
+    11,
+    36,
+    115,
+    364,
+    1152,
+    3645,
+    11529,
+    36458,
+    115292,
+    364585,
+    1152921,
+    3645857,
+    11529215,
+    36458579,
+    115292150,
+    364585791,
+    1152921504,
+    3645857917,
+    11529215046,
+    12690139800,
+    13967962910,
+    15374455359,
+    16922573400,
+    18626577904,
+    20502165730,
+    22566614317,
+    24838940844,
+    27340077407,
+    30093063843,
+    33123259967,
+    36458579179,
+    40129745594,
+    44170577070,
+    48618296722,
+    53513875818,
+    58902411194,
+    64833540674,
+    71361900321,
+    78547627733,
+    86456916011,
+    95162623518,
+    104744945028,
+    115292150460,
+    126901398003,
+    139679629105,
+    153744553599,
+    169225734008,
+    186265779049,
+    205021657303,
+    225666143174,
+    248389408440,
+    273400774071,
+    300930638436,
+    331232599678,
+    364585791794,
+    401297455949,
+    441705770702,
+    486182967220,
+    535138758181,
+    589024111941,
+    648335406741,
+    713619003216,
+    785476277335,
+    864569160119,
+    951626235188,
+    1047449450282,
+    1152921504606,
+    1269013980032,
+    1396796291058,
+    1537445535995,
+    1692257340087,
+    1862657790493,
+    2050216573033,
+    2256661431742,
+    2483894084408,
+    2734007740716,
+    3009306384365,
+    3312325996782,
+    3645857917946,
+    4012974559497,
+    4417057707021,
+    4861829672203,
+    5351387581815,
+    5890241119415,
+    6483354067410,
+    7136190032163,
+    7854762773350,
+    8645691601195,
+    9516262351880,
+    10474494502821,
+    11529215046069,
+    12690139800321,
+    13967962910589,
+    15374455359953,
+    16922573400877,
+    18626577904933,
+    20502165730335,
+    22566614317427,
+    24838940844088,
+    27340077407167,
+    30093063843653,
+    33123259967829,
+    36458579179461,
+    40129745594970,
+    44170577070218,
+    48618296722035,
+    53513875818155,
+    58902411194158,
+    64833540674111,
+    71361900321637,
+    78547627733507,
+    86456916011959,
+    95162623518806,
+    104744945028218,
+    115292150460693,
+    126901398003217,
+    139679629105898,
+    153744553599533,
+    169225734008779,
+    186265779049340,
+    205021657303361,
+    225666143174277,
+    248389408440894,
+    273400774071677,
+    300930638436537,
+    331232599678303,
+    364585791794624,
+    401297455949715,
+    441705770702192,
+    486182967220368,
+    535138758181564,
+    589024111941594,
+    648335406741122,
+    713619003216384,
+    785476277335086,
+    864569160119614,
+    951626235188078,
+    1047449450282203,
+    1152921504606953,
+    1269013980032201,
+    1396796291059013,
+    1537445535995362,
+    1692257340087825,
+    1862657790493439,
+    2050216573033649,
+    2256661431742819,
+    2483894084408989,
+    2734007740716820,
+    3009306384365428,
+    3312325996783091,
+    3645857917946316,
+    4012974559497230,
+    4417057707022008,
+    4861829672203778,
+    5351387581815743,
+    5890241119416050,
+    6483354067411341,
+    7136190032163974,
+    7854762773351010,
+    8645691601196304,
+    9516262351880961,
+    10474494502822226,
+    11529215046069744,
+    12690139800322251,
+    13967962910590391,
+    15374455359953904,
+    16922573400878566,
+    18626577904934734,
+    20502165730336870,
+    22566614317428611,
+    24838940844090351,
+    27340077407168709,
+    30093063843654844,
+    33123259967831526,
+    36458579179463839,
+    40129745594973044,
+    44170577070220896,
+    48618296722038678,
+    53513875818158421,
+    58902411194161586,
+    64833540674114609,
+    71361900321641055,
+    78547627733511549,
+    86456916011964633,
+    95162623518811372,
+    104744945028224191,
+    115292150460699563,
+    126901398003224855,
+    139679629105906491,
+    153744553599541874,
+    169225734008788786,
+    186265779049350772,
+    205021657303372486,
+    225666143174290271,
+    248389408440908090,
+    273400774071692128,
+    300930638436553984,
+    331232599678321364,
+    364585791794645112,
+    401297455949737837,
+    441705770702217104,
+    486182967220395738,
+    535138758181594069,
+    589024111941626719,
+    648335406741158037,
+    713619003216423698,
+    785476277335129960,
+    864569160119662258,
+    951626235188131256,
+    1047449450282261208,
+];
+
+/// The fee paid for a transfer of `amount` with a fee selector, as the rollup
+/// computes it: selectors up to 223 take `amount` times a factor below one,
+/// kept with 60 fractional bits, and selectors above multiply by a power of
+/// ten. `None` on overflow.
+pub fn fee_amount(amount: u128, selector: u32) -> Option<u128> {
+    let selector = selector.min(255) as usize;
+    let factor = match FEE_FACTOR_LSH60.get(selector) {
+        Some(&factor) => factor,
+        None => {
+            return 10u128
+                .checked_pow(selector as u32 - 224)?
+                .checked_mul(amount)
+        }
+    };
+
+    // amount * factor >> 60, without overflowing on the low bits.
+    let high = amount >> 60;
+    let low = amount & ((1 << 60) - 1);
+    high.checked_mul(factor)?.checked_add((low * factor) >> 60)
+}
+
+pub struct TransactionsPoolPostOptions {
+    api: HermezApi,
+
+    body: PostPoolL2Transaction,
+}
+
+impl TransactionsPoolPostOptions {
+    pub fn new(api: &HermezApi) -> Self {
         Self {
-            http,
-            url,
+            api: api.clone(),
             body: Default::default(),
         }
     }
 
-    setter_body!(id, &'a str);
+    setter_body!(id, &str);
     setter_body!(r#type, TransactionTypeL2);
     setter_body!(token_id, u32);
-    setter_body!(from_account_index, &'a str);
-    setter_body!(amount, &'a str);
+    setter_body!(from_account_index, &str);
+    setter_body!(amount, &str);
     setter_body!(fee, u16);
     setter_body!(nonce, u128);
-    setter_body!(signature, &'a str);
+    setter_body!(signature, &str);
 
-    setter_body!(to_account_index, &'a str);
-    setter_body!(to_hez_ethereum_address, &'a str);
-    setter_body!(to_bjj, &'a str);
+    setter_body!(to_account_index, &str);
+    setter_body!(to_hez_ethereum_address, &str);
+    setter_body!(to_bjj, &str);
 
-    setter_body!(request_from_account_index, &'a str);
-    setter_body!(request_to_account_index, &'a str);
-    setter_body!(request_to_hez_ethereum_address, &'a str);
-    setter_body!(request_to_bjj, &'a str);
+    setter_body!(request_from_account_index, &str);
+    setter_body!(request_to_account_index, &str);
+    setter_body!(request_to_hez_ethereum_address, &str);
+    setter_body!(request_to_bjj, &str);
     setter_body!(request_token_id, u32);
-    setter_body!(request_amount, &'a str);
+    setter_body!(request_amount, &str);
     setter_body!(request_fee, u16);
     setter_body!(request_nonce, u128);
 
+    /// Sets the id of the transaction, and signs it with the key of the
+    /// sender for the network with chain id `chain_id`, given by
+    /// `Config::chain_id`. Every other field must be set first.
+    pub fn sign(&mut self, key: &PrivateKey, chain_id: u16) -> Result<&mut Self, ErrorKind> {
+        let hash = self.body.hash_to_sign(chain_id)?;
+        self.body.id = Some(self.body.tx_id()?);
+        self.body.signature = Some(key.sign(&hash).to_hex());
+        Ok(self)
+    }
+
+    /// The transaction built so far.
+    pub fn body(&self) -> &PostPoolL2Transaction {
+        &self.body
+    }
+
     pub async fn fetch(&self) -> Result<String, ErrorKind> {
         test_required!(self, id);
 
-        let url = self.url.join("transactions_pool").unwrap();
-        let transaction_id: String = self.http.post(&url, &self.body).await?;
+        Self::post(&self.api, &self.body).await
+    }
+
+    async fn post(api: &HermezApi, body: &PostPoolL2Transaction) -> Result<String, ErrorKind> {
+        let url = api.inner.url.join("transactions-pool").unwrap();
+        let transaction_id: String = api.inner.http.post(Endpoint::PoolPost, &url, body).await?;
         Ok(transaction_id)
     }
+
+    /// Posts the transaction to the coordinators forging the current and
+    /// next slots, as listed in `StateNetwork::next_forgers`, rather than to
+    /// the configured node.
+    ///
+    /// Each coordinator is sent the transaction once, even when it forges
+    /// several of the slots. Returns the outcome for each coordinator. A
+    /// coordinator that already holds the transaction counts as accepting it.
+    /// Fails when the node lists no next forgers.
+    ///
+    /// # Example
+    ///
+    /// ```no_run
+    /// use hermez_api::HermezApi;
+    ///
+    /// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+    /// let submissions = api
+    ///     .transactions_pool_post_options()
+    ///     .id("0x02...")
+    ///     // ...
+    ///     .send_to_next_forgers()
+    ///     .await
+    ///     .unwrap();
+    ///
+    /// for submission in submissions.iter().filter(|s| s.result.is_ok()) {
+    ///     println!("accepted by {} for slots {:?}", submission.url, submission.slots);
+    /// }
+    /// ```
+    pub async fn send_to_next_forgers(&self) -> Result<Vec<ForgerSubmission>, ErrorKind> {
+        test_required!(self, id);
+
+        let state = self.api.get_state().await?;
+        let mut submissions = by_coordinator(state.network.next_forgers);
+        if submissions.is_empty() {
+            return Err(ErrorKind::Api(String::from(
+                "The node lists no next forgers",
+            )));
+        }
+
+        let id = self.body.id.as_deref().unwrap_or_default();
+        let results = join_all(submissions.iter().map(|submission| async move {
+            let api = self
+                .api
+                .with_base_url(submission.url.trim_end_matches('/'))
+                .map_err(|err| ErrorKind::Api(format!("Invalid coordinator URL: {}", err)))?;
+            accepted(Self::post(&api, &self.body).await, id)
+        }))
+        .await;
+
+        for (submission, result) in submissions.iter_mut().zip(results) {
+            submission.result = result;
+        }
+        Ok(submissions)
+    }
+}
+
+// One submission per coordinator of `next_forgers`, with the slots it forges,
+// in order of first appearance.
+fn by_coordinator(next_forgers: Vec<NextForger>) -> Vec<ForgerSubmission> {
+    let mut submissions: Vec<ForgerSubmission> = Vec::new();
+    for forger in next_forgers {
+        let url = forger.coordinator.url;
+        match submissions.iter_mut().find(|s| s.url == url) {
+            Some(submission) => submission.slots.push(forger.period.slot_num),
+            None => submissions.push(ForgerSubmission {
+                forger_addr: forger.coordinator.forger_addr,
+                url,
+                slots: vec![forger.period.slot_num],
+                result: Err(ErrorKind::Api(String::from("Not sent"))),
+            }),
+        }
+    }
+    submissions
+}
+
+// The outcome of posting transaction `id` to a coordinator. The node answers
+// 409 Conflict for a transaction already in its pool, which is as good as
+// accepting it. Any other id in the answer is an error.
+fn accepted(result: Result<String, ErrorKind>, id: &str) -> Result<String, ErrorKind> {
+    match result {
+        Ok(returned) if returned.eq_ignore_ascii_case(id) => Ok(returned),
+        Ok(returned) => Err(ErrorKind::Api(format!(
+            "Coordinator returned transaction id {} instead of {}",
+            returned, id
+        ))),
+        Err(ErrorKind::Http(409, _)) => Ok(id.to_owned()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Outcome of posting a transaction to a coordinator with
+/// `TransactionsPoolPostOptions::send_to_next_forgers()`.
+#[derive(Clone, Debug)]
+pub struct ForgerSubmission {
+    pub forger_addr: String,
+    /// Base URL of the coordinator's API.
+    pub url: String,
+    /// Upcoming slots forged by the coordinator.
+    pub slots: Vec<u32>,
+    /// Transaction id returned by the coordinator, or the error met.
+    pub result: Result<String, ErrorKind>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        accepted, by_coordinator, fee_amount, float40, float40_floor, PoolQuery,
+        PostPoolL2Transaction, TransactionTypeL2, FEE_FACTOR_LSH60,
+    };
+    use crate::babyjubjub::PrivateKey;
+    use crate::state::NextForger;
+    use crate::test_support;
+    use crate::{ErrorKind, HermezApi};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_sign() {
+        assert_eq!(Some(1000), float40(1000));
+        assert_eq!(Some((8 << 35) | 10u64.pow(10)), float40(10u128.pow(18)));
+        assert_eq!(None, float40((1 << 35) + 1));
+        assert_eq!(34359738360, float40_floor((1 << 35) + 1));
+
+        let transaction = PostPoolL2Transaction {
+            r#type: Some(TransactionTypeL2::Transfer),
+            token_id: Some(1),
+            from_account_index: Some(String::from("hez:TKN:256")),
+            to_account_index: Some(String::from("hez:TKN:257")),
+            amount: Some(String::from("1000")),
+            fee: Some(126),
+            nonce: Some(0),
+            ..Default::default()
+        };
+        let id = transaction.tx_id().unwrap();
+        assert!(id.starts_with("0x02"));
+        assert_eq!(68, id.len());
+
+        let key = PrivateKey::new([1; 32]);
+        let hash = transaction.hash_to_sign(5).unwrap();
+        assert_ne!(hash, transaction.hash_to_sign(1).unwrap());
+        assert!(key.public_key().verify(&hash, &key.sign(&hash)));
+
+        let atomic = PostPoolL2Transaction {
+            request_nonce: Some(1),
+            ..transaction
+        };
+        assert!(atomic.hash_to_sign(5).is_err());
+    }
+
+    #[test]
+    fn test_fee_amount() {
+        const ETHER: u128 = 1_000_000_000_000_000_000;
+
+        // Factors of the node for 10^-16, 10^-8, 10^-7 and 10^-4.
+        assert_eq!(0, FEE_FACTOR_LSH60[1]);
+        assert_eq!(115, FEE_FACTOR_LSH60[16]);
+        assert_eq!(11529215046, FEE_FACTOR_LSH60[32]);
+        assert_eq!(115292150460, FEE_FACTOR_LSH60[56]);
+        assert_eq!(115292150460693, FEE_FACTOR_LSH60[128]);
+        assert_eq!(1047449450282261208, FEE_FACTOR_LSH60[223]);
+
+        assert_eq!(Some(0), fee_amount(ETHER, 0));
+        assert_eq!(Some(9_999_999_999), fee_amount(ETHER, 32));
+        assert_eq!(Some(100_000_000_000_007), fee_amount(ETHER, 128));
+        assert_eq!(Some(ETHER), fee_amount(ETHER, 224));
+        assert_eq!(Some(100 * ETHER), fee_amount(ETHER, 226));
+        assert_eq!(None, fee_amount(u128::MAX, 230));
+        assert_eq!(fee_amount(ETHER, 255), fee_amount(ETHER, 300));
+    }
+
+    fn next_forger(url: &str, slot_num: u32) -> NextForger {
+        serde_json::from_value(serde_json::json!({
+            "coordinator": {
+                "itemId": 0,
+                "forgerAddr": format!("forger of {}", url),
+                "bidderAddr": "0x0000000000000000000000000000000000000000",
+                "URL": url,
+                "ethereumBlock": 0
+            },
+            "period": {
+                "slotNum": slot_num,
+                "fromBlock": 0,
+                "toBlock": 0,
+                "fromTimestamp": "2021-06-12T04:53:21Z",
+                "toTimestamp": "2021-06-12T05:03:06Z"
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_paths() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        let url = test_support::serve(move |request| {
+            seen.lock()
+                .unwrap()
+                .push(format!("{} {}", request.method, request.path()));
+            match (request.method.as_str(), request.path()) {
+                ("POST", "transactions-pool") => {
+                    let body: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+                    (200, body["id"].clone())
+                }
+                ("GET", "transactions-pool") => (
+                    200,
+                    serde_json::json!({ "transactionsPool": [], "pendingItems": 0 }),
+                ),
+                _ => (404, serde_json::json!({ "message": "Not found" })),
+            }
+        });
+        let api = HermezApi::new(&url).unwrap();
+
+        async_std::task::block_on(async {
+            let id = api
+                .transactions_pool_post_options()
+                .id("0x02aa")
+                .fetch()
+                .await
+                .unwrap();
+            assert_eq!("0x02aa", id);
+            assert!(api.get_transactions_pool("0x02aa").await.is_err());
+            assert!(api.run_all(&PoolQuery::default()).await.unwrap().is_empty());
+        });
+        assert_eq!(
+            vec![
+                "POST transactions-pool",
+                "GET transactions-pool/0x02aa",
+                "GET transactions-pool",
+            ],
+            *requests.lock().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_by_coordinator() {
+        assert!(by_coordinator(Vec::new()).is_empty());
+
+        let submissions = by_coordinator(vec![
+            next_forger("https://a", 10),
+            next_forger("https://b", 11),
+            next_forger("https://a", 12),
+        ]);
+        assert_eq!(2, submissions.len());
+        assert_eq!("https://a", submissions[0].url);
+        assert_eq!("forger of https://a", submissions[0].forger_addr);
+        assert_eq!(vec![10, 12], submissions[0].slots);
+        assert_eq!("https://b", submissions[1].url);
+        assert_eq!(vec![11], submissions[1].slots);
+    }
+
+    #[test]
+    fn test_accepted() {
+        let id = "0x02aa";
+        assert_eq!(
+            id,
+            accepted(Ok(String::from("0x02AA")), id)
+                .unwrap()
+                .to_lowercase()
+        );
+        assert!(accepted(Ok(String::from("0x02bb")), id).is_err());
+        let duplicate = Err(ErrorKind::Http(409, String::from("Item already exists")));
+        assert_eq!(id, accepted(duplicate, id).unwrap());
+        assert!(accepted(Err(ErrorKind::Http(500, String::new())), id).is_err());
+    }
 }
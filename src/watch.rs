@@ -0,0 +1,760 @@
+//! Polling watchers, that turn the API into streams of new objects.
+
+use futures::stream::{self, Stream, StreamExt};
+use futures_timer::Delay;
+use serde::{Deserialize, Serialize};
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, VecDeque};
+use std::time::Duration;
+
+use super::accounts::{Account, AccountsQuery};
+use super::batches::{Batch, FullBatch};
+use super::transactions_history::{HistoryQuery, HistoryTransaction};
+use super::{ErrorKind, HermezApi, PaginationOrder};
+
+// Number of items requested at once while catching up.
+const ITEMS_PER_REQUEST: u64 = 100;
+
+/// Watches for newly forged batches. Created with `HermezApi::watch_batches()`.
+///
+/// Batches are returned in order, each exactly once, starting from the batch
+/// number given. To resume after a restart without skipping or repeating a
+/// batch, store the number of the last batch handled, and start from the
+/// one after it.
+///
+/// Unless a fixed interval is set, the node is polled at a quarter of the
+/// average time between batches, as reported by `StateMetrics::batch_frequency`,
+/// kept between `min_interval` and `max_interval`.
+///
+/// # Example
+///
+/// ```no_run
+/// use futures::StreamExt;
+/// use hermez_api::HermezApi;
+///
+/// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+/// let mut batches = api.watch_batches(checkpoint + 1).stream().boxed();
+///
+/// while let Some(batch) = batches.next().await {
+///     let batch = batch.unwrap();
+///     // ...
+///     checkpoint = batch.batch_num;
+/// }
+/// ```
+#[derive(Clone)]
+pub struct BatchWatcher {
+    api: HermezApi,
+    from_batch_num: u32,
+    interval: Option<Duration>,
+    min_interval: Duration,
+    max_interval: Duration,
+}
+
+impl BatchWatcher {
+    pub fn new(api: &HermezApi, from_batch_num: u32) -> Self {
+        Self {
+            api: api.clone(),
+            from_batch_num,
+            interval: None,
+            min_interval: Duration::from_secs(1),
+            max_interval: Duration::from_secs(60),
+        }
+    }
+
+    /// Polls at a fixed interval, rather than adapting to the batch frequency.
+    pub fn interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = Some(interval);
+        self
+    }
+
+    /// The shortest interval between polls, one second by default.
+    pub fn min_interval(&mut self, min_interval: Duration) -> &mut Self {
+        self.min_interval = min_interval;
+        self
+    }
+
+    /// The longest interval between polls, one minute by default.
+    pub fn max_interval(&mut self, max_interval: Duration) -> &mut Self {
+        self.max_interval = max_interval;
+        self
+    }
+
+    /// A stream of batches, which never ends.
+    ///
+    /// Errors are returned as they happen, and polling goes on after them.
+    pub fn stream(&self) -> impl Stream<Item = Result<Batch, ErrorKind>> + Send + 'static {
+        let state = BatchWatch {
+            watcher: self.clone(),
+            next_batch_num: self.from_batch_num,
+            pending: VecDeque::new(),
+            interval: self.interval.unwrap_or(self.min_interval),
+            idle: false,
+            stale_interval: self.interval.is_none(),
+        };
+
+        stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(batch) = state.pending.pop_front() {
+                    return Some((Ok(batch), state));
+                }
+                if state.idle {
+                    Delay::new(state.interval).await;
+                }
+                if let Err(err) = state.poll().await {
+                    state.idle = true;
+                    return Some((Err(err), state));
+                }
+            }
+        })
+    }
+
+    /// A stream of batches along with their transactions.
+    pub fn stream_full(&self) -> impl Stream<Item = Result<FullBatch, ErrorKind>> + Send + 'static {
+        let api = self.api.clone();
+        self.stream().then(move |batch| {
+            let api = api.clone();
+            async move { api.get_full_batch(batch?.batch_num).await }
+        })
+    }
+}
+
+struct BatchWatch {
+    watcher: BatchWatcher,
+    next_batch_num: u32,
+    pending: VecDeque<Batch>,
+    interval: Duration,
+    idle: bool,
+    // New batches arrived since the interval was last worked out.
+    stale_interval: bool,
+}
+
+impl BatchWatch {
+    async fn poll(&mut self) -> Result<(), ErrorKind> {
+        let mut options = self.watcher.api.batches_get_options();
+        options.order(PaginationOrder::Asc).limit(ITEMS_PER_REQUEST);
+        if self.next_batch_num > 0 {
+            options.min_batch_num(self.next_batch_num - 1);
+        }
+        let (batches, _) = options.fetch().await?;
+
+        let batches = following(batches, self.next_batch_num);
+        self.next_batch_num += batches.len() as u32;
+        self.pending.extend(batches);
+
+        self.idle = self.pending.is_empty();
+        if !self.idle {
+            self.stale_interval = self.watcher.interval.is_none();
+        } else if self.stale_interval {
+            let state = self.watcher.api.get_state().await?;
+            self.interval = poll_interval(
+                state.metrics.batch_frequency,
+                self.watcher.min_interval,
+                self.watcher.max_interval,
+            );
+            self.stale_interval = false;
+        }
+        Ok(())
+    }
+}
+
+// The batches that follow on from `next_batch_num` without a gap, in order
+// and each once, so that none is skipped if the node returns a gap.
+fn following(mut batches: Vec<Batch>, mut next_batch_num: u32) -> Vec<Batch> {
+    batches.sort_by_key(|batch| batch.batch_num);
+    let mut following = Vec::new();
+    for batch in batches {
+        if batch.batch_num == next_batch_num {
+            next_batch_num += 1;
+            following.push(batch);
+        } else if batch.batch_num > next_batch_num {
+            break;
+        }
+    }
+    following
+}
+
+// A quarter of the time between batches, kept within bounds.
+fn poll_interval(batch_frequency: f64, min: Duration, max: Duration) -> Duration {
+    if !batch_frequency.is_finite() || batch_frequency <= 0.0 {
+        return min;
+    }
+    Duration::from_secs_f64(batch_frequency / 4.0).clamp(min, max.max(min))
+}
+
+//----------------------------------------------------------------------------
+
+/// What is known of an account at a checkpoint.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AccountState {
+    pub balance: String,
+    pub nonce: u128,
+    /// Item id of the last history transaction seen for the account.
+    pub history_item: u64,
+}
+
+/// The accounts seen by an `AccountWatcher`, from which it can resume.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccountCheckpoint {
+    pub accounts: BTreeMap<String, AccountState>,
+}
+
+/// A change to a watched account.
+#[derive(Debug)]
+pub enum AccountEvent {
+    /// An account matching the filter appeared. `transactions` is its history so far.
+    Created {
+        account: Account,
+        transactions: Vec<HistoryTransaction>,
+    },
+    /// The balance went up, with the history transactions that caused it.
+    BalanceIncreased {
+        account_index: String,
+        previous: String,
+        balance: String,
+        transactions: Vec<HistoryTransaction>,
+    },
+    /// The balance went down, with the history transactions that caused it.
+    BalanceDecreased {
+        account_index: String,
+        previous: String,
+        balance: String,
+        transactions: Vec<HistoryTransaction>,
+    },
+    /// The account sent one or more L2 transactions.
+    NonceAdvanced {
+        account_index: String,
+        previous: u128,
+        nonce: u128,
+    },
+    /// Comes after the events of each poll that found changes. Once those
+    /// events are handled, store the checkpoint to resume from it later.
+    Checkpoint(AccountCheckpoint),
+}
+
+/// Watches accounts for balance and nonce changes. Created with
+/// `HermezApi::watch_accounts()`.
+///
+/// The accounts matching the filter are polled, and the history of those
+/// whose balance changed is fetched to find the transactions behind the
+/// change. Only the address, BJJ and token id fields of the filter are used.
+///
+/// Without a checkpoint, the first poll records the current state of the
+/// accounts, and returns it as a `Checkpoint` event with no other events.
+///
+/// # Example
+///
+/// ```no_run
+/// use futures::StreamExt;
+/// use hermez_api::accounts::AccountsQuery;
+/// use hermez_api::watch::AccountEvent;
+/// use hermez_api::HermezApi;
+///
+/// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+/// let filter = AccountsQuery {
+///     hez_ethereum_address: Some("hez:0xaa942cfcd25ad4d90a62358b0dd84f33b398262a".to_owned()),
+///     ..Default::default()
+/// };
+///
+/// let mut events = api.watch_accounts(filter).checkpoint(saved).stream().boxed();
+///
+/// while let Some(event) = events.next().await {
+///     match event.unwrap() {
+///         AccountEvent::BalanceIncreased { account_index, transactions, .. } => {
+///             // credit the deposit
+///         }
+///         AccountEvent::Checkpoint(checkpoint) => {
+///             // save the checkpoint
+///         }
+///         _ => {}
+///     }
+/// }
+/// ```
+#[derive(Clone)]
+pub struct AccountWatcher {
+    api: HermezApi,
+    filter: AccountsQuery,
+    checkpoint: Option<AccountCheckpoint>,
+    interval: Duration,
+}
+
+impl AccountWatcher {
+    pub fn new(api: &HermezApi, filter: AccountsQuery) -> Self {
+        Self {
+            api: api.clone(),
+            filter,
+            checkpoint: None,
+            interval: Duration::from_secs(10),
+        }
+    }
+
+    /// Resumes from a checkpoint returned by a previous watcher.
+    pub fn checkpoint(&mut self, checkpoint: AccountCheckpoint) -> &mut Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// The interval between polls, ten seconds by default.
+    pub fn interval(&mut self, interval: Duration) -> &mut Self {
+        self.interval = interval;
+        self
+    }
+
+    /// A stream of events, which never ends.
+    ///
+    /// Errors are returned as they happen, and polling goes on after them.
+    pub fn stream(&self) -> impl Stream<Item = Result<AccountEvent, ErrorKind>> + Send + 'static {
+        let state = AccountWatch {
+            watcher: self.clone(),
+            checkpoint: self.checkpoint.clone(),
+            pending: VecDeque::new(),
+            idle: false,
+        };
+
+        stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(event) = state.pending.pop_front() {
+                    return Some((Ok(event), state));
+                }
+                if state.idle {
+                    Delay::new(state.watcher.interval).await;
+                }
+                state.idle = true;
+                if let Err(err) = state.poll().await {
+                    return Some((Err(err), state));
+                }
+            }
+        })
+    }
+}
+
+struct AccountWatch {
+    watcher: AccountWatcher,
+    checkpoint: Option<AccountCheckpoint>,
+    pending: VecDeque<AccountEvent>,
+    idle: bool,
+}
+
+impl AccountWatch {
+    async fn poll(&mut self) -> Result<(), ErrorKind> {
+        let api = &self.watcher.api;
+        let filter = &self.watcher.filter;
+        let query = AccountsQuery {
+            hez_ethereum_address: filter.hez_ethereum_address.clone(),
+            bjj: filter.bjj.clone(),
+            token_ids: filter.token_ids.clone(),
+            order: Some(PaginationOrder::Asc),
+            limit: Some(ITEMS_PER_REQUEST),
+            ..Default::default()
+        };
+        let accounts = api.run_all(&query).await?;
+
+        let mut checkpoint = match &self.checkpoint {
+            Some(checkpoint) => checkpoint.clone(),
+            None => {
+                let history_item = last_history_item(api).await?;
+                let accounts = accounts
+                    .into_iter()
+                    .map(|account| {
+                        let state = AccountState {
+                            balance: account.balance,
+                            nonce: account.nonce,
+                            history_item,
+                        };
+                        (account.account_index, state)
+                    })
+                    .collect();
+                let checkpoint = AccountCheckpoint { accounts };
+                self.checkpoint = Some(checkpoint.clone());
+                self.pending.push_back(AccountEvent::Checkpoint(checkpoint));
+                return Ok(());
+            }
+        };
+
+        // Events are only queued once every request has succeeded, so a
+        // failed poll is retried whole, and no event is returned twice.
+        let mut events = Vec::new();
+        for account in accounts {
+            let account_index = account.account_index.clone();
+            match checkpoint.accounts.get_mut(&account_index) {
+                None => {
+                    let transactions = api.run_all(&history_since(&account_index, 0)).await?;
+                    let state = AccountState {
+                        balance: account.balance.clone(),
+                        nonce: account.nonce,
+                        history_item: last_item(&transactions, 0),
+                    };
+                    checkpoint.accounts.insert(account_index, state);
+                    events.push(AccountEvent::Created {
+                        account,
+                        transactions,
+                    });
+                }
+                Some(state) => {
+                    let ordering = cmp_amounts(&account.balance, &state.balance);
+                    let nonce_advanced = account.nonce > state.nonce;
+                    if ordering == Ordering::Equal && !nonce_advanced {
+                        continue;
+                    }
+                    // The history is followed on any change, or transactions
+                    // that leave the balance as it was would be returned with
+                    // the next change of balance.
+                    let transactions = api
+                        .run_all(&history_since(&account_index, state.history_item + 1))
+                        .await?;
+                    state.history_item = last_item(&transactions, state.history_item);
+                    if ordering != Ordering::Equal {
+                        let previous = std::mem::replace(&mut state.balance, account.balance);
+                        let (account_index, balance) =
+                            (account_index.clone(), state.balance.clone());
+                        events.push(if ordering == Ordering::Greater {
+                            AccountEvent::BalanceIncreased {
+                                account_index,
+                                previous,
+                                balance,
+                                transactions,
+                            }
+                        } else {
+                            AccountEvent::BalanceDecreased {
+                                account_index,
+                                previous,
+                                balance,
+                                transactions,
+                            }
+                        });
+                    }
+                    if nonce_advanced {
+                        events.push(AccountEvent::NonceAdvanced {
+                            account_index,
+                            previous: state.nonce,
+                            nonce: account.nonce,
+                        });
+                        state.nonce = account.nonce;
+                    }
+                }
+            }
+        }
+
+        if !events.is_empty() {
+            self.pending.extend(events);
+            self.pending
+                .push_back(AccountEvent::Checkpoint(checkpoint.clone()));
+            self.checkpoint = Some(checkpoint);
+        }
+        Ok(())
+    }
+}
+
+fn history_since(account_index: &str, from_item: u64) -> HistoryQuery {
+    HistoryQuery {
+        account_index: Some(account_index.to_owned()),
+        from_item: Some(from_item),
+        order: Some(PaginationOrder::Asc),
+        limit: Some(ITEMS_PER_REQUEST),
+        ..Default::default()
+    }
+}
+
+async fn last_history_item(api: &HermezApi) -> Result<u64, ErrorKind> {
+    let query = HistoryQuery {
+        order: Some(PaginationOrder::Desc),
+        limit: Some(1),
+        ..Default::default()
+    };
+    let page = api.run(&query).await?;
+    Ok(last_item(&page.transactions, 0))
+}
+
+fn last_item(transactions: &[HistoryTransaction], default: u64) -> u64 {
+    transactions
+        .iter()
+        .map(|tx| tx.item_id)
+        .max()
+        .unwrap_or(default)
+}
+
+// Compares two amounts given as decimal strings, which may not fit in a u128.
+fn cmp_amounts(a: &str, b: &str) -> Ordering {
+    let a = a.trim_start_matches('0');
+    let b = b.trim_start_matches('0');
+    a.len().cmp(&b.len()).then_with(|| a.cmp(b))
+}
+
+impl HermezApi {
+    /// Watch for new batches, starting with `from_batch_num`.
+    pub fn watch_batches(&self, from_batch_num: u32) -> BatchWatcher {
+        BatchWatcher::new(self, from_batch_num)
+    }
+
+    /// Watch the accounts matching `filter` for balance and nonce changes.
+    pub fn watch_accounts(&self, filter: AccountsQuery) -> AccountWatcher {
+        AccountWatcher::new(self, filter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        cmp_amounts, following, poll_interval, AccountCheckpoint, AccountEvent, AccountWatch,
+    };
+    use crate::accounts::AccountsQuery;
+    use crate::batches::Batch;
+    use crate::test_support::{self, account};
+    use crate::HermezApi;
+    use std::cmp::Ordering;
+    use std::collections::VecDeque;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    fn batch(batch_num: u32) -> Batch {
+        serde_json::from_value(test_support::batch(batch_num, 0)).unwrap()
+    }
+
+    fn following_nums(batch_nums: &[u32], next_batch_num: u32) -> Vec<u32> {
+        let batches = batch_nums
+            .iter()
+            .map(|&batch_num| batch(batch_num))
+            .collect();
+        following(batches, next_batch_num)
+            .iter()
+            .map(|batch| batch.batch_num)
+            .collect()
+    }
+
+    #[test]
+    fn test_following() {
+        assert_eq!(vec![5, 6, 7], following_nums(&[4, 5, 6, 7], 5));
+        assert!(following_nums(&[], 5).is_empty());
+        // Gaps
+        assert!(following_nums(&[6, 7], 5).is_empty());
+        assert_eq!(vec![5, 6], following_nums(&[5, 6, 8, 9], 5));
+        // Repeated batches
+        assert_eq!(vec![5, 6], following_nums(&[5, 5, 6, 6], 5));
+        assert!(following_nums(&[3, 4, 4], 5).is_empty());
+        // Out of order
+        assert_eq!(vec![5, 6, 7], following_nums(&[7, 5, 6], 5));
+        assert_eq!(vec![5], following_nums(&[8, 5, 7], 5));
+    }
+
+    #[test]
+    fn test_cmp_amounts() {
+        assert_eq!(Ordering::Equal, cmp_amounts("100", "100"));
+        assert_eq!(Ordering::Equal, cmp_amounts("0", "000"));
+        assert_eq!(Ordering::Greater, cmp_amounts("1000", "999"));
+        assert_eq!(Ordering::Less, cmp_amounts("0099", "100"));
+        assert_eq!(
+            Ordering::Greater,
+            cmp_amounts(
+                "3402823669209384634633746074317682114560",
+                "340282366920938463463374607431768211455"
+            )
+        );
+    }
+
+    #[test]
+    fn test_poll_interval() {
+        let min = Duration::from_secs(1);
+        let max = Duration::from_secs(60);
+
+        assert_eq!(Duration::from_secs(15), poll_interval(60.0, min, max));
+        assert_eq!(min, poll_interval(2.0, min, max));
+        assert_eq!(max, poll_interval(3600.0, min, max));
+        assert_eq!(min, poll_interval(0.0, min, max));
+        assert_eq!(min, poll_interval(f64::NAN, min, max));
+    }
+
+    type Shared = Arc<Mutex<Vec<serde_json::Value>>>;
+
+    fn transfer(item_id: u64, from: &str, to: &str, amount: u128) -> serde_json::Value {
+        let id = format!("0x{:02x}", item_id);
+        let mut tx = test_support::transaction(&id, "Transfer", from, to, amount);
+        tx["itemId"] = serde_json::json!(item_id);
+        tx
+    }
+
+    // Serves `accounts` and the transactions of `history`, as they are at
+    // the time of each request, and returns the URL of the node.
+    fn serve(accounts: Shared, history: Shared) -> String {
+        test_support::serve(move |request| {
+            if request.path() == "accounts" {
+                let accounts = accounts.lock().unwrap().clone();
+                return (
+                    200,
+                    serde_json::json!({ "accounts": accounts, "pendingItems": 0 }),
+                );
+            }
+            let from_item: u64 = request
+                .param("fromItem")
+                .map_or(0, |item| item.parse().unwrap());
+            let account_index = request.param("accountIndex");
+            let mut page: Vec<serde_json::Value> = history
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|tx| tx["itemId"].as_u64().unwrap() >= from_item)
+                .filter(|tx| match &account_index {
+                    Some(index) => {
+                        tx["fromAccountIndex"] == index.as_str()
+                            || tx["toAccountIndex"] == index.as_str()
+                    }
+                    None => true,
+                })
+                .cloned()
+                .collect();
+            if request.param("order").as_deref() == Some("DESC") {
+                page.reverse();
+            }
+            if let Some(limit) = request.param("limit") {
+                page.truncate(limit.parse().unwrap());
+            }
+            (
+                200,
+                serde_json::json!({ "transactions": page, "pendingItems": 0 }),
+            )
+        })
+    }
+
+    // The events of one poll, in short.
+    fn poll(watch: &mut AccountWatch) -> Vec<String> {
+        async_std::task::block_on(watch.poll()).unwrap();
+        let item_ids = |transactions: &[crate::transactions_history::HistoryTransaction]| {
+            transactions.iter().map(|tx| tx.item_id).collect::<Vec<_>>()
+        };
+        watch
+            .pending
+            .drain(..)
+            .map(|event| match event {
+                AccountEvent::Created {
+                    account,
+                    transactions,
+                } => format!(
+                    "created {} {:?}",
+                    account.account_index,
+                    item_ids(&transactions)
+                ),
+                AccountEvent::BalanceIncreased {
+                    account_index,
+                    previous,
+                    balance,
+                    transactions,
+                } => format!(
+                    "increased {} {} {} {:?}",
+                    account_index,
+                    previous,
+                    balance,
+                    item_ids(&transactions)
+                ),
+                AccountEvent::BalanceDecreased {
+                    account_index,
+                    previous,
+                    balance,
+                    transactions,
+                } => format!(
+                    "decreased {} {} {} {:?}",
+                    account_index,
+                    previous,
+                    balance,
+                    item_ids(&transactions)
+                ),
+                AccountEvent::NonceAdvanced {
+                    account_index,
+                    previous,
+                    nonce,
+                } => format!("nonce {} {} {}", account_index, previous, nonce),
+                AccountEvent::Checkpoint(_) => String::from("checkpoint"),
+            })
+            .collect()
+    }
+
+    fn watch(api: &HermezApi, checkpoint: Option<AccountCheckpoint>) -> AccountWatch {
+        AccountWatch {
+            watcher: api.watch_accounts(AccountsQuery::default()),
+            checkpoint,
+            pending: VecDeque::new(),
+            idle: false,
+        }
+    }
+
+    #[test]
+    fn test_account_watch() {
+        let accounts: Shared = Arc::new(Mutex::new(vec![account("hez:ETH:256", "100", 0)]));
+        let history: Shared = Arc::new(Mutex::new(vec![transfer(
+            1,
+            "hez:ETH:300",
+            "hez:ETH:256",
+            100,
+        )]));
+        let api = HermezApi::new(&serve(accounts.clone(), history.clone())).unwrap();
+
+        // The first poll only records the accounts.
+        let mut first = watch(&api, None);
+        assert_eq!(vec!["checkpoint"], poll(&mut first));
+
+        *accounts.lock().unwrap() = vec![
+            account("hez:ETH:256", "150", 1),
+            account("hez:ETH:257", "5", 0),
+        ];
+        history.lock().unwrap().extend(vec![
+            transfer(2, "hez:ETH:300", "hez:ETH:256", 60),
+            transfer(3, "hez:ETH:256", "hez:ETH:400", 10),
+            transfer(4, "hez:ETH:300", "hez:ETH:257", 5),
+        ]);
+        assert_eq!(
+            vec![
+                "increased hez:ETH:256 100 150 [2, 3]",
+                "nonce hez:ETH:256 0 1",
+                "created hez:ETH:257 [4]",
+                "checkpoint",
+            ],
+            poll(&mut first)
+        );
+        assert!(poll(&mut first).is_empty());
+
+        // Resuming from the checkpoint returns nothing already returned.
+        let checkpoint = first.checkpoint.clone().unwrap();
+        assert_eq!(3, checkpoint.accounts["hez:ETH:256"].history_item);
+        assert_eq!(4, checkpoint.accounts["hez:ETH:257"].history_item);
+        let mut resumed = watch(&api, Some(checkpoint));
+        assert!(poll(&mut resumed).is_empty());
+
+        accounts.lock().unwrap()[1] = account("hez:ETH:257", "2", 1);
+        history
+            .lock()
+            .unwrap()
+            .push(transfer(5, "hez:ETH:257", "hez:ETH:300", 3));
+        assert_eq!(
+            vec![
+                "decreased hez:ETH:257 5 2 [5]",
+                "nonce hez:ETH:257 0 1",
+                "checkpoint",
+            ],
+            poll(&mut resumed)
+        );
+
+        // A transaction that leaves the balance as it was only advances the
+        // nonce, and is not returned with the next change of balance.
+        accounts.lock().unwrap()[0] = account("hez:ETH:256", "150", 2);
+        history
+            .lock()
+            .unwrap()
+            .push(transfer(6, "hez:ETH:256", "hez:ETH:256", 10));
+        assert_eq!(
+            vec!["nonce hez:ETH:256 1 2", "checkpoint"],
+            poll(&mut resumed)
+        );
+        assert_eq!(
+            6,
+            resumed.checkpoint.as_ref().unwrap().accounts["hez:ETH:256"].history_item
+        );
+
+        accounts.lock().unwrap()[0] = account("hez:ETH:256", "170", 2);
+        history
+            .lock()
+            .unwrap()
+            .push(transfer(7, "hez:ETH:300", "hez:ETH:256", 20));
+        assert_eq!(
+            vec!["increased hez:ETH:256 150 170 [7]", "checkpoint"],
+            poll(&mut resumed)
+        );
+    }
+}
@@ -0,0 +1,501 @@
+//! Planning withdrawals of exits from the rollup.
+//!
+//! An exit is withdrawn instantly if the bucket for its USD value still has
+//! withdrawals left. Buckets refill by `rate_withdrawals` every `rate_blocks`
+//! blocks, up to `max_withdrawals`. Otherwise the funds go through the
+//! withdrawal delayer, and can be taken out once the withdrawal delay is over,
+//! unless the delayer is in emergency mode.
+//!
+//! `HermezApi::pending_withdrawals()` tells where the exits of an account
+//! stand in that process.
+
+use serde::{Deserialize, Serialize};
+
+use super::exits::{Exit, ExitStatus};
+use super::schedule::Schedule;
+use super::state::{self, State};
+use super::{parse_amount, ErrorKind, HermezApi};
+
+/// An instant withdrawal bucket, as stored by the rollup contract.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Bucket {
+    /// Highest USD value of an exit in the bucket.
+    pub ceil_usd: u128,
+    /// Block at which `withdrawals` was last updated.
+    pub block_stamp: u128,
+    /// Withdrawals available at `block_stamp`.
+    pub withdrawals: u128,
+    pub rate_blocks: u128,
+    pub rate_withdrawals: u128,
+    pub max_withdrawals: u128,
+}
+
+impl Bucket {
+    fn new(bucket: &state::Bucket) -> Result<Self, ErrorKind> {
+        Ok(Self {
+            ceil_usd: parse_amount(&bucket.ceil_usd)?,
+            block_stamp: parse_amount(&bucket.block_stamp)?,
+            withdrawals: parse_amount(&bucket.withdrawals)?,
+            rate_blocks: parse_amount(&bucket.rate_blocks)?,
+            rate_withdrawals: parse_amount(&bucket.rate_withdrawals)?,
+            max_withdrawals: parse_amount(&bucket.max_withdrawals)?,
+        })
+    }
+
+    /// Withdrawals available at a block, after refilling.
+    pub fn available_at(&self, block: u128) -> u128 {
+        if self.withdrawals >= self.max_withdrawals || self.rate_blocks == 0 {
+            return self.withdrawals;
+        }
+        let periods = block.saturating_sub(self.block_stamp) / self.rate_blocks;
+        (self.withdrawals + periods * self.rate_withdrawals).min(self.max_withdrawals)
+    }
+
+    /// The first block, from `block` on, at which a withdrawal is available.
+    /// `None` if the bucket never refills.
+    pub fn next_available(&self, block: u128) -> Option<u128> {
+        if self.available_at(block) > 0 {
+            return Some(block);
+        }
+        if self.rate_blocks == 0 || self.rate_withdrawals == 0 || self.max_withdrawals == 0 {
+            return None;
+        }
+        let periods = block.saturating_sub(self.block_stamp) / self.rate_blocks + 1;
+        Some(self.block_stamp + periods * self.rate_blocks)
+    }
+
+    // Uses up a withdrawal at a block, as the contract does.
+    fn withdraw(&mut self, block: u128) {
+        let available = self.available_at(block);
+        if available >= self.max_withdrawals || self.rate_blocks == 0 {
+            self.block_stamp = block;
+        } else {
+            let periods = block.saturating_sub(self.block_stamp) / self.rate_blocks;
+            self.block_stamp += periods * self.rate_blocks;
+        }
+        self.withdrawals = available.saturating_sub(1);
+    }
+}
+
+/// How an exit can be withdrawn.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Withdrawal {
+    /// Right away.
+    Instant,
+    /// Through the withdrawal delayer, available `delay` seconds after
+    /// withdrawing from the rollup. An instant withdrawal is expected to be
+    /// possible again from `instant_at_block`, if waiting is preferred.
+    Delayed {
+        delay: u128,
+        instant_at_block: Option<u128>,
+    },
+    /// Not before `instant_at_block`, as the withdrawal delayer is in
+    /// emergency mode and no instant withdrawal is left.
+    Blocked { instant_at_block: Option<u128> },
+}
+
+/// The outcome of `WithdrawalPlanner::plan()`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WithdrawalPlan {
+    pub withdrawal: Withdrawal,
+    /// Value of the exit in USD, if the token has a price.
+    pub amount_usd: Option<f64>,
+    /// Index of the bucket the exit falls in, if any.
+    pub bucket: Option<usize>,
+    /// Why the decision was made, in plain words.
+    pub explanation: Vec<String>,
+}
+
+/// Predicts whether exits can be withdrawn instantly.
+///
+/// # Example
+///
+/// ```no_run
+/// use hermez_api::HermezApi;
+///
+/// let api = HermezApi::new(HermezApi::TESTNET_URL).unwrap();
+/// let planner = api.get_withdrawal_planner().await.unwrap();
+/// let (exits, _) = api
+///     .exits_get_options()
+///     .hez_ethereum_address("hez:0xaa942cfcd25ad4d90a62358b0dd84f33b398262a")
+///     .only_pending_withdraws(true)
+///     .fetch()
+///     .await
+///     .unwrap();
+///
+/// for plan in planner.plan_all(&exits).unwrap() {
+///     println!("{:?}: {}", plan.withdrawal, plan.explanation.join(" "));
+/// }
+/// ```
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WithdrawalPlanner {
+    pub buckets: Vec<Bucket>,
+    /// The current Ethereum block.
+    pub block: u128,
+    /// Delay of the withdrawal delayer, in seconds.
+    pub withdrawal_delay: u128,
+    pub emergency_mode: bool,
+}
+
+impl WithdrawalPlanner {
+    pub fn new(state: &State) -> Result<Self, ErrorKind> {
+        Ok(Self {
+            buckets: state
+                .rollup
+                .buckets
+                .iter()
+                .map(Bucket::new)
+                .collect::<Result<_, _>>()?,
+            block: state.network.last_ethereum_block,
+            withdrawal_delay: state.withdrawal_delayer.withdrawal_delay,
+            emergency_mode: state.withdrawal_delayer.emergency_mode,
+        })
+    }
+
+    /// Plans the withdrawal of an exit, as if it were made at the current block.
+    pub fn plan(&self, exit: &Exit) -> Result<WithdrawalPlan, ErrorKind> {
+        self.plan_with(&self.buckets, exit)
+    }
+
+    /// Plans the withdrawal of several exits, made one after the other, each
+    /// instant withdrawal using up a withdrawal from its bucket.
+    pub fn plan_all(&self, exits: &[Exit]) -> Result<Vec<WithdrawalPlan>, ErrorKind> {
+        let mut buckets = self.buckets.clone();
+        let mut plans = Vec::new();
+        for exit in exits {
+            let plan = self.plan_with(&buckets, exit)?;
+            if let (Withdrawal::Instant, Some(bucket)) = (&plan.withdrawal, plan.bucket) {
+                buckets[bucket].withdraw(self.block);
+            }
+            plans.push(plan);
+        }
+        Ok(plans)
+    }
+
+    fn plan_with(&self, buckets: &[Bucket], exit: &Exit) -> Result<WithdrawalPlan, ErrorKind> {
+        let amount = parse_amount(&exit.balance)?;
+        let amount_usd = exit
+            .token
+            .usd
+            .map(|usd| amount as f64 / 10f64.powi(exit.token.decimals as i32) * usd);
+        self.decide(buckets, amount_usd, &exit.token.symbol)
+    }
+
+    fn decide(
+        &self,
+        buckets: &[Bucket],
+        amount_usd: Option<f64>,
+        symbol: &str,
+    ) -> Result<WithdrawalPlan, ErrorKind> {
+        let mut plan = WithdrawalPlan {
+            withdrawal: Withdrawal::Instant,
+            amount_usd,
+            bucket: None,
+            explanation: Vec::new(),
+        };
+
+        let usd = match amount_usd {
+            Some(usd) => usd,
+            None => {
+                plan.explanation.push(format!(
+                    "{} has no USD price, so the exit is not limited by any bucket.",
+                    symbol
+                ));
+                return Ok(plan);
+            }
+        };
+        // Values are counted in whole dollars.
+        let usd = usd.floor();
+        if usd == 0.0 {
+            plan.explanation
+                .push(String::from("The exit is worth less than one dollar."));
+            return Ok(plan);
+        }
+
+        let (index, bucket) = match buckets
+            .iter()
+            .enumerate()
+            .find(|(_, bucket)| usd <= bucket.ceil_usd as f64)
+        {
+            Some(found) => found,
+            None => {
+                plan.explanation.push(format!(
+                    "The exit is worth {} USD, above every bucket.",
+                    usd
+                ));
+                return Ok(plan);
+            }
+        };
+        plan.bucket = Some(index);
+
+        let available = bucket.available_at(self.block);
+        if available > 0 {
+            plan.explanation.push(format!(
+                "The exit is worth {} USD, and bucket {} (up to {} USD) has {} instant withdrawals left.",
+                usd, index, bucket.ceil_usd, available
+            ));
+            return Ok(plan);
+        }
+
+        let instant_at_block = bucket.next_available(self.block);
+        plan.explanation.push(format!(
+            "The exit is worth {} USD, and bucket {} (up to {} USD) has no instant withdrawal left.",
+            usd, index, bucket.ceil_usd
+        ));
+        plan.explanation.push(match instant_at_block {
+            Some(block) => format!("The bucket refills at block {}.", block),
+            None => String::from("The bucket does not refill."),
+        });
+
+        plan.withdrawal = if self.emergency_mode {
+            plan.explanation.push(String::from(
+                "The withdrawal delayer is in emergency mode, so delayed withdrawals are frozen.",
+            ));
+            Withdrawal::Blocked { instant_at_block }
+        } else {
+            plan.explanation.push(format!(
+                "A delayed withdrawal can be taken out of the withdrawal delayer {} seconds after it is made.",
+                self.withdrawal_delay
+            ));
+            Withdrawal::Delayed {
+                delay: self.withdrawal_delay,
+                instant_at_block,
+            }
+        };
+        Ok(plan)
+    }
+}
+
+/// Exits not fully withdrawn, grouped by `ExitStatus`.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct PendingWithdrawals {
+    /// The Ethereum block the statuses were computed at.
+    pub block: u128,
+    /// Delay of the withdrawal delayer, estimated in blocks.
+    pub delay_blocks: u128,
+    pub pending: Vec<Exit>,
+    pub instantly_withdrawn: Vec<Exit>,
+    pub delayed_requested: Vec<Exit>,
+    pub delayed_withdrawable: Vec<Exit>,
+    pub delayed_withdrawn: Vec<Exit>,
+}
+
+impl PendingWithdrawals {
+    fn new(block: u128, delay_blocks: u128, exits: Vec<Exit>) -> Self {
+        let mut withdrawals = Self {
+            block,
+            delay_blocks,
+            ..Default::default()
+        };
+        for exit in exits {
+            let group = match withdrawals.status(&exit) {
+                ExitStatus::Pending => &mut withdrawals.pending,
+                ExitStatus::InstantlyWithdrawn => &mut withdrawals.instantly_withdrawn,
+                ExitStatus::DelayedRequested => &mut withdrawals.delayed_requested,
+                ExitStatus::DelayedWithdrawable { .. } => &mut withdrawals.delayed_withdrawable,
+                ExitStatus::DelayedWithdrawn => &mut withdrawals.delayed_withdrawn,
+            };
+            group.push(exit);
+        }
+        withdrawals
+    }
+
+    /// The status of an exit, at the block the exits were grouped at.
+    pub fn status(&self, exit: &Exit) -> ExitStatus {
+        exit.status(self.delay_blocks, self.block)
+    }
+}
+
+impl HermezApi {
+    /// Get the state of the network, and build a `WithdrawalPlanner` from it.
+    pub async fn get_withdrawal_planner(&self) -> Result<WithdrawalPlanner, ErrorKind> {
+        WithdrawalPlanner::new(&self.get_state().await?)
+    }
+
+    /// Get all the exits of an Ethereum address with a withdrawal pending,
+    /// grouped by status.
+    ///
+    /// The delay of the withdrawal delayer, given in seconds, is converted to
+    /// blocks with the block time estimated by `Schedule`.
+    pub async fn pending_withdrawals(
+        &self,
+        hez_ethereum_address: &str,
+    ) -> Result<PendingWithdrawals, ErrorKind> {
+        let mut options = self.exits_get_options();
+        options
+            .hez_ethereum_address(hez_ethereum_address)
+            .only_pending_withdraws(true);
+        let (config, state, exits) = futures::join!(
+            self.get_config(),
+            self.get_state(),
+            self.run_all(options.query())
+        );
+        let state = state?;
+        let schedule = Schedule::new(&config?, &state)?;
+
+        let block_time = schedule.block_time().num_milliseconds().max(1) as u128;
+        let delay = state.withdrawal_delayer.withdrawal_delay * 1000;
+        let delay_blocks = delay.div_ceil(block_time);
+
+        Ok(PendingWithdrawals::new(
+            state.network.last_ethereum_block,
+            delay_blocks,
+            exits?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Bucket, PendingWithdrawals, Withdrawal, WithdrawalPlanner};
+    use crate::exits::{Exit, ExitStatus};
+
+    fn bucket(ceil_usd: u128, withdrawals: u128) -> Bucket {
+        Bucket {
+            ceil_usd,
+            block_stamp: 1000,
+            withdrawals,
+            rate_blocks: 40,
+            rate_withdrawals: 1,
+            max_withdrawals: 3,
+        }
+    }
+
+    #[test]
+    fn test_bucket() {
+        let mut bucket = bucket(100, 0);
+
+        assert_eq!(0, bucket.available_at(1039));
+        assert_eq!(1, bucket.available_at(1040));
+        assert_eq!(3, bucket.available_at(2000));
+        assert_eq!(Some(1040), bucket.next_available(1010));
+        assert_eq!(Some(1090), bucket.next_available(1090));
+
+        bucket.withdraw(1090);
+        assert_eq!(1080, bucket.block_stamp);
+        assert_eq!(1, bucket.withdrawals);
+        assert_eq!(2, bucket.available_at(1120));
+    }
+
+    #[test]
+    fn test_plan() {
+        let mut planner = WithdrawalPlanner {
+            buckets: vec![bucket(100, 1), bucket(1000, 0)],
+            block: 1010,
+            withdrawal_delay: 3600,
+            emergency_mode: false,
+        };
+
+        let plan = planner.decide(&planner.buckets, Some(50.5), "ETH").unwrap();
+        assert_eq!(Withdrawal::Instant, plan.withdrawal);
+        assert_eq!(Some(0), plan.bucket);
+
+        let plan = planner
+            .decide(&planner.buckets, Some(500.0), "ETH")
+            .unwrap();
+        assert_eq!(
+            Withdrawal::Delayed {
+                delay: 3600,
+                instant_at_block: Some(1040)
+            },
+            plan.withdrawal
+        );
+        assert_eq!(Some(1), plan.bucket);
+
+        let plan = planner
+            .decide(&planner.buckets, Some(5000.0), "ETH")
+            .unwrap();
+        assert_eq!(Withdrawal::Instant, plan.withdrawal);
+        assert_eq!(None, plan.bucket);
+
+        planner.emergency_mode = true;
+        let plan = planner
+            .decide(&planner.buckets, Some(500.0), "ETH")
+            .unwrap();
+        assert_eq!(
+            Withdrawal::Blocked {
+                instant_at_block: Some(1040)
+            },
+            plan.withdrawal
+        );
+    }
+
+    fn exit(
+        instant_withdraw: Option<u128>,
+        delayed_withdraw_request: Option<u128>,
+        delayed_withdraw: Option<u128>,
+    ) -> Exit {
+        serde_json::from_value(serde_json::json!({
+            "batchNum": 100,
+            "accountIndex": "hez:ETH:256",
+            "bjj": "hez:rR7LXKal-av7I56Y0dEBCVmwc9zpoLY5ERhy5w7G-xwe",
+            "hezEthereumAddress": "hez:0xaa942cfcd25ad4d90a62358b0dd84f33b398262a",
+            "itemId": 1,
+            "merkleProof": {
+                "root": "0",
+                "siblings": [],
+                "oldKey": "0",
+                "oldValue": "0",
+                "isOld0": false,
+                "key": "0",
+                "value": "0",
+                "fnc": 0
+            },
+            "balance": "1000000000000000000",
+            "instantWithdraw": instant_withdraw,
+            "delayedWithdrawRequest": delayed_withdraw_request,
+            "delayedWithdraw": delayed_withdraw,
+            "token": {
+                "id": 0,
+                "ethereumAddress": "0x0000000000000000000000000000000000000000",
+                "itemId": 1,
+                "name": "Ether",
+                "symbol": "ETH",
+                "decimals": 18,
+                "ethereumBlockNum": 0,
+                "USD": 1800.0,
+                "fiatUpdate": null
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_pending_withdrawals() {
+        assert_eq!(
+            ExitStatus::Pending,
+            exit(None, None, None).status(100, 2000)
+        );
+        assert_eq!(
+            ExitStatus::InstantlyWithdrawn,
+            exit(Some(1500), None, None).status(100, 2000)
+        );
+        assert_eq!(
+            ExitStatus::DelayedRequested,
+            exit(None, Some(1950), None).status(100, 2000)
+        );
+        assert_eq!(
+            ExitStatus::DelayedWithdrawable { at_block: 2000 },
+            exit(None, Some(1900), None).status(100, 2000)
+        );
+        assert_eq!(
+            ExitStatus::DelayedWithdrawn,
+            exit(None, Some(1500), Some(1800)).status(100, 2000)
+        );
+
+        let withdrawals = PendingWithdrawals::new(
+            2000,
+            100,
+            vec![
+                exit(None, None, None),
+                exit(None, Some(1950), None),
+                exit(None, Some(1900), None),
+                exit(None, None, None),
+            ],
+        );
+        assert_eq!(2, withdrawals.pending.len());
+        assert_eq!(1, withdrawals.delayed_requested.len());
+        assert_eq!(1, withdrawals.delayed_withdrawable.len());
+        assert!(withdrawals.instantly_withdrawn.is_empty());
+    }
+}